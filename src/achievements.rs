@@ -0,0 +1,61 @@
+use crate::constants::achievements;
+use crate::events::GameEvent;
+use crate::map::branches::DungeonLevel;
+
+/// A milestone of the run, announced once when it's reached and listed in the morgue
+/// file afterwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Achievement {
+    FirstBlood,
+    Slayer,
+    Veteran,
+    DeepDelver,
+}
+
+impl Achievement {
+    pub fn name(self) -> &'static str {
+        match self {
+            Achievement::FirstBlood => achievements::FIRST_BLOOD,
+            Achievement::Slayer => achievements::SLAYER,
+            Achievement::Veteran => achievements::VETERAN,
+            Achievement::DeepDelver => achievements::DEEP_DELVER,
+        }
+    }
+
+    /// The achievement an event earns, if any. `kills` already counts a death the event
+    /// reports, since the statistics hear about it first.
+    pub fn earned_by(event: &GameEvent, kills: u32) -> Option<Achievement> {
+        match *event {
+            GameEvent::EntityDied {
+                player: false,
+                by_player: true,
+                ..
+            } if kills == 1 => Some(Achievement::FirstBlood),
+            GameEvent::EntityDied {
+                player: false,
+                by_player: true,
+                ..
+            } if kills == achievements::SLAYER_KILLS => Some(Achievement::Slayer),
+            GameEvent::LeveledUp { level } if level >= achievements::VETERAN_LEVEL => {
+                Some(Achievement::Veteran)
+            }
+            GameEvent::StairsDescended {
+                branch,
+                dungeon_level,
+                ..
+            } => {
+                let depth = DungeonLevel {
+                    branch,
+                    depth: dungeon_level,
+                }
+                .absolute_depth();
+                if depth >= achievements::DEEP_DELVER_DEPTH {
+                    Some(Achievement::DeepDelver)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
@@ -3,46 +3,231 @@ extern crate tcod;
 pub const GAME_TITLE: &str = "TOMBS OF THE ANCIENT KINGS";
 pub const FONT_PATH: &str = "arial10x10.png";
 pub const SAVE_FILE_NAME: &str = "savegame";
+pub const SAVE_META_FILE_NAME: &str = "savegame.meta";
+pub const SETTINGS_FILE_NAME: &str = "settings.json";
+pub const STASH_FILE_NAME: &str = "stash";
+pub const EVENT_LOG_FILE_NAME: &str = "events.ndjson";
+pub const MONSTERS_FILE_NAME: &str = "monsters.json";
+pub const ITEM_SCRIPTS_FILE_NAME: &str = "item_scripts.json";
+pub const KEYBINDINGS_FILE_NAME: &str = "keybindings.toml";
+pub const SCORES_FILE_NAME: &str = "scores.json";
+pub const DAILY_SCORES_FILE_NAME: &str = "daily_scores.json";
+pub const REPLAY_FILE_NAME: &str = "replay.json";
+
+pub const KEYBINDINGS_FILE_HEADER: &str = "\
+# Keys for each action. A key is a single character like \"g\", or one of Up, Down,
+# Left, Right, Home, End, PageUp, PageDown, Insert, Delete, Enter, Escape, Backspace,
+# Tab, Space, NumPad0 to NumPad9, NumPadEnter or F1 to F12, optionally after Ctrl+
+# and/or Alt+. Give an action a list to bind it to several keys.
+";
+
+pub const AUTOSAVE_TURNS: u32 = 100;
+pub const AUTOSAVE_FAILED: &str = "The game could not be saved automatically!";
+
+pub fn create_event_log_warning() -> String {
+    format!("warning: could not write to {}", EVENT_LOG_FILE_NAME)
+}
+
+pub fn create_scores_warning() -> String {
+    format!("warning: could not update {}", SCORES_FILE_NAME)
+}
+
+pub fn create_replay_warning() -> String {
+    format!("warning: could not write {}", REPLAY_FILE_NAME)
+}
+
+pub fn create_bad_item_scripts_warning(error: &str) -> String {
+    format!(
+        "warning: could not read {} ({}), using the built-in scripted items",
+        ITEM_SCRIPTS_FILE_NAME, error
+    )
+}
+
+pub fn create_bad_monsters_warning() -> String {
+    format!(
+        "warning: could not read {}, using the built-in monsters",
+        MONSTERS_FILE_NAME
+    )
+}
+
+pub fn create_bad_keybindings_warning(line: usize) -> String {
+    format!(
+        "warning: could not read line {} of {}, using the default keys",
+        line, KEYBINDINGS_FILE_NAME
+    )
+}
+
+pub fn create_keybindings_write_warning() -> String {
+    format!("warning: could not write {}", KEYBINDINGS_FILE_NAME)
+}
+
+pub fn create_bad_settings_warning() -> String {
+    format!(
+        "warning: could not read {}, using the default settings",
+        SETTINGS_FILE_NAME
+    )
+}
 
 pub mod gui {
+    use tcod::colors::{self, Color};
+
     pub const SCREEN_WIDTH: i32 = 80;
-    pub const SCREEN_HEIGHT: i32 = 50;
 
     pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
 
     pub const INVENTORY_WIDTH: i32 = 50;
 
-    pub const MAP_WIDTH: i32 = 80;
-    pub const MAP_HEIGHT: i32 = 43;
+    // the whole dungeon floor, which can be bigger than the part of it on screen
+    pub const MAP_WIDTH: i32 = 120;
+    pub const MAP_HEIGHT: i32 = 70;
+
+    // the part of the screen above the panel that shows the map
+    pub const VIEW_WIDTH: i32 = 80;
+    pub const VIEW_HEIGHT: i32 = 43;
 
     // sizes and coordinates relevant for the GUI
     pub const BAR_WIDTH: i32 = 20;
+    // the default and minimum panel height, the settings file can ask for a taller one
     pub const PANEL_HEIGHT: i32 = 7;
-    pub const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+    pub const MAX_PANEL_HEIGHT: i32 = 20;
+    pub const STATUS_Y: i32 = 2;
+    pub const MANA_Y: i32 = 4;
+    pub const GOLD_Y: i32 = 5;
 
     // Message Log Constants
-    pub const MSG_X: i32 = BAR_WIDTH + 2;
-    pub const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
-    pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+
+    // Monsters that leave FOV are drawn faded at the tile they were last seen on
+    pub const SHOW_LAST_SEEN: bool = true;
+    pub const LAST_SEEN_FADE: f32 = 0.4;
+
+    // Background of tiles picked out while aiming
+    pub const HIGHLIGHT_COLOR: Color = colors::DARK_CYAN;
+    // While targeting, the tiles within reach and the ones the blast would catch
+    pub const RANGE_COLOR: Color = colors::DARKEST_AZURE;
+    pub const BLAST_COLOR: Color = colors::DARKER_FLAME;
+
+    /// A word on how hurt a monster looks, shown next to its name under the mouse
+    pub fn condition(hp: i32, max_hp: i32) -> &'static str {
+        match hp * 4 / max_hp.max(1) {
+            4 => "unhurt",
+            3 => "lightly wounded",
+            2 => "wounded",
+            1 => "badly wounded",
+            _ => "nearly dead",
+        }
+    }
+
+    pub mod threat {
+        use tcod::colors::{self, Color};
+
+        // how much harder a monster hits the player than the other way around
+        pub const DANGEROUS: f32 = 1.5;
+        pub const TRIVIAL: f32 = 0.5;
+
+        pub const DANGEROUS_COLOR: Color = colors::RED;
+        pub const TRIVIAL_COLOR: Color = colors::GREY;
+        pub const TINT: f32 = 0.35;
+    }
+
+    /// The alternate characters used by the symbol glyph set
+    pub mod glyphs {
+        use tcod::chars;
+
+        // bits for the walls a wall tile connects to, indexing into WALLS
+        pub const NORTH: usize = 1;
+        pub const SOUTH: usize = 2;
+        pub const EAST: usize = 4;
+        pub const WEST: usize = 8;
+
+        pub const WALLS: [char; 16] = [
+            chars::BLOCK1, // a lone pillar
+            chars::DVLINE,
+            chars::DVLINE,
+            chars::DVLINE,
+            chars::DHLINE,
+            chars::DSW,
+            chars::DNW,
+            chars::DTEEE,
+            chars::DHLINE,
+            chars::DSE,
+            chars::DNE,
+            chars::DTEEW,
+            chars::DHLINE,
+            chars::DTEEN,
+            chars::DTEES,
+            chars::DCROSS,
+        ];
+
+        // object characters that get swapped out, anything else is drawn as is
+        pub const OBJECTS: &[(char, char)] = &[
+            ('@', chars::SMILIE),
+            ('#', '?'),
+            ('<', chars::ARROW2_S),
+        ];
+    }
+
+    // Damage numbers float over whoever got hit for this many frames
+    pub const DAMAGE_POPUP_FRAMES: i32 = 10;
+    pub const DAMAGE_POPUP_COLOR: Color = colors::LIGHT_RED;
+    // critical hits stand out in the same color as their log message
+    pub const CRITICAL_POPUP_COLOR: Color = colors::LIGHT_ORANGE;
+
+    // Explored objects outside FOV are drawn with their color scaled by this
+    pub const OUT_OF_FOV_FADE: f32 = 0.5;
 
     pub const WELCOME_MESSAGE: &str =
         "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.";
 
     pub mod menus {
         pub mod main {
+            use tcod::colors::{self, Color};
+
             pub const MENU_NO_HEADER: &str = "";
             pub const GAME_CONSOLE_HEADER: &str = "Rusty Rogues";
             pub const AUTHOR_LINE: &str = "By Zach";
             pub const NEW_GAME: &str = "Play a new game";
+            pub const SEEDED_GAME: &str = "Play a seeded game";
+            pub const DAILY_RUN: &str = "Daily Run";
             pub const CONTINUE: &str = "Continue last game";
+            pub const WATCH_REPLAY: &str = "Watch the last run";
+            pub const HALL_OF_FAME: &str = "Hall of Fame";
+            pub const CREDITS: &str = "Credits";
             pub const QUIT: &str = "Quit";
             pub const IMAGE_PATH: &str = "menu_background.png";
+            pub const FALLBACK_BACKGROUND: Color = colors::DARKEST_GREY;
             pub const START_MENU_WIDTH: i32 = 24;
+
+            pub fn create_missing_image_warning(path: &str) -> String {
+                format!("warning: could not load {}, using a plain menu background", path)
+            }
+
+            pub fn create_save_summary(meta: &crate::persistence::SaveMeta) -> String {
+                if meta.won {
+                    return format!(
+                        "Last save: won in {} turns at level {}, {} min ago",
+                        meta.turns,
+                        meta.player_level,
+                        meta.minutes_ago()
+                    );
+                }
+
+                format!(
+                    "Last save: depth {}, level {}, HP {}/{}, {} turns, {} min ago",
+                    meta.dungeon_level,
+                    meta.player_level,
+                    meta.hp,
+                    meta.max_hp,
+                    meta.turns,
+                    meta.minutes_ago()
+                )
+            }
         }
 
         pub mod level_up {
             pub const WIDTH: i32 = 40;
             pub const TITLE: &str = "Level up! Choose a stat to raise:\n";
+            pub const POSTPONED: &str =
+                "You put off training for now. You can choose a stat after your next move.";
 
             pub fn create_log_message(level: i32) -> String {
                 format!(
@@ -51,16 +236,16 @@ pub mod gui {
                 )
             }
 
-            pub fn create_constitution_option(base: i32) -> String {
-                format!("Constitution (+20 HP, from {})", base)
+            pub fn create_constitution_option(gain: i32, base: i32) -> String {
+                format!("Constitution (+{} HP, from {})", gain, base)
             }
 
-            pub fn create_stength_option(base: i32) -> String {
-                format!("Strength (+1 attack, from {})", base)
+            pub fn create_stength_option(gain: i32, base: i32) -> String {
+                format!("Strength (+{} attack, from {})", gain, base)
             }
 
-            pub fn create_agility_option(base: i32) -> String {
-                format!("Agility (+1 defense, from {})", base)
+            pub fn create_agility_option(gain: i32, base: i32) -> String {
+                format!("Agility (+{} defense, from {})", gain, base)
             }
         }
 
@@ -72,17 +257,235 @@ pub mod gui {
                 "After a rare moment of peace, you descend deeper into the heart of the dungeon.";
             pub const REST_COLOR: Color = colors::VIOLET;
             pub const NEXT_LEVEL_COLOR: Color = colors::RED;
+            pub const ON_STAIRS_LOG_MESSAGE: &str =
+                "You are standing on stairs. Press < to descend.";
+            pub const RETURN_LOG_MESSAGE: &str =
+                "You head back down to a floor you have already explored.";
+            pub const CLIMB_LOG_MESSAGE: &str = "You climb back up the stairs.";
+            pub const UP_STAIRS_NAME: &str = "up stairs";
+            pub const ON_STAIRS_COLOR: Color = colors::LIGHT_GREY;
+
+            // the floor the stairs lead up to, None when it's town
+            pub fn create_on_up_stairs_message(destination: Option<&str>) -> String {
+                match destination {
+                    None => "You are standing on the way up. Press > to return to town.".to_string(),
+                    Some(floor) => format!(
+                        "You are standing on the way up. Press > to climb back to {}.",
+                        floor
+                    ),
+                }
+            }
+
+            pub fn create_remaining_message(monsters: usize) -> String {
+                match monsters {
+                    0 => "Not a single monster remains on this floor.".to_string(),
+                    1 => "1 monster still lurks on this floor.".to_string(),
+                    _ => format!("{} monsters still lurk on this floor.", monsters),
+                }
+            }
+
+        }
+
+        pub mod wait {
+            pub const WIDTH: i32 = 30;
+            pub const HEADER: &str = "How long do you want to wait?\n";
+            pub const TURN_CHOICES: [u32; 4] = [5, 10, 20, 50];
+            pub const ENEMY_IN_VIEW: &str = "You can't wait with an enemy in sight!";
+
+            pub fn create_option(turns: u32) -> String {
+                format!("{} turns", turns)
+            }
+
+            pub fn create_done_message(turns: u32) -> String {
+                format!("You wait for {} turns.", turns)
+            }
 
+            pub fn create_interrupted_message(turns: u32) -> String {
+                format!("You stop waiting after {} turns, something is coming!", turns)
+            }
+        }
+
+        pub mod credits {
+            use super::main::AUTHOR_LINE;
+            use crate::constants::GAME_TITLE;
+
+            // how many frames each line of scrolling takes
+            pub const FRAMES_PER_LINE: i32 = 4;
+
+            pub const LINES: &[&str] = &[
+                GAME_TITLE,
+                "",
+                AUTHOR_LINE,
+                "",
+                "Built by following the Rust roguelike tutorial",
+                "by Tomas Sedovic",
+                "",
+                "Rendering, input and field of view by libtcod",
+                "through the tcod-rs bindings",
+                "",
+                "Press any key to return to the menu",
+            ];
+        }
+
+        pub mod seed {
+            pub const HEADER: &str =
+                "Enter a seed, a number or any word, and press Enter. The same seed always makes the same dungeon.";
+            pub const MAX_LENGTH: usize = 20;
+            pub const WIDTH: i32 = 40;
+
+            pub fn create_character_line(seed: u32) -> String {
+                format!("* Seed: {} \n", seed)
+            }
+        }
+
+        pub mod history {
+            use tcod::colors::{self, Color};
+
+            pub const HEADER: &str = "Message history";
+            pub const FOOTER: &str = "Up/Down: scroll, PgUp/PgDn: page, Home/End: jump, Esc: close";
+            pub const EMPTY: &str = "Nothing has happened yet.";
+            pub const HEADER_COLOR: Color = colors::LIGHT_YELLOW;
+            pub const FOOTER_COLOR: Color = colors::LIGHT_GREY;
+        }
+
+        pub mod game_over {
+            pub const TITLE: &str = "You died! What now?\n";
+            pub const PLAY_AGAIN: &str = "Play again";
+            pub const MAIN_MENU: &str = "Return to the main menu";
+            pub const WIDTH: i32 = 30;
+        }
+
+        pub mod victory {
+            pub const KEEP_PLAYING: &str = "Stay in town";
+            pub const PLAY_AGAIN: &str = "Play again";
+            pub const MAIN_MENU: &str = "Return to the main menu";
+            pub const WIDTH: i32 = 40;
+
+            pub fn create_title(turns: u32) -> String {
+                format!(
+                    "You escaped the dungeon with the Amulet in {} turns. You have won!\n",
+                    turns
+                )
+            }
         }
 
         pub mod character_sheet {}
 
-        pub mod inventory {}
+        pub mod inventory {
+            // the default, the settings file can change it
+            pub const CAPACITY: usize = 52;
+            pub const NOTHING_TO_EQUIP: &str = "There is nothing here to equip.";
+
+            pub fn create_full_message(capacity: usize, item: &str) -> String {
+                format!(
+                    "Your inventory is full ({} items), cannot pick up {}",
+                    capacity, item
+                )
+            }
+
+            pub fn create_pickup_message(item: &str, quantity: i32) -> String {
+                if quantity > 1 {
+                    format!("You picked up {} (x{})!", item, quantity)
+                } else {
+                    format!("You picked up a {}!", item)
+                }
+            }
+
+            pub fn create_drop_message(item: &str, quantity: i32) -> String {
+                if quantity > 1 {
+                    format!("You dropped {} (x{})", item, quantity)
+                } else {
+                    format!("You dropped a {}", item)
+                }
+            }
+
+            pub fn create_downgrade_question(new_item: &str, old_item: &str) -> String {
+                format!("Equip weaker {} over {}?\n", new_item, old_item)
+            }
+        }
+
+        pub mod paging {
+            // menus pick options by letter, longer ones are split into pages
+            pub const LETTERS: usize = 26;
+            // leaves two letters for turning the page either way
+            pub const PAGE_SIZE: usize = 24;
+            pub const NEXT: &str = "Next page";
+            pub const PREVIOUS: &str = "Previous page";
+
+            pub fn create_header(header: &str, page: usize, pages: usize) -> String {
+                format!("{}(page {} of {})\n", header, page, pages)
+            }
+        }
+
+        pub mod confirm {
+            pub const WIDTH: i32 = 40;
+            pub const YES: &str = "Yes";
+            pub const NO: &str = "No";
+        }
 
         pub mod drop {}
     }
 }
 
+pub mod classes {
+    pub const WIDTH: i32 = 60;
+    pub const HEADER: &str = "Choose your class:\n";
+
+    /// Starting stats, and what each stat gains when picked at a level up
+    pub struct ClassStats {
+        pub name: &'static str,
+        pub description: &'static str,
+        pub hp: i32,
+        pub defense: i32,
+        pub power: i32,
+        // added on to the spell mana pool, negative for classes with little magic
+        pub mana_bonus: i32,
+        pub level_hp: i32,
+        pub level_power: i32,
+        pub level_defense: i32,
+    }
+
+    pub const WARRIOR: ClassStats = ClassStats {
+        name: "Warrior",
+        description: "tough, starts with a sword",
+        hp: 120,
+        defense: 2,
+        power: 2,
+        mana_bonus: -10,
+        level_hp: 25,
+        level_power: 1,
+        level_defense: 1,
+    };
+
+    pub const ROGUE: ClassStats = ClassStats {
+        name: "Rogue",
+        description: "nimble, starts with a bow and arrows",
+        hp: 90,
+        defense: 1,
+        power: 3,
+        mana_bonus: 0,
+        level_hp: 15,
+        level_power: 1,
+        level_defense: 2,
+    };
+
+    pub const MAGE: ClassStats = ClassStats {
+        name: "Mage",
+        description: "frail, but with a deep pool of mana",
+        hp: 80,
+        defense: 0,
+        power: 2,
+        mana_bonus: 20,
+        level_hp: 15,
+        level_power: 1,
+        level_defense: 1,
+    };
+
+    pub fn create_option(class: &ClassStats) -> String {
+        format!("{}: {}", class.name, class.description)
+    }
+}
+
 pub mod player_base {
     use crate::colors::{self, Color};
 
@@ -91,6 +494,959 @@ pub mod player_base {
     pub const COLOR: Color = colors::WHITE;
 }
 
+pub mod monsters {
+    // for monster definitions that don't bring their own
+    pub const GENERIC_DEATH_FLAVOR: &str = "is dead";
+}
+
+pub mod identify {
+    use crate::colors::{self, Color};
+
+    // potions are drawn as this, anything else that needs identifying is a scroll
+    pub const POTION_SYMBOL: char = '!';
+    pub const POTIONS: [(&str, Color); 8] = [
+        ("Bubbly Blue", colors::LIGHT_BLUE),
+        ("Smoky Grey", colors::GREY),
+        ("Fizzing Orange", colors::ORANGE),
+        ("Cloudy White", colors::WHITE),
+        ("Glowing Green", colors::LIGHT_GREEN),
+        ("Oily Black", colors::DARKER_GREY),
+        ("Swirling Pink", colors::PINK),
+        ("Thick Amber", colors::AMBER),
+    ];
+    pub const SCROLL_LABELS: [&str; 8] = [
+        "ZELGO MER",
+        "FOOBIE BLETCH",
+        "ELBIB YLOH",
+        "VERR YED HORRE",
+        "PRATYAVAYAH",
+        "DAIYEN FOOELS",
+        "KERNOD WEL",
+        "JUYED AWK YACC",
+    ];
+    pub const SCROLL_COLOR: Color = colors::LIGHT_YELLOW;
+
+    pub const UNKNOWN_POTION: &str =
+        "An unfamiliar potion. There's no telling what it does until someone drinks it.";
+    pub const UNKNOWN_SCROLL: &str =
+        "A scroll covered in strange words. Reading it is the only way to find out what they do.";
+    pub const HEADER: &str =
+        "Press the key next to an item to identify it, or any other to cancel.\n";
+    pub const NOTHING_TO_IDENTIFY: &str = "There is nothing else you carry left to identify.";
+    pub const LEARNED_COLOR: Color = colors::LIGHT_CYAN;
+
+    pub fn create_potion_name(look: &str) -> String {
+        format!("{} Potion", look)
+    }
+
+    pub fn create_scroll_name(label: &str) -> String {
+        format!("Scroll labelled {}", label)
+    }
+
+    pub fn create_learned_message(looks: &str, name: &str) -> String {
+        format!("The {} was a {}.", looks, name)
+    }
+}
+
+pub mod scripts {
+    use crate::colors::{self, Color};
+
+    // messages without a color of their own
+    pub const MESSAGE_COLOR: Color = colors::LIGHT_VIOLET;
+    pub const MISSING: &str = "Nothing happens. Whatever this once did has been forgotten.";
+    pub const UNKNOWN_DESCRIPTION: &str = "Nobody remembers what this does.";
+    pub const FAILED_COLOR: Color = colors::RED;
+
+    // the colors a script can name for its messages
+    pub const COLORS: [(&str, Color); 12] = [
+        ("white", colors::WHITE),
+        ("grey", colors::LIGHT_GREY),
+        ("red", colors::RED),
+        ("orange", colors::ORANGE),
+        ("yellow", colors::YELLOW),
+        ("green", colors::GREEN),
+        ("light_green", colors::LIGHT_GREEN),
+        ("cyan", colors::CYAN),
+        ("light_cyan", colors::LIGHT_CYAN),
+        ("light_blue", colors::LIGHT_BLUE),
+        ("violet", colors::VIOLET),
+        ("light_violet", colors::LIGHT_VIOLET),
+    ];
+
+    pub fn create_failed_message(error: &str) -> String {
+        format!("The magic fizzles out: {}", error)
+    }
+
+    pub mod errors {
+        pub const UNEXPECTED_END: &str = "the script stops partway through";
+        pub const DIVIDE_BY_ZERO: &str = "division by zero";
+        pub const OVERFLOW: &str = "a number got too big";
+
+        pub fn create_syntax_error(line: usize, near: &str) -> String {
+            format!("line {}: didn't expect {}", line, near)
+        }
+
+        pub fn create_type_error(expected: &str, found: &str) -> String {
+            format!("expected {} but got {}", expected, found)
+        }
+
+        pub fn create_unknown_variable_error(name: &str) -> String {
+            format!("no variable called {}", name)
+        }
+
+        pub fn create_unknown_function_error(name: &str) -> String {
+            format!("no function called {}", name)
+        }
+
+        pub fn create_arguments_error(function: &str, count: usize) -> String {
+            format!("{} can't take {} arguments", function, count)
+        }
+
+        pub fn create_index_error(index: i32, len: usize) -> String {
+            format!("no item {} in a list of {}", index, len)
+        }
+
+        pub fn create_unknown_color_error(color: &str) -> String {
+            format!("no color called {}", color)
+        }
+
+        pub fn create_unknown_effect_error(effect: &str) -> String {
+            format!("no status effect called {}", effect)
+        }
+    }
+
+    // the scrolls and spells the game has always had, run like any other script. The
+    // numbers they use are handed to them by the game, so the descriptions stay right.
+    pub mod built_in {
+        pub const HEAL: &str = r#"
+// nothing to aim, the potion always goes to whoever drinks it
+let target = player();
+if hp(target) == max_hp(target) {
+    message("You are already at full health.", "red");
+    cancel();
+}
+message("Your wounds start to close up!", "light_violet");
+heal(target, AMOUNT);
+"#;
+
+        pub const LIGHTNING: &str = r#"
+let targets = monsters_in_range(RANGE);
+if len(targets) == 0 {
+    message("No enemy is close enough to strike.", "red");
+    cancel();
+}
+
+// the player picks among the enemies in range, unless there's only one
+let target = targets[0];
+if len(targets) > 1 {
+    message("Left-click a highlighted enemy to strike it, or right-click to cancel.", "light_cyan");
+    target = choose(targets, RANGE);
+}
+
+message("A lightning bolt strikes the " + name(target) + " with a loud thunder!\n The damage is " + DAMAGE + " hit points", "light_blue");
+if !damage(target, DAMAGE) {
+    status(target, "Stun", STUN_TURNS);
+}
+"#;
+
+        pub const CONFUSE: &str = r#"
+message("Left-click an enemy to confuse it, or right-click to cancel.", "light_cyan");
+let target = pick_monster(RANGE);
+status(target, "Confusion", TURNS);
+message("The eyes of the " + name(target) + " look vacant, as it starts to stumble around!", "light_green");
+"#;
+
+        pub const FIREBALL: &str = r#"
+message("Left-click a target tile for the fireball, or right-click to cancel.", "light_cyan");
+// walls shield anything behind them from the blast
+let caught = pick_area(RADIUS);
+if HITS_CASTER && contains(caught, player()) && !confirm("You are within the blast radius and will be burned too. Cast anyway?\n") {
+    cancel();
+}
+
+message("The fireball explodes, burning everything within " + RADIUS + " tiles!", "orange");
+for target in caught {
+    if target != player() || HITS_CASTER {
+        message("The " + name(target) + " gets burned for " + DAMAGE + " hit points.", "orange");
+        if !damage(target, DAMAGE) {
+            status(target, "Burning", BURN_TURNS);
+        }
+    }
+}
+"#;
+    }
+}
+
+pub mod effects {
+    use crate::colors::{self, Color};
+    use crate::effects::EffectKind;
+
+    // afflicted creatures are drawn partway between their own color and the effect's
+    pub const STATUS_TINT: f32 = 0.6;
+    pub const STUNNED: &str = "You are stunned and can't act!";
+
+    pub mod poison {
+        pub const DAMAGE_PER_TURN: i32 = 2;
+    }
+
+    pub mod burning {
+        pub const DAMAGE_PER_TURN: i32 = 3;
+    }
+
+    pub mod bleed {
+        pub const DAMAGE_PER_TURN: i32 = 1;
+    }
+
+    // monster hits bring their effects along in the monster definitions
+    pub const FIREBALL_BURN_TURNS: i32 = 3;
+    pub const LIGHTNING_STUN_TURNS: i32 = 1;
+
+    pub fn color(kind: EffectKind) -> Color {
+        match kind {
+            EffectKind::Poison => colors::LIME,
+            EffectKind::Burning => colors::ORANGE,
+            EffectKind::Bleed => colors::RED,
+            EffectKind::Stun => colors::LIGHT_BLUE,
+            EffectKind::Confusion => colors::LIGHT_MAGENTA,
+            EffectKind::Haste => colors::LIGHT_YELLOW,
+            EffectKind::Slow => colors::LIGHT_SEPIA,
+        }
+    }
+
+    fn adjective(kind: EffectKind) -> &'static str {
+        match kind {
+            EffectKind::Poison => "poisoned",
+            EffectKind::Burning => "burning",
+            EffectKind::Bleed => "bleeding",
+            EffectKind::Stun => "stunned",
+            EffectKind::Confusion => "confused",
+            EffectKind::Haste => "hasted",
+            EffectKind::Slow => "slowed",
+        }
+    }
+
+    fn short_name(kind: EffectKind) -> &'static str {
+        match kind {
+            EffectKind::Poison => "Poison",
+            EffectKind::Burning => "Burn",
+            EffectKind::Bleed => "Bleed",
+            EffectKind::Stun => "Stun",
+            EffectKind::Confusion => "Confused",
+            EffectKind::Haste => "Haste",
+            EffectKind::Slow => "Slow",
+        }
+    }
+
+    pub fn create_status_label(kind: EffectKind, turns_left: i32) -> String {
+        format!("({}, {} turns)", adjective(kind), turns_left)
+    }
+
+    pub fn create_panel_label(kind: EffectKind, turns_left: i32) -> String {
+        format!("{} {}", short_name(kind), turns_left)
+    }
+
+    pub fn create_applied_message(name: &str, kind: EffectKind) -> String {
+        format!("{} is {}!", name, adjective(kind))
+    }
+
+    pub fn damage_source(kind: EffectKind) -> &'static str {
+        match kind {
+            EffectKind::Poison => "poison",
+            EffectKind::Burning => "the flames",
+            _ => "bleeding",
+        }
+    }
+
+    pub fn create_damage_message(name: &str, kind: EffectKind, damage: i32) -> String {
+        format!(
+            "{} takes {} damage from {}.",
+            name,
+            damage,
+            damage_source(kind)
+        )
+    }
+
+    pub fn create_expired_message(name: &str, kind: EffectKind) -> String {
+        format!("{} is no longer {}.", name, adjective(kind))
+    }
+}
+
+pub mod town {
+    use crate::colors::{self, Color};
+
+    pub const NAME: &str = "Town";
+    pub const ARRIVAL_MESSAGE: &str =
+        "You climb out into the daylight of the town above the tombs.";
+    pub const ARRIVAL_COLOR: Color = colors::LIGHT_AMBER;
+
+    pub const HEALER_NAME: &str = "Healer";
+    pub const HEALER_COLOR: Color = colors::LIGHT_GREEN;
+    pub const HEALER_MENDS: &str = "The healer tends to your wounds. You feel fully restored.";
+    pub const HEALER_NOTHING_TO_MEND: &str = "The healer looks you over and finds nothing to mend.";
+    // the town shop sells what the first floor could turn up
+    pub const SHOP_STOCK_DEPTH: u32 = 1;
+
+    pub mod stash {
+        use tcod::colors::{self, Color};
+
+        pub const NAME: &str = "Stash chest";
+        pub const COLOR: Color = colors::DARK_AMBER;
+        pub const WIDTH: i32 = 50;
+        // withdrawing lists the stash as a lettered menu, so it shares the a-z limit
+        pub const CAPACITY: usize = 26;
+
+        pub const DEPOSIT: &str = "Store an item";
+        pub const WITHDRAW: &str = "Take an item";
+        pub const DEPOSIT_HEADER: &str =
+            "Press the key next to an item to store it, or any other to cancel.\n";
+        pub const WITHDRAW_HEADER: &str =
+            "Press the key next to an item to take it, or any other to cancel.\n";
+
+        pub const FULL: &str = "The stash chest is full.";
+        pub const EMPTY: &str = "The stash chest is empty.";
+        pub const INVENTORY_FULL: &str = "Your inventory is too full to take anything out.";
+        pub const UNREADABLE: &str = "The stash chest is stuck shut. Its file could not be read.";
+        pub const UNWRITABLE: &str = "The stash chest could not be saved!";
+
+        pub fn create_header(items: usize) -> String {
+            format!(
+                "Your stash chest holds {} of {} items. Whatever is stored here stays, even if you die.\n",
+                items, CAPACITY
+            )
+        }
+
+        pub fn create_deposit_message(item: &str) -> String {
+            format!("You store the {} in the stash chest.", item)
+        }
+
+        pub fn create_withdraw_message(item: &str) -> String {
+            format!("You take the {} out of the stash chest.", item)
+        }
+    }
+
+    pub mod shrine {
+        use tcod::colors::{self, Color};
+
+        pub const NAME: &str = "Shrine";
+        pub const SYMBOL: char = '_';
+        pub const COLOR: Color = colors::LIGHT_CYAN;
+        // what it takes to unlearn each level's training
+        pub const COST_PER_LEVEL: i32 = 25;
+
+        pub const NOTHING_TO_UNLEARN: &str =
+            "You kneel at the shrine, but there is no training yet to give back.";
+        pub const CANCELLED: &str = "You rise before the rite is done. Nothing has changed.";
+        pub const RETRAINED: &str =
+            "Your old training fades, and you rise from the shrine remade.";
+
+        pub fn create_too_poor_message(cost: i32) -> String {
+            format!(
+                "The shrine asks {} gold to unlearn your training. You don't have that much.",
+                cost
+            )
+        }
+
+        pub fn create_question(cost: i32, points: i32) -> String {
+            format!(
+                "For {} gold the shrine takes back the training of your {} levels, and you choose each again. Kneel?\n",
+                cost, points
+            )
+        }
+
+        pub fn create_title(point: i32, points: i32) -> String {
+            format!("Retraining {} of {}. Choose a stat to raise:\n", point, points)
+        }
+    }
+
+    // '#' is wall, anything else is floor. '@' is where the player arrives, 'H' the
+    // healer, 'S' the stash chest, 'A' the shrine and '<' the stairs down to the first
+    // floor
+    pub const LAYOUT: &[&str] = &[
+        "##############################",
+        "#............................#",
+        "#..#####..............#####..#",
+        "#..#...#..............#...#..#",
+        "#..#.H.#..............#.S.#..#",
+        "#..##.##..............##.##..#",
+        "#............................#",
+        "#.............@..............#",
+        "#............................#",
+        "#..##.##..............##.##..#",
+        "#..#.A.#..............#.$.#..#",
+        "#..#...#.......<......#...#..#",
+        "#..#####..............#####..#",
+        "#............................#",
+        "##############################",
+    ];
+}
+
+pub mod danger {
+    use crate::colors::{self, Color};
+
+    // a floor's danger is the combined xp of the monsters spawned on it
+    const LEVELS: &[(i32, &str, Color)] = &[
+        (200, "This floor feels quiet.", colors::LIGHT_GREY),
+        (500, "Something stirs in the dark. This floor feels dangerous.", colors::LIGHT_ORANGE),
+        (i32::MAX, "A chill runs down your spine. This floor feels deadly.", colors::LIGHT_RED),
+    ];
+
+    pub fn describe(danger: i32) -> (&'static str, Color) {
+        LEVELS
+            .iter()
+            .find(|&&(limit, _, _)| danger < limit)
+            .map(|&(_, message, color)| (message, color))
+            .unwrap_or((LEVELS[LEVELS.len() - 1].1, LEVELS[LEVELS.len() - 1].2))
+    }
+
+    pub fn create_message(description: &str, danger: i32) -> String {
+        format!("{} (danger {})", description, danger)
+    }
+}
+
+pub mod difficulty {
+    use crate::colors::{self, Color};
+
+    // the factor never leaves these bounds, so it can't trivialize or wall off a run
+    pub const MIN_FACTOR: f32 = 0.8;
+    pub const MAX_FACTOR: f32 = 1.25;
+    // a close call eases off faster than a good floor leans on
+    pub const STEP_UP: f32 = 0.05;
+    pub const STEP_DOWN: f32 = 0.1;
+
+    // fractions of max hp: falling below NEAR_DEATH is a close call, while a floor
+    // cleared quickly without dropping below CRUISING means the player is cruising
+    pub const NEAR_DEATH: f32 = 0.25;
+    pub const CRUISING: f32 = 0.6;
+    pub const FAST_CLEAR_TURNS: u32 = 400;
+
+    pub const HARDER: &str = "The dungeon takes your measure. Its denizens grow bolder.";
+    pub const EASIER: &str = "The dungeon loosens its grip on you.";
+    pub const COLOR: Color = colors::LIGHT_VIOLET;
+
+    pub fn create_character_line(factor: f32) -> String {
+        format!("* Difficulty: {:.0}% \n", factor * 100.0)
+    }
+}
+
+pub mod floor_themes {
+    use crate::colors::{self, Color};
+    use crate::map::spawn_tables::FloorTheme;
+
+    pub const ARRIVAL_COLOR: Color = colors::LIGHT_ORANGE;
+
+    /// Announces a themed floor as the player arrives, plain floors get no message
+    pub fn arrival_message(theme: FloorTheme) -> Option<&'static str> {
+        match theme {
+            FloorTheme::Mixed => None,
+            FloorTheme::OrcWarren => {
+                Some("Crude totems line the walls. You have stumbled into an orc warren.")
+            }
+            FloorTheme::TrollDen => {
+                Some("Gnawed bones litter the floor. This level is a troll den.")
+            }
+        }
+    }
+}
+
+pub mod combat {
+    use crate::colors::{self, Color};
+
+    pub const BASE_CRIT_CHANCE: f32 = 0.05;
+    // critical hits multiply the damage that gets through the target's defense
+    pub const CRIT_MULTIPLIER: i32 = 2;
+    pub const CRIT_COLOR: Color = colors::LIGHT_ORANGE;
+    pub const SWORD_CRIT_BONUS: f32 = 0.05;
+    pub const FUMBLE_CHANCE: f32 = 0.03;
+    pub const FUMBLE_COLOR: Color = colors::LIGHT_GREY;
+
+    pub fn create_crit_message(attacker: &str, target: &str, damage: i32) -> String {
+        format!(
+            "{} lands a critical hit on {} for {} hit points!",
+            attacker, target, damage
+        )
+    }
+
+    pub fn create_fumble_message(attacker: &str, target: &str) -> String {
+        format!("{} fumbles the attack on {}.", attacker, target)
+    }
+}
+
+pub mod curses {
+    use crate::colors::{self, Color};
+
+    // how much a blessing or a curse changes each bonus the gear has
+    pub const BLESSED_BOOST: i32 = 1;
+    pub const CURSED_PENALTY: i32 = 2;
+    pub const BLESSED_CHANCE: f32 = 0.1;
+    pub const CURSED_CHANCE: f32 = 0.15;
+
+    pub const BLESSED: &str = "Blessed";
+    pub const UNCURSED: &str = "Uncursed";
+    pub const CURSED: &str = "Cursed";
+    pub const COLOR: Color = colors::LIGHT_RED;
+
+    pub fn create_name(state: &str, name: &str) -> String {
+        format!("{} {}", state, name)
+    }
+
+    pub fn create_stuck_message(name: &str) -> String {
+        format!("The {} tightens around you. It's cursed!", name)
+    }
+
+    pub fn create_cant_remove_message(name: &str) -> String {
+        format!("The {} won't come off. It's cursed!", name)
+    }
+
+    pub fn create_identified_message(name: &str, state: &str) -> String {
+        format!("The {} is {}.", name, state.to_lowercase())
+    }
+}
+
+pub mod speed {
+    // the energy acting costs, which a creature of normal speed gains every turn
+    pub const ACTION_COST: i32 = 100;
+    pub const NORMAL: i32 = 100;
+    // what haste and slow do to a creature's speed, as a percentage
+    pub const HASTED_PERCENT: i32 = 200;
+    pub const SLOWED_PERCENT: i32 = 50;
+}
+
+pub mod encumbrance {
+    pub const BASE_CAPACITY: i32 = 20;
+    pub const CAPACITY_PER_POWER: i32 = 4;
+    // an overloaded player moves at this percentage of their speed
+    pub const SPEED_PERCENT: i32 = 67;
+    pub const STATUS_LABEL: &str = "Encumbered";
+
+    pub fn create_pickup_warning(item: &str) -> String {
+        format!("The {} is one thing too many, you are now encumbered!", item)
+    }
+}
+
+pub mod underfoot {
+    pub fn create_message(names: &[String]) -> String {
+        format!("You step over: {}.", names.join(", "))
+    }
+}
+
+pub mod travel {
+    pub const ENEMY_IN_VIEW: &str = "You can't travel with an enemy in sight!";
+    pub const INTERRUPTED: &str = "You stop, something is in sight!";
+    pub const BLOCKED: &str = "Something is in the way.";
+    pub const NO_PATH: &str = "You don't know a way there.";
+}
+
+pub mod pile {
+    use crate::colors::{self, Color};
+
+    // drawn in place of the items when more than one shares a tile
+    pub const SYMBOL: char = '&';
+    pub const COLOR: Color = colors::LIGHT_YELLOW;
+
+    // the menu letters options a-z
+    pub const MAX_LISTED: usize = 26;
+    pub const HEADER: &str = "Press the key next to an item to pick it up, or any other to leave the pile.\n";
+}
+
+pub mod gold {
+    use crate::colors::{self, Color};
+
+    pub const NAME: &str = "gold";
+    pub const SYMBOL: char = '$';
+    pub const COLOR: Color = colors::GOLD;
+    pub const ROOM_CHANCE: f32 = 0.3;
+    // a room's pile holds up to this much for every dungeon level
+    pub const PILE_PER_LEVEL: i32 = 10;
+    pub const DROP_CHANCE: f32 = 0.5;
+    // a monster carries up to one coin for every this many experience points it's worth
+    pub const XP_PER_COIN: i32 = 2;
+
+    pub fn create_pickup_message(amount: i32) -> String {
+        format!("You pick up {} gold.", amount)
+    }
+
+    pub fn create_counter(gold: i32) -> String {
+        format!("Gold: {}", gold)
+    }
+}
+
+pub mod shop {
+    use crate::colors::{self, Color};
+
+    pub const NAME: &str = "Shopkeeper";
+    pub const SYMBOL: char = 'S';
+    pub const COLOR: Color = colors::LIGHT_YELLOW;
+    // floors before this one are too close to town for anyone to set up shop
+    pub const MIN_LEVEL: u32 = 2;
+    pub const CHANCE: f32 = 0.4;
+    pub const STOCK_SIZE: usize = 5;
+    pub const WIDTH: i32 = 50;
+
+    pub const SOLD_OUT: &str = "The shopkeeper shrugs. There is nothing left to sell.";
+    pub const NOT_ENOUGH_GOLD: &str = "You can't afford that.";
+    pub const INVENTORY_FULL: &str = "Your inventory is too full to carry anything more.";
+
+    pub fn create_header(gold: i32) -> String {
+        format!(
+            "You have {} gold. Press the key next to an item to buy it, or any other to cancel.\n",
+            gold
+        )
+    }
+
+    pub fn create_option(item: &str, quantity: i32, price: i32) -> String {
+        if quantity > 1 {
+            format!("{} (x{}) - {} gold", item, quantity, price)
+        } else {
+            format!("{} - {} gold", item, price)
+        }
+    }
+
+    pub fn create_bought_message(item: &str, price: i32) -> String {
+        format!("You buy the {} for {} gold.", item, price)
+    }
+}
+
+pub mod traps {
+    use crate::colors::{self, Color};
+    use crate::traps::TrapKind;
+
+    pub const SYMBOL: char = '^';
+    pub const ROOM_CHANCE: f32 = 0.25;
+
+    pub const SPIKE_DAMAGE: i32 = 5;
+    pub const DART_DAMAGE: i32 = 2;
+    pub const DART_POISON_TURNS: i32 = 4;
+    pub const PIT_DAMAGE: i32 = 3;
+    // turns spent climbing back out
+    pub const PIT_STUN_TURNS: i32 = 2;
+    pub const ALARM_RADIUS: i32 = 15;
+    pub const ALARM_CHASE_TURNS: i32 = 10;
+
+    pub fn name(kind: TrapKind) -> &'static str {
+        match kind {
+            TrapKind::Spike => "spike",
+            TrapKind::Dart => "dart",
+            TrapKind::Alarm => "alarm",
+            TrapKind::Pit => "pit",
+        }
+    }
+
+    pub fn color(kind: TrapKind) -> Color {
+        match kind {
+            TrapKind::Spike => colors::LIGHT_GREY,
+            TrapKind::Dart => colors::LIME,
+            TrapKind::Alarm => colors::YELLOW,
+            TrapKind::Pit => colors::DARK_ORANGE,
+        }
+    }
+
+    pub fn create_triggered_message(name: &str, kind: TrapKind) -> String {
+        match kind {
+            TrapKind::Spike => format!("Spikes shoot up from the floor under {}!", name),
+            TrapKind::Dart => format!("A poisoned dart flies out of the wall at {}!", name),
+            TrapKind::Alarm => format!("{} steps on a plate and an alarm blares!", name),
+            TrapKind::Pit => format!("The floor gives way and {} falls into a pit!", name),
+        }
+    }
+}
+
+pub mod daily {
+    use crate::colors::{self, Color};
+
+    pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+    // spreads consecutive days far apart over the seeds
+    pub const SEED_MULTIPLIER: u32 = 0x9E37_79B1;
+    pub const COLOR: Color = colors::LIGHT_AZURE;
+
+    pub fn create_intro(class: &str) -> String {
+        format!(
+            "Today's daily run: everyone plays this dungeon as a {}. Good luck!",
+            class
+        )
+    }
+}
+
+pub mod replay {
+    use crate::colors::{self, Color};
+
+    // how long each step of a replay stays on screen, from slowest to fastest
+    pub const DELAYS_MS: [u64; 5] = [500, 250, 100, 40, 0];
+    pub const DEFAULT_SPEED: usize = 2;
+    pub const COLOR: Color = colors::LIGHT_AZURE;
+    pub const NONE_RECORDED: &str = "No run has been recorded yet.";
+    pub const OVER: &str = "That's the end of the recorded run.";
+    pub const WIDTH: i32 = 40;
+
+    pub fn create_status(speed: usize, speeds: usize) -> String {
+        format!("Replay, speed {}/{}: +/- to change, Esc to stop", speed, speeds)
+    }
+
+    // plays the last run through without a window, to see where it ends up
+    pub const CHECK_FLAG: &str = "--check-replay";
+
+    pub fn create_check_summary(depth: u32, turns: u32, won: bool) -> String {
+        let ending = if won { "escaped" } else { "did not escape" };
+        format!(
+            "Replay reached depth {} after {} turns and {}.",
+            depth, turns, ending
+        )
+    }
+}
+
+pub mod hall_of_fame {
+    use crate::scores::Score;
+
+    // how many runs the scores file keeps, and how many of those the menu lists
+    pub const KEPT: usize = 100;
+    pub const SHOWN: usize = 10;
+    pub const WIDTH: i32 = 78;
+    pub const HEADER: &str = "Hall of Fame\n\n";
+    pub const DAILY_HEADER: &str = "\nToday's Daily Run\n\n";
+    pub const EMPTY: &str = "No run has ended yet.\n";
+    // what each floor of depth adds to the score, before the difficulty multiplier
+    pub const DEPTH_POINTS: i32 = 50;
+
+    /// How the run's score adds up, shown when it ends
+    pub fn create_breakdown(score: &Score) -> String {
+        format!(
+            "\nExperience: {}\nDepth {}: {}\nDifficulty: x{:.2}\nScore: {}\n",
+            score.xp,
+            score.depth,
+            score.depth_points(),
+            score.multiplier,
+            score.points()
+        )
+    }
+
+    pub fn create_line(rank: usize, score: &Score) -> String {
+        let ending = match score.killed_by {
+            Some(ref cause) => format!("killed by {}", cause),
+            None => "escaped with the Amulet".to_string(),
+        };
+        format!(
+            "{:>2}. {} points: level {} {}, depth {}, {} xp, {} turns, {}\n",
+            rank,
+            score.points(),
+            score.player_level,
+            score.class,
+            score.depth,
+            score.xp,
+            score.turns,
+            ending
+        )
+    }
+}
+
+pub mod achievements {
+    use crate::colors::{self, Color};
+
+    pub const COLOR: Color = colors::LIGHT_AMBER;
+    pub const FIRST_BLOOD: &str = "First Blood";
+    pub const SLAYER: &str = "Slayer";
+    pub const VETERAN: &str = "Veteran";
+    pub const DEEP_DELVER: &str = "Deep Delver";
+
+    // what it takes to earn them
+    pub const SLAYER_KILLS: u32 = 50;
+    pub const VETERAN_LEVEL: i32 = 5;
+    pub const DEEP_DELVER_DEPTH: u32 = 10;
+
+    pub fn create_unlocked_message(name: &str) -> String {
+        format!("Achievement unlocked: {}!", name)
+    }
+}
+
+pub mod morgue {
+    use crate::traps::TrapKind;
+
+    // how many of the last log messages the morgue file ends with
+    pub const LAST_MESSAGES: usize = 10;
+    pub const OWN_FIREBALL: &str = "their own fireball";
+    pub const UNKNOWN_CAUSE: &str = "something unseen";
+    pub const WRITE_FAILED: &str = "The morgue file could not be written!";
+
+    pub fn create_file_name(timestamp: u64) -> String {
+        format!("morgue-{}.txt", timestamp)
+    }
+
+    pub fn create_written_message(file_name: &str) -> String {
+        format!("The story of your run has been written to {}.", file_name)
+    }
+
+    pub fn create_trap_cause(kind: TrapKind) -> String {
+        format!("a {} trap", crate::constants::traps::name(kind))
+    }
+
+    pub fn create_summary(class: &str, level: i32, place: &str, turns: u32, cause: &str) -> String {
+        format!(
+            "{}\n\nA level {} {} died on {} after {} turns, killed by {}.\n",
+            crate::constants::GAME_TITLE,
+            level,
+            class,
+            place,
+            turns,
+            cause
+        )
+    }
+
+    pub fn create_stats(max_hp: i32, power: i32, defense: i32, xp: i32) -> String {
+        format!(
+            "\nMaximum HP: {}\nAttack: {}\nDefense: {}\nExperience: {}\n",
+            max_hp, power, defense, xp
+        )
+    }
+
+    pub fn create_record(deepest: u32, kills: u32, gold: i32) -> String {
+        format!(
+            "\nDeepest floor reached: depth {}\nMonsters killed: {}\nGold: {}\n",
+            deepest, kills, gold
+        )
+    }
+
+    pub const ACHIEVEMENTS_HEADER: &str = "\nAchievements:\n";
+    pub const NO_ACHIEVEMENTS: &str = "  none\n";
+    pub const INVENTORY_HEADER: &str = "\nInventory:\n";
+    pub const EMPTY_INVENTORY: &str = "  nothing\n";
+    pub const MESSAGES_HEADER: &str = "\nLast messages:\n";
+}
+
+pub mod look {
+    use crate::colors::{self, Color};
+    use crate::traps::TrapKind;
+
+    pub const TEXT_COLOR: Color = colors::LIGHT_YELLOW;
+    pub const UNEXPLORED: &str = "You haven't been there yet.";
+    pub const OUT_OF_SIGHT: &str = "You can't see there from here.";
+    pub const WALL: &str = "A solid stone wall.";
+    pub const FLOOR: &str = "Bare dungeon floor.";
+    pub const DOOR: &str = "A locked door. It needs the key from this level.";
+
+    pub fn create_trap_line(kind: TrapKind) -> String {
+        format!(
+            "A sprung {} trap. It will go off again if stepped on.",
+            crate::constants::traps::name(kind)
+        )
+    }
+}
+
+pub mod locks {
+    use crate::colors::{self, Color};
+
+    pub const VAULT_CHANCE: f32 = 0.3;
+    pub const DOOR_SYMBOL: char = '+';
+    pub const DOOR_COLOR: Color = colors::DARK_AMBER;
+    pub const KEY_SYMBOL: char = '~';
+    pub const KEY_COLOR: Color = colors::GOLD;
+
+    pub const LOCKED: &str = "The door is locked. Its key must be somewhere on this level.";
+    pub const UNLOCKED: &str = "You unlock the door.";
+    pub const UNLOCKED_KEY_USED: &str = "You unlock the door. The key stays jammed in the lock.";
+    pub const USE_HINT: &str = "Walk into a locked door to use the key on it.";
+
+    pub fn create_key_name(branch: &str, depth: u32) -> String {
+        format!("Key to {} level {}", branch, depth)
+    }
+}
+
+pub mod branches {
+    use crate::colors::{self, Color};
+
+    pub const MAIN_NAME: &str = "Dungeon";
+    pub const STAIRS_COLOR: Color = colors::LIGHT_PURPLE;
+    pub const ARRIVAL_COLOR: Color = colors::LIGHT_PURPLE;
+
+    pub mod crypt {
+        pub const NAME: &str = "Crypt";
+        pub const ENTRY_DEPTH: u32 = 4;
+        pub const FLOORS: u32 = 3;
+        pub const ARRIVAL: &str = "The air turns cold and still. You have entered the Crypt.";
+    }
+
+    pub mod mines {
+        pub const NAME: &str = "Mines";
+        pub const ENTRY_DEPTH: u32 = 2;
+        pub const FLOORS: u32 = 4;
+        pub const ARRIVAL: &str =
+            "Old pick marks scar the walls around you. You have entered the Mines.";
+    }
+
+    pub fn create_level_label(branch: &str, depth: u32) -> String {
+        format!("{} Level: {}", branch, depth)
+    }
+
+    pub fn create_stairs_name(branch: &str) -> String {
+        format!("stairs down to the {}", branch)
+    }
+
+    pub fn create_on_stairs_message(branch: &str) -> String {
+        format!("These stairs lead down into the {}. Press < to descend.", branch)
+    }
+}
+
+pub mod boss {
+    use crate::colors::{self, Color};
+
+    // every this many main dungeon floors is a boss floor
+    pub const EVERY: u32 = 5;
+    pub const GOLD_PER_DEPTH: i32 = 15;
+    pub const SLAIN_COLOR: Color = colors::LIGHT_FUCHSIA;
+    pub const ARRIVAL: &str = "A heavy silence hangs over this floor. Something large is waiting.";
+    pub const SEALED: &str = "The way down is sealed.";
+
+    pub fn create_slain_message(boss: &str) -> String {
+        format!("{} has fallen! The way down lies open.", boss)
+    }
+
+    // '#' is wall, anything else is floor. '@' is where the player arrives, 'B' the
+    // boss and '<' the stairs down, behind it
+    pub const LAYOUT: &[&str] = &[
+        "#########################################",
+        "##########.....................##########",
+        "#######...........................#######",
+        "#####...............................#####",
+        "####.....##...................##.....####",
+        "###......##...................##......###",
+        "##.....................................##",
+        "#@..................B..................<#",
+        "##.....................................##",
+        "###......##...................##......###",
+        "####.....##...................##.....####",
+        "#####...............................#####",
+        "#######...........................#######",
+        "##########.....................##########",
+        "#########################################",
+    ];
+}
+
+pub mod amulet {
+    use crate::colors::{self, Color};
+
+    // the bottom of the main dungeon, which holds the Amulet instead of stairs down
+    pub const FINAL_DEPTH: u32 = 12;
+    pub const NAME: &str = "Amulet of the Deep";
+    pub const SYMBOL: char = '"';
+    pub const COLOR: Color = colors::LIGHT_AMBER;
+    pub const DESCRIPTION: &str =
+        "The reason you came down here. Carry it back up and out of the dungeon to win.";
+    pub const USE_HINT: &str = "The Amulet does nothing here. Get it out of the dungeon!";
+    pub const TAKEN: &str =
+        "The Amulet is yours! Now climb back up through every floor and escape to town.";
+    pub const ONLY_UP: &str = "The Amulet pulls at you. There is nothing left below, only the way up.";
+}
+
+pub mod remains {
+    pub const LOOT_CHANCE: f32 = 0.35;
+    pub const NOTHING_TO_SEARCH: &str = "There is nothing here to search.";
+
+    pub fn create_found_message(item: &str, remains: &str) -> String {
+        format!("You search the {} and find a {}!", remains, item)
+    }
+
+    pub fn create_empty_message(remains: &str) -> String {
+        format!("You search the {} but find nothing of use.", remains)
+    }
+}
+
 pub mod gear {
     pub mod dagger {
         use crate::colors::{self, Color};
@@ -101,6 +1457,125 @@ pub mod gear {
         pub const HP_BONUS: i32 = 0;
         pub const DEFENSE_BONUS: i32 = 0;
         pub const POWER_BONUS: i32 = 2;
+        // a short blade finds the gaps in armor
+        pub const CRIT_BONUS: f32 = 0.1;
+        pub const WEIGHT: i32 = 3;
+    }
+
+    pub mod boomerang {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Boomerang";
+        pub const SYMBOL: char = ')';
+        pub const COLOR: Color = colors::LIGHT_SEPIA;
+        pub const WEIGHT: i32 = 2;
+        pub const RANGE: f32 = 6.0;
+        pub const DAMAGE: i32 = 12;
+
+        pub const INSTRUCTIONS: &str =
+            "Left-click a target tile to throw the boomerang, or right-click to cancel.";
+        pub const INSTRUCTION_COLOR: Color = colors::LIGHT_CYAN;
+        pub const MISS: &str = "The boomerang sails through the air and returns to your hand.";
+
+        pub fn create_hit_message(name: &str) -> String {
+            format!(
+                "The boomerang strikes the {} for {} hit points and returns to your hand.",
+                name, DAMAGE
+            )
+        }
+    }
+
+    pub mod bow {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Bow";
+        pub const SYMBOL: char = '}';
+        pub const COLOR: Color = colors::LIGHT_SEPIA;
+        pub const WEIGHT: i32 = 4;
+        pub const RANGE: f32 = 8.0;
+        pub const DAMAGE: i32 = 8;
+    }
+
+    pub mod crossbow {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Crossbow";
+        pub const SYMBOL: char = '}';
+        pub const COLOR: Color = colors::DARK_SEPIA;
+        pub const WEIGHT: i32 = 7;
+        pub const RANGE: f32 = 6.0;
+        pub const DAMAGE: i32 = 14;
+    }
+
+    pub mod arrows {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Arrows";
+        pub const SYMBOL: char = '(';
+        pub const COLOR: Color = colors::LIGHTER_SEPIA;
+        // per arrow, they're too light to notice
+        pub const WEIGHT: i32 = 0;
+        // how many come in a bundle found on the floor
+        pub const BUNDLE: i32 = 8;
+        pub const RECOVER_CHANCE: f32 = 0.5;
+
+        pub const INSTRUCTIONS: &str =
+            "Left-click an enemy to shoot it, or right-click to cancel.";
+        pub const INSTRUCTION_COLOR: Color = colors::LIGHT_CYAN;
+        pub const NO_LAUNCHER: &str = "You need a bow or crossbow in hand to shoot.";
+        pub const NO_AMMO: &str = "You have no arrows to shoot.";
+
+        pub fn create_hit_message(launcher: &str, target: &str, damage: i32) -> String {
+            format!(
+                "Your {} looses an arrow into the {} for {} hit points.",
+                launcher.to_lowercase(),
+                target,
+                damage
+            )
+        }
+    }
+
+    pub mod leather_armor {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Leather Armor";
+        pub const SYMBOL: char = '[';
+        pub const COLOR: Color = colors::DARK_SEPIA;
+        pub const DEFENSE_BONUS: i32 = 2;
+        pub const WEIGHT: i32 = 12;
+    }
+
+    pub mod helmet {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Helmet";
+        pub const SYMBOL: char = '[';
+        pub const COLOR: Color = colors::LIGHT_GREY;
+        pub const DEFENSE_BONUS: i32 = 1;
+        pub const WEIGHT: i32 = 4;
+    }
+
+    pub mod boots {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Boots";
+        pub const SYMBOL: char = '[';
+        pub const COLOR: Color = colors::SEPIA;
+        pub const DEFENSE_BONUS: i32 = 1;
+        pub const WEIGHT: i32 = 3;
+    }
+
+    // the unique crown every boss drops, never found anywhere else
+    pub mod crown {
+        use crate::colors::{self, Color};
+
+        pub const NAME: &str = "Crown of the Ancient King";
+        pub const SYMBOL: char = '^';
+        pub const COLOR: Color = colors::GOLD;
+        pub const POWER_BONUS: i32 = 2;
+        pub const DEFENSE_BONUS: i32 = 2;
+        pub const HP_BONUS: i32 = 20;
+        pub const WEIGHT: i32 = 3;
     }
 
     pub mod iron_sword {}
@@ -108,39 +1583,194 @@ pub mod gear {
     pub mod shield {}
 }
 
+pub mod spells {
+    pub const WIDTH: i32 = 40;
+
+    pub const BASE_MANA: i32 = 20;
+    pub const MANA_PER_LEVEL: i32 = 5;
+    // one point of mana comes back every this many turns
+    pub const REGEN_INTERVAL: u32 = 4;
+
+    pub const LIGHTNING_COST: i32 = 12;
+    pub const CONFUSE_COST: i32 = 8;
+    pub const FIREBALL_COST: i32 = 18;
+
+    pub fn max_mana(level: i32) -> i32 {
+        BASE_MANA + MANA_PER_LEVEL * (level - 1)
+    }
+
+    pub fn create_header(mana: i32) -> String {
+        format!(
+            "Press the key next to a spell to cast it, or any other to cancel. Mana: {}\n",
+            mana
+        )
+    }
+
+    pub fn create_option(name: &str, cost: i32) -> String {
+        format!("{} ({} mana)", name, cost)
+    }
+
+    pub fn create_no_mana_message(name: &str) -> String {
+        format!("You don't have enough mana to cast {}.", name)
+    }
+}
+
+/// What each item does, shown when examining it
+pub mod descriptions {
+    pub const RECALL: &str =
+        "Carries you up to town. Read another there to return to the spot you left.";
+    pub const DIG: &str = "Crumbles an adjacent cracked wall. The wand is not used up.";
+    pub const MUDDLE: &str =
+        "A cloudy potion of unclear make. It doesn't smell like anything good.";
+    pub const DAGGER: &str = "A short, sharp blade, quick to find the gaps in armor.";
+    pub const SWORD: &str = "A plain but well balanced blade.";
+    pub const SHIELD: &str = "A sturdy wooden shield.";
+    pub const LEATHER_ARMOR: &str = "A jerkin of boiled leather, worn on the body.";
+    pub const HELMET: &str = "A dented iron helmet.";
+    pub const BOOTS: &str = "A pair of thick-soled boots.";
+    pub const CROWN: &str =
+        "A heavy golden circlet, taken from a fallen boss. Its long-dead king's strength lingers in it.";
+
+    pub fn create_heal(amount: i32) -> String {
+        format!(
+            "A bubbling red potion. Drinking it restores up to {} hit points.",
+            amount
+        )
+    }
+
+    pub fn create_lightning(range: i32, damage: i32) -> String {
+        format!(
+            "Calls down a bolt on an enemy within {} tiles for {} damage.",
+            range, damage
+        )
+    }
+
+    pub fn create_confuse(range: i32, turns: i32) -> String {
+        format!(
+            "Scrambles the mind of an enemy within {} tiles, making it stumble about for {} turns.",
+            range, turns
+        )
+    }
+
+    pub fn create_fireball(radius: i32, damage: i32) -> String {
+        format!(
+            "Hurls a fireball at a tile you can see, burning everything within {} tiles for {} damage. Mind your own footing.",
+            radius, damage
+        )
+    }
+
+    pub fn create_boomerang(range: f32, damage: i32) -> String {
+        format!(
+            "Thrown at a tile up to {} tiles away, it hits the first creature in its path for {} damage and flies back to your hand.",
+            range, damage
+        )
+    }
+
+    pub fn create_launcher(range: f32, damage: i32) -> String {
+        format!(
+            "Shoots arrows at an enemy within {} tiles for {} damage. Wield it, then fire with F.",
+            range, damage
+        )
+    }
+
+    pub fn create_arrows(recover_chance: f32) -> String {
+        format!(
+            "Ammunition for a bow or crossbow. Each shot uses one up, though about {:.0}% of them can be picked up again where they land.",
+            recover_chance * 100.0
+        )
+    }
+
+    pub fn create_key(branch: &str, depth: u32) -> String {
+        format!(
+            "Opens the locked doors on {} level {}. Walk into one to use it.",
+            branch, depth
+        )
+    }
+}
+
 pub mod consumables {
     pub mod potions {
         pub mod healing {}
-    }
 
-    pub mod scrolls {
-        pub mod lightning {}
+        pub mod murky {
+            use tcod::colors::{self, Color};
+
+            pub const NAME: &str = "Murky Potion";
+            pub const COLOR: Color = colors::DARK_CHARTREUSE;
+            pub const NUM_TURNS: i32 = 8;
+            // eighth turns to rotate the movement keys by: a right angle either way, or a reversal
+            pub const ROTATIONS: [usize; 3] = [2, 4, 6];
 
-        pub mod confusion {
+            pub const DRUNK: &str =
+                "The world tilts sideways! Your feet no longer go where you tell them.";
+            pub const WORN_OFF: &str = "Your sense of direction returns.";
+
+            pub fn create_status_label(turns_left: i32) -> String {
+                format!("Scrambled {}", turns_left)
+            }
         }
+    }
 
-        pub mod fireball {
+    pub mod wands {
+        pub mod digging {
             use tcod::colors::{self, Color};
 
-            pub const RADIUS: i32 = 3;
-            pub const RADIUS_COLOR: Color = colors::ORANGE;
-
-            pub const DAMAGE: i32 = 25;
-            pub const DAMAGE_COLOR: Color = colors::ORANGE;
+            pub const NAME: &str = "Wand of Digging";
+            pub const COLOR: Color = colors::LIGHT_AMBER;
+            pub const RANGE: f32 = 1.5;
 
-            pub const INSTRUCTIONS: &str = "Left-click a target tile for the fireball, or right-click to cancel.";
+            pub const INSTRUCTIONS: &str =
+                "Left-click an adjacent cracked wall to dig through it, or right-click to cancel.";
             pub const INSTRUCTION_COLOR: Color = colors::LIGHT_CYAN;
 
-            pub fn create_radius_message() -> String {
-                format!(
-                    "The fireball explodes, burning everything within {} tiles!",
-                    RADIUS
-                )
-            }
+            pub const CRUMBLES: &str = "The cracked wall crumbles to dust!";
+            pub const TOO_SOLID: &str = "The wand only works on cracked walls.";
+            pub const BOUNDARY: &str = "The outer walls of the dungeon are too solid to dig.";
+        }
+    }
 
-            pub fn create_damage_message(name: &String) -> String {
-                format!("The {} gets burned for {} hit points.", name, DAMAGE)
+    pub mod scrolls {
+        pub mod identify {
+            pub const NAME: &str = "Scroll of Identify";
+            pub const DESCRIPTION: &str =
+                "Reveals what one unidentified potion or scroll you carry really is, or whether a piece of gear is cursed.";
+        }
+
+        pub mod remove_curse {
+            use tcod::colors::{self, Color};
+
+            pub const NAME: &str = "Scroll of Remove Curse";
+            pub const DESCRIPTION: &str =
+                "Lifts the curse from every piece of gear you carry, so it can be taken off again.";
+            pub const NOTHING_CURSED: &str = "You feel as if someone is watching over you.";
+            pub const COLOR: Color = colors::LIGHT_CYAN;
+
+            pub fn create_lifted_message(count: usize) -> String {
+                match count {
+                    1 => "A soft glow washes over you, and a curse lifts.".to_string(),
+                    _ => format!("A soft glow washes over you, and {} curses lift.", count),
+                }
             }
         }
+
+        pub mod recall {
+            use tcod::colors::{self, Color};
+
+            pub const NAME: &str = "Scroll of Recall";
+            pub const TO_TOWN: &str =
+                "The world blurs around you, and you find yourself standing in the town above.";
+            pub const BACK_DOWN: &str =
+                "The town fades away, and you are back in the depths where you left off.";
+            pub const COLOR: Color = colors::LIGHT_VIOLET;
+            pub const NOWHERE_TO_RETURN: &str =
+                "The scroll has nowhere to take you back to. Read one in the dungeon first.";
+        }
+
+        pub mod fireball {
+            pub const RADIUS: i32 = 3;
+            pub const DAMAGE: i32 = 25;
+            // whether the caster gets burned when standing inside their own blast
+            pub const HITS_CASTER: bool = true;
+        }
     }
 }
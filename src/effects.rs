@@ -0,0 +1,62 @@
+use crate::constants::effects;
+
+/// The lingering conditions a creature can be under. Each one ticks once per turn
+/// until its duration runs out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EffectKind {
+    Poison,
+    Burning,
+    Bleed,
+    Stun,
+    Confusion,
+    // change how fast the creature acts while they last
+    Haste,
+    Slow,
+}
+
+impl EffectKind {
+    /// Damage dealt at the start of every turn the effect is active
+    pub fn damage_per_turn(self) -> i32 {
+        match self {
+            EffectKind::Poison => effects::poison::DAMAGE_PER_TURN,
+            EffectKind::Burning => effects::burning::DAMAGE_PER_TURN,
+            EffectKind::Bleed => effects::bleed::DAMAGE_PER_TURN,
+            EffectKind::Stun | EffectKind::Confusion | EffectKind::Haste | EffectKind::Slow => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: EffectKind,
+    pub turns_left: i32,
+}
+
+/// Adds an effect, or tops up the duration if the same one is already active.
+/// Effects don't stack, a second dose of poison just makes it last longer.
+pub fn apply(effects: &mut Vec<StatusEffect>, kind: EffectKind, turns: i32) {
+    match effects.iter_mut().find(|effect| effect.kind == kind) {
+        Some(effect) => effect.turns_left = effect.turns_left.max(turns),
+        None => effects.push(StatusEffect {
+            kind,
+            turns_left: turns,
+        }),
+    }
+}
+
+/// Counts every effect down by a turn and takes off the ones that ran out,
+/// returning those so the caller can announce them
+pub fn tick(effects: &mut Vec<StatusEffect>) -> Vec<EffectKind> {
+    for effect in effects.iter_mut() {
+        effect.turns_left -= 1;
+    }
+
+    let expired = effects
+        .iter()
+        .filter(|effect| effect.turns_left <= 0)
+        .map(|effect| effect.kind)
+        .collect();
+    effects.retain(|effect| effect.turns_left > 0);
+
+    expired
+}
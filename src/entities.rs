@@ -0,0 +1,198 @@
+use std::ops::{Index, IndexMut};
+
+/// A handle to something in a registry. Slots get reused once their occupant is
+/// removed, and the generation tells the new occupant apart from the old one, so a
+/// handle kept past a removal finds nothing instead of the wrong object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    /// The first thing ever put in a registry
+    pub const FIRST: EntityId = EntityId {
+        index: 0,
+        generation: 0,
+    };
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// Holds the objects of the current floor under stable IDs. Unlike indices into a
+/// list, an ID keeps pointing at the same object however many others come and go.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Registry<T> {
+    slots: Vec<Slot<T>>,
+    // slots emptied by a removal, filled again before the list grows
+    free: Vec<u32>,
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Registry {
+            slots: vec![],
+            free: vec![],
+        }
+    }
+}
+
+impl<T> Registry<T> {
+    pub fn insert(&mut self, value: T) -> EntityId {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                EntityId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                EntityId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Takes the object out, leaving every ID that pointed at it pointing at nothing
+    pub fn remove(&mut self, id: EntityId) -> Option<T> {
+        let slot = self.slot_mut(id)?;
+        let value = slot.value.take();
+        slot.generation += 1;
+        self.free.push(id.index);
+        value
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.slot_mut(id).and_then(|slot| slot.value.as_mut())
+    }
+
+    fn slot_mut(&mut self, id: EntityId) -> Option<&mut Slot<T>> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation && slot.value.is_some())
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Two different objects borrowed mutably at once, e.g. an attacker and its target
+    pub fn pair_mut(&mut self, first: EntityId, second: EntityId) -> (&mut T, &mut T) {
+        assert!(first.index != second.index, "cannot borrow an entity twice");
+        let (low, high) = if first.index < second.index {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        let (head, tail) = self.slots.split_at_mut(high.index as usize);
+        let low_value = Self::checked(&mut head[low.index as usize], low);
+        let high_value = Self::checked(&mut tail[0], high);
+
+        if first.index < second.index {
+            (low_value, high_value)
+        } else {
+            (high_value, low_value)
+        }
+    }
+
+    fn checked(slot: &mut Slot<T>, id: EntityId) -> &mut T {
+        assert!(slot.generation == id.generation, "stale entity id {:?}", id);
+        slot.value.as_mut().expect("no entity with that id")
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+
+    /// Every object along with its ID
+    pub fn entries(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = EntityId {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            slot.value.as_ref().map(|value| (id, value))
+        })
+    }
+
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let id = EntityId {
+                    index: index as u32,
+                    generation: slot.generation,
+                };
+                slot.value.as_mut().map(|value| (id, value))
+            })
+    }
+
+    /// The IDs of everything there is right now. Going through these rather than the
+    /// registry itself leaves it free to change along the way, as long as each ID is
+    /// checked before use.
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.entries().map(|(id, _)| id).collect()
+    }
+
+    /// The first object matching the predicate
+    pub fn find<P: FnMut(&T) -> bool>(&self, mut predicate: P) -> Option<EntityId> {
+        self.entries()
+            .find(|&(_, value)| predicate(value))
+            .map(|(id, _)| id)
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        for id in self.ids() {
+            if !keep(&self[id]) {
+                self.remove(id);
+            }
+        }
+    }
+
+    /// Takes out everything but the one object, e.g. to clear a floor around the player
+    pub fn drain_except(&mut self, kept: EntityId) -> Vec<T> {
+        self.ids()
+            .into_iter()
+            .filter(|&id| id != kept)
+            .filter_map(|id| self.remove(id))
+            .collect()
+    }
+}
+
+impl<T> Index<EntityId> for Registry<T> {
+    type Output = T;
+
+    fn index(&self, id: EntityId) -> &T {
+        self.get(id).expect("no entity with that id")
+    }
+}
+
+impl<T> IndexMut<EntityId> for Registry<T> {
+    fn index_mut(&mut self, id: EntityId) -> &mut T {
+        self.get_mut(id).expect("no entity with that id")
+    }
+}
@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use crate::achievements::Achievement;
+use crate::constants;
+use crate::map::branches::Branch;
+
+/// Something that happened during a run. The game publishes these as they happen, and
+/// the message log, the run's statistics and its achievements all work from them,
+/// besides the event file written for tools.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GameEvent {
+    TurnEnded {
+        turn: u32,
+    },
+    Moved {
+        name: String,
+        from: (i32, i32),
+        to: (i32, i32),
+    },
+    Attacked {
+        attacker: String,
+        target: String,
+        damage: i32,
+        critical: bool,
+    },
+    EntityDied {
+        name: String,
+        // the monster definition it was spawned from, None for anything else
+        enemy: Option<String>,
+        xp: i32,
+        player: bool,
+        // the player made the kill and gets the experience, rather than another monster
+        by_player: bool,
+    },
+    ItemPickedUp {
+        item: String,
+        quantity: i32,
+    },
+    ItemUsed {
+        item: String,
+    },
+    LeveledUp {
+        level: i32,
+    },
+    // the player took the stairs down, to a floor they may have been on before
+    StairsDescended {
+        branch: Branch,
+        dungeon_level: u32,
+        first_visit: bool,
+    },
+    FloorChanged {
+        branch: Branch,
+        dungeon_level: u32,
+    },
+    AchievementUnlocked {
+        achievement: Achievement,
+    },
+}
+
+/// The run's events. Events are only ever appended, never changed or removed, so
+/// anything already written to the event file stays valid.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    events: Vec<GameEvent>,
+    // how many events have already been handed to the subscribers, and written out
+    handled: usize,
+    flushed: usize,
+}
+
+impl EventLog {
+    /// Queues the event up for the subscribers
+    pub fn publish(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+
+    /// The oldest event the subscribers haven't heard about yet
+    pub fn next_unhandled(&mut self) -> Option<GameEvent> {
+        let event = self.events.get(self.handled).cloned()?;
+        self.handled += 1;
+        Some(event)
+    }
+
+    /// Appends everything recorded since the last flush to the event file, one JSON
+    /// object per line
+    pub fn flush_to_file(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.flushed == self.events.len() {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(constants::EVENT_LOG_FILE_NAME)?;
+        for event in &self.events[self.flushed..] {
+            writeln!(file, "{}", serde_json::to_string(event)?)?;
+        }
+
+        self.flushed = self.events.len();
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use tcod::map::Map as FovMap;
+
+use crate::constants::gui::{MAP_HEIGHT, MAP_WIDTH};
+use crate::replay::{Input, Replay};
+use crate::settings::Settings;
+use crate::{Game, Messages, Objects};
+
+/// Whatever shows the game to the player and takes their input. The rules only ever
+/// go through this, never a window directly, so a run can also be played without one.
+pub trait Frontend {
+    /// The tiles the player can see, brought up to date before every render
+    fn fov(&self) -> &FovMap;
+    fn fov_mut(&mut self) -> &mut FovMap;
+    fn settings(&self) -> &Settings;
+    fn replay(&self) -> &Replay;
+    fn replay_mut(&mut self) -> &mut Replay;
+
+    /// Shows the game as it stands
+    fn render(&mut self, objects: &Objects, game: &mut Game);
+    /// Forgets anything drawn of the last floor
+    fn clear_map(&mut self);
+    /// Picks out tiles on the map until told otherwise, e.g. the targets in reach
+    fn highlight(&mut self, tiles: Vec<(i32, i32)>);
+
+    /// What the player does next during play, None once they stop playing
+    fn next_input(&mut self) -> Option<Input>;
+    /// The option picked from the menu, None if the player backs out
+    fn menu(&mut self, header: &str, options: &[&str], width: i32) -> Option<usize>;
+    /// A tile the player aims at in view and in range, None if they back out. Area
+    /// spells show how far the blast reaches.
+    fn pick_tile(
+        &mut self,
+        objects: &Objects,
+        game: &mut Game,
+        max_range: Option<f32>,
+        blast_radius: Option<i32>,
+    ) -> Option<(i32, i32)>;
+
+    /// Lets the player look around the map, which changes nothing in the game
+    fn look_around(&mut self, objects: &Objects, game: &mut Game);
+    fn show_message_history(&mut self, log: &Messages);
+    fn toggle_fullscreen(&mut self);
+    /// Whether the player has closed the game altogether
+    fn is_closed(&self) -> bool;
+}
+
+/// Plays a recorded run through without showing anything, answering everything the
+/// game asks from the recording
+pub struct Headless {
+    fov: FovMap,
+    settings: Settings,
+    replay: Replay,
+}
+
+impl Headless {
+    pub fn new(settings: Settings, inputs: Vec<Input>) -> Self {
+        let mut replay = Replay::default();
+        replay.start_playback(inputs, vec![]);
+        Headless {
+            fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+            settings,
+            replay,
+        }
+    }
+}
+
+impl Frontend for Headless {
+    fn fov(&self) -> &FovMap {
+        &self.fov
+    }
+
+    fn fov_mut(&mut self) -> &mut FovMap {
+        &mut self.fov
+    }
+
+    fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    fn replay_mut(&mut self) -> &mut Replay {
+        &mut self.replay
+    }
+
+    fn render(&mut self, _objects: &Objects, _game: &mut Game) {}
+
+    fn clear_map(&mut self) {}
+
+    fn highlight(&mut self, _tiles: Vec<(i32, i32)>) {}
+
+    fn next_input(&mut self) -> Option<Input> {
+        self.replay.next()
+    }
+
+    fn menu(&mut self, _header: &str, _options: &[&str], _width: i32) -> Option<usize> {
+        self.replay.next_menu()
+    }
+
+    fn pick_tile(
+        &mut self,
+        _objects: &Objects,
+        _game: &mut Game,
+        _max_range: Option<f32>,
+        _blast_radius: Option<i32>,
+    ) -> Option<(i32, i32)> {
+        self.replay.next_target()
+    }
+
+    fn look_around(&mut self, _objects: &Objects, _game: &mut Game) {}
+
+    fn show_message_history(&mut self, _log: &Messages) {}
+
+    fn toggle_fullscreen(&mut self) {}
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
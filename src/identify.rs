@@ -0,0 +1,84 @@
+use rand::{Rng, SeedableRng, XorShiftRng};
+use tcod::colors::Color;
+
+use crate::constants::identify;
+
+/// How an unidentified potion or scroll shows up in one run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Appearance {
+    pub name: String,
+    pub color: Color,
+}
+
+/// Which potions and scrolls look like what this run, and which of them the player
+/// has figured out. Items are told apart by their true name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Identification {
+    appearances: Vec<(String, Appearance)>,
+    identified: Vec<String>,
+}
+
+impl Identification {
+    /// Hands out shuffled appearances to the given potions and scrolls. They only
+    /// depend on the run's seed, so a seeded run looks the same every time. Anything
+    /// left over once the appearances run out is known from the start.
+    pub fn new(potions: &[String], scrolls: &[String], seed: u32) -> Self {
+        let mut rng = XorShiftRng::from_seed([seed, 0x1B87_3593, 0xCC9E_2D51, 0xE654_6B64]);
+
+        let mut potion_looks: Vec<Appearance> = identify::POTIONS
+            .iter()
+            .map(|&(look, color)| Appearance {
+                name: identify::create_potion_name(look),
+                color,
+            })
+            .collect();
+        let mut scroll_looks: Vec<Appearance> = identify::SCROLL_LABELS
+            .iter()
+            .map(|label| Appearance {
+                name: identify::create_scroll_name(label),
+                color: identify::SCROLL_COLOR,
+            })
+            .collect();
+        rng.shuffle(&mut potion_looks);
+        rng.shuffle(&mut scroll_looks);
+
+        let appearances = potions
+            .iter()
+            .cloned()
+            .zip(potion_looks)
+            .chain(scrolls.iter().cloned().zip(scroll_looks))
+            .collect();
+        Identification {
+            appearances,
+            identified: vec![],
+        }
+    }
+
+    /// What the item looks like, None once the player knows what it is
+    pub fn appearance(&self, name: &str) -> Option<&Appearance> {
+        if self.is_known(name) {
+            return None;
+        }
+        self.appearances
+            .iter()
+            .find(|(true_name, _)| true_name == name)
+            .map(|(_, appearance)| appearance)
+    }
+
+    pub fn is_known(&self, name: &str) -> bool {
+        self.identified.iter().any(|known| known == name)
+            || !self
+                .appearances
+                .iter()
+                .any(|(true_name, _)| true_name == name)
+    }
+
+    /// Lets the player know the item from now on, returning false if they already did
+    pub fn identify(&mut self, name: &str) -> bool {
+        if self.is_known(name) {
+            return false;
+        }
+        self.identified.push(name.to_string());
+        true
+    }
+}
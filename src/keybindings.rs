@@ -0,0 +1,372 @@
+use std::fs::{self, File};
+use std::io::Read;
+
+use tcod::input::{Key, KeyCode};
+
+use crate::constants;
+
+/// Everything the player can do with a key press during play
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    Rest,
+    MessageHistory,
+    PickUp,
+    Equip,
+    Fire,
+    Search,
+    Inventory,
+    Drop,
+    Look,
+    Examine,
+    WaitTurns,
+    Cast,
+    Character,
+    Descend,
+    Climb,
+    Fullscreen,
+    Exit,
+}
+
+impl Action {
+    pub const ALL: [Action; 25] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUpLeft,
+        Action::MoveUpRight,
+        Action::MoveDownLeft,
+        Action::MoveDownRight,
+        Action::Rest,
+        Action::MessageHistory,
+        Action::PickUp,
+        Action::Equip,
+        Action::Fire,
+        Action::Search,
+        Action::Inventory,
+        Action::Drop,
+        Action::Look,
+        Action::Examine,
+        Action::WaitTurns,
+        Action::Cast,
+        Action::Character,
+        Action::Descend,
+        Action::Climb,
+        Action::Fullscreen,
+        Action::Exit,
+    ];
+
+    /// What the action is called in the keybindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUpLeft => "move_up_left",
+            Action::MoveUpRight => "move_up_right",
+            Action::MoveDownLeft => "move_down_left",
+            Action::MoveDownRight => "move_down_right",
+            Action::Rest => "rest",
+            Action::MessageHistory => "message_history",
+            Action::PickUp => "pick_up",
+            Action::Equip => "equip",
+            Action::Fire => "fire",
+            Action::Search => "search",
+            Action::Inventory => "inventory",
+            Action::Drop => "drop",
+            Action::Look => "look",
+            Action::Examine => "examine",
+            Action::WaitTurns => "wait",
+            Action::Cast => "cast",
+            Action::Character => "character",
+            Action::Descend => "descend",
+            Action::Climb => "climb",
+            Action::Fullscreen => "fullscreen",
+            Action::Exit => "exit",
+        }
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::MoveUp => &["Up", "NumPad8"],
+            Action::MoveDown => &["Down", "NumPad2"],
+            Action::MoveLeft => &["Left", "NumPad4"],
+            Action::MoveRight => &["Right", "NumPad6"],
+            Action::MoveUpLeft => &["Home", "NumPad7"],
+            Action::MoveUpRight => &["PageUp", "NumPad9"],
+            Action::MoveDownLeft => &["End", "NumPad1"],
+            Action::MoveDownRight => &["PageDown", "NumPad3"],
+            Action::Rest => &["NumPad5"],
+            Action::MessageHistory => &["Ctrl+m"],
+            Action::PickUp => &["g"],
+            Action::Equip => &["e"],
+            Action::Fire => &["f"],
+            Action::Search => &["s"],
+            Action::Inventory => &["i"],
+            Action::Drop => &["d"],
+            Action::Look => &["x"],
+            Action::Examine => &["X"],
+            Action::WaitTurns => &["w"],
+            Action::Cast => &["z"],
+            Action::Character => &["c"],
+            Action::Descend => &["<"],
+            Action::Climb => &[">"],
+            Action::Fullscreen => &["Alt+Enter"],
+            Action::Exit => &["Escape"],
+        }
+    }
+
+    /// How far a movement action steps, None for everything else
+    pub fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Action::MoveUp => Some((0, -1)),
+            Action::MoveDown => Some((0, 1)),
+            Action::MoveLeft => Some((-1, 0)),
+            Action::MoveRight => Some((1, 0)),
+            Action::MoveUpLeft => Some((-1, -1)),
+            Action::MoveUpRight => Some((1, -1)),
+            Action::MoveDownLeft => Some((-1, 1)),
+            Action::MoveDownRight => Some((1, 1)),
+            _ => None,
+        }
+    }
+}
+
+// the named keys a binding can use, anything else has to be a single character
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+    ("Enter", KeyCode::Enter),
+    ("Escape", KeyCode::Escape),
+    ("Backspace", KeyCode::Backspace),
+    ("Tab", KeyCode::Tab),
+    ("Space", KeyCode::Spacebar),
+    ("NumPad0", KeyCode::NumPad0),
+    ("NumPad1", KeyCode::NumPad1),
+    ("NumPad2", KeyCode::NumPad2),
+    ("NumPad3", KeyCode::NumPad3),
+    ("NumPad4", KeyCode::NumPad4),
+    ("NumPad5", KeyCode::NumPad5),
+    ("NumPad6", KeyCode::NumPad6),
+    ("NumPad7", KeyCode::NumPad7),
+    ("NumPad8", KeyCode::NumPad8),
+    ("NumPad9", KeyCode::NumPad9),
+    ("NumPadEnter", KeyCode::NumPadEnter),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+];
+
+/// Either a named key or whatever key types the given character
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeyName {
+    Code(KeyCode),
+    Char(char),
+}
+
+/// One key press an action answers to, like "g", "Up" or "Alt+Enter"
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeySpec {
+    key: KeyName,
+    ctrl: bool,
+    alt: bool,
+}
+
+impl KeySpec {
+    fn parse(text: &str) -> Option<Self> {
+        let mut spec = KeySpec {
+            key: KeyName::Char(' '),
+            ctrl: false,
+            alt: false,
+        };
+
+        let mut rest = text;
+        loop {
+            if rest.starts_with("Ctrl+") {
+                spec.ctrl = true;
+                rest = &rest["Ctrl+".len()..];
+            } else if rest.starts_with("Alt+") {
+                spec.alt = true;
+                rest = &rest["Alt+".len()..];
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        spec.key = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyName::Char(c),
+            _ => KeyName::Code(
+                KEY_NAMES
+                    .iter()
+                    .find(|(name, _)| *name == rest)
+                    .map(|&(_, code)| code)?,
+            ),
+        };
+        Some(spec)
+    }
+
+    fn matches(self, key: Key) -> bool {
+        let key_matches = match self.key {
+            KeyName::Code(code) => key.code == code,
+            KeyName::Char(c) => key.printable == c,
+        };
+        key_matches && key.ctrl == self.ctrl && key.alt == self.alt
+    }
+}
+
+/// Which keys trigger which actions, read from the keybindings file at startup.
+/// Actions the file leaves out keep their default keys.
+pub struct Keybindings {
+    bindings: Vec<(Action, Vec<KeySpec>)>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|&action| {
+                let keys = action
+                    .default_keys()
+                    .iter()
+                    .map(|text| KeySpec::parse(text).expect("default key bindings are valid"))
+                    .collect();
+                (action, keys)
+            })
+            .collect();
+
+        Keybindings { bindings }
+    }
+}
+
+impl Keybindings {
+    /// Reads the keybindings file, writing one with the defaults first if there isn't
+    /// one yet so players have something to edit. A broken file gets a warning and
+    /// the defaults.
+    pub fn load() -> Self {
+        let mut text = String::new();
+        let read = File::open(constants::KEYBINDINGS_FILE_NAME)
+            .and_then(|mut file| file.read_to_string(&mut text));
+        if read.is_err() {
+            if fs::write(constants::KEYBINDINGS_FILE_NAME, default_file()).is_err() {
+                eprintln!("{}", constants::create_keybindings_write_warning());
+            }
+            return Keybindings::default();
+        }
+
+        let mut keybindings = Keybindings::default();
+        for (index, line) in text.lines().enumerate() {
+            if keybindings.apply_line(line).is_none() {
+                eprintln!("{}", constants::create_bad_keybindings_warning(index + 1));
+                return Keybindings::default();
+            }
+        }
+        keybindings
+    }
+
+    /// Reads one `action = "key"` or `action = ["key", "key"]` line, returning None
+    /// if it can't be made sense of
+    fn apply_line(&mut self, line: &str) -> Option<()> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Some(());
+        }
+
+        let equals = line.find('=')?;
+        let name = line[..equals].trim();
+        let action = *Action::ALL.iter().find(|action| action.name() == name)?;
+
+        let (values, rest) = parse_value(line[equals + 1..].trim())?;
+        let rest = rest.trim();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return None;
+        }
+
+        let keys = values
+            .iter()
+            .map(|text| KeySpec::parse(text))
+            .collect::<Option<Vec<_>>>()?;
+        let binding = self
+            .bindings
+            .iter_mut()
+            .find(|(bound, _)| *bound == action)?;
+        binding.1 = keys;
+        Some(())
+    }
+
+    /// The action bound to the key, checking actions in the order the game lists them
+    pub fn action_for(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.iter().any(|spec| spec.matches(key)))
+            .map(|&(action, _)| action)
+    }
+}
+
+/// Splits a quoted string or a bracketed list of them off the front of the text,
+/// returning the strings and whatever follows
+fn parse_value(text: &str) -> Option<(Vec<&str>, &str)> {
+    if let Some(list) = text.strip_prefix('[') {
+        let mut values = vec![];
+        let mut rest = list.trim_start();
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                return Some((values, after));
+            }
+            let (value, after) = parse_string(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+        }
+    }
+
+    let (value, rest) = parse_string(text)?;
+    Some((vec![value], rest))
+}
+
+fn parse_string(text: &str) -> Option<(&str, &str)> {
+    let inner = text.strip_prefix('"')?;
+    let end = inner.find('"')?;
+    Some((&inner[..end], &inner[end + 1..]))
+}
+
+/// The keybindings file written out on first start, listing every action with its
+/// default keys
+fn default_file() -> String {
+    let mut text = String::from(constants::KEYBINDINGS_FILE_HEADER);
+    for action in Action::ALL.iter() {
+        let keys: Vec<String> = action
+            .default_keys()
+            .iter()
+            .map(|key| format!("\"{}\"", key))
+            .collect();
+        text.push_str(&format!("{} = [{}]\n", action.name(), keys.join(", ")));
+    }
+    text
+}
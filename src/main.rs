@@ -4,31 +4,62 @@ extern crate serde_derive;
 extern crate rand;
 extern crate tcod;
 
+mod achievements;
 mod constants;
+mod effects;
+mod entities;
+mod events;
+mod frontend;
+mod identify;
+mod keybindings;
+mod map;
+mod monsters;
+mod persistence;
+mod replay;
+mod scores;
+mod scripts;
+mod settings;
+mod traps;
 
 use tcod::colors::{self, Color};
 use tcod::console::*;
 use tcod::input::Key;
 use tcod::input::KeyCode::*;
 use tcod::input::{self, Event, Mouse};
-use tcod::map::{FovAlgorithm, Map as FovMap};
+use tcod::map::Map as FovMap;
 
 use std::cmp;
-use std::error::Error;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::env;
+use std::mem;
+use std::ops::Range;
+use std::thread;
 
 use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
-use rand::Rng;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use achievements::Achievement;
+use effects::{EffectKind, StatusEffect};
+use entities::{EntityId, Registry};
+use events::{EventLog, GameEvent};
+use frontend::{Frontend, Headless};
+use identify::Identification;
+use keybindings::{Action, Keybindings};
+use map::branches::{Branch, DungeonLevel};
+use map::spawn_tables::FloorTheme;
+use monsters::{MonsterAi, MonsterDef};
+use replay::{Input, Replay};
+use scripts::{Halt, Host, Script, ScriptDef, Value};
+use settings::{GlyphSet, Layout, Settings};
+use traps::{Trap, TrapKind};
 
 const ROOM_MAX_SIZE: i32 = 10;
 const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
+const MAX_ROOMS: i32 = 60;
+const ROOM_AREA_PER_MONSTER: i32 = 16;
+const CRACKED_WALL_CHANCE: f32 = 0.05;
 
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
-const FOV_LIGHT_WALLS: bool = true;
-
-const PLAYER: usize = 0;
+// the player is put in the registry first and never taken out of it
+const PLAYER: EntityId = EntityId::FIRST;
 const TORCH_RADIUS: i32 = 10;
 const LEVEL_UP_BASE: i32 = 200;
 const LEVEL_UP_FACTOR: i32 = 150;
@@ -37,6 +68,9 @@ const LIGHTNING_DAMAGE: i32 = 40;
 const LIGHTNING_RANGE: i32 = 5;
 const CONFUSE_RANGE: i32 = 8;
 const CONFUSE_NUM_TURNS: i32 = 10;
+const CHASE_NUM_TURNS: i32 = 5;
+const WANDER_CHANCE: f32 = 0.2;
+const WANDER_RADIUS: f32 = 4.0;
 
 const LIMIT_FPS: i32 = 20;
 
@@ -56,11 +90,22 @@ const COLOR_LIGHT_GROUND: Color = Color {
     g: 180,
     b: 50,
 };
+const COLOR_DARK_CRACKED_WALL: Color = Color {
+    r: 40,
+    g: 20,
+    b: 90,
+};
+const COLOR_LIGHT_CRACKED_WALL: Color = Color {
+    r: 150,
+    g: 90,
+    b: 40,
+};
 
 type Map = Vec<Vec<Tile>>;
 type Messages = Vec<(String, Color)>;
+type Objects = Registry<GameObject>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct GameObject {
     x: i32,
     y: i32,
@@ -75,6 +120,23 @@ struct GameObject {
     always_visible: bool,
     level: i32,
     equipment: Option<Equipment>,
+    last_seen: Option<(i32, i32)>,
+    searchable: bool,
+    weight: i32,
+    // the name of the monster definition this was spawned from
+    enemy: Option<String>,
+    npc: Option<Npc>,
+    // how many are in the stack, for items like arrows that pile up
+    quantity: i32,
+    effects: Vec<StatusEffect>,
+    // how many coins a gold pile holds, zero for anything else
+    gold: i32,
+    // the name of the item script a scripted item runs
+    #[serde(default)]
+    script: Option<String>,
+    // stairs that won't let the player through yet, like those behind a living boss
+    #[serde(default)]
+    locked: bool,
 }
 
 impl GameObject {
@@ -93,16 +155,125 @@ impl GameObject {
             always_visible: false,
             level: 1,
             equipment: None,
+            last_seen: None,
+            searchable: false,
+            weight: 0,
+            enemy: None,
+            script: None,
+            npc: None,
+            quantity: 1,
+            effects: vec![],
+            gold: 0,
+            locked: false,
         }
     }
 
-    pub fn draw(&self, con: &mut Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
+    pub fn draw(&self, con: &mut dyn Console, color: Color, dimmed: bool, glyphs: GlyphSet) {
+        self.draw_as(con, self.glyph(glyphs), color, dimmed);
+    }
+
+    /// Draws something else at the object's position, like the pile marker that
+    /// stands in for several items sharing a tile
+    pub fn draw_as(&self, con: &mut dyn Console, glyph: char, color: Color, dimmed: bool) {
+        let color = if dimmed {
+            color * constants::gui::OUT_OF_FOV_FADE
+        } else {
+            color
+        };
+        con.set_default_foreground(color);
+        con.put_char(self.x, self.y, glyph, BackgroundFlag::None);
+    }
+
+    pub fn glyph(&self, glyphs: GlyphSet) -> char {
+        match glyphs {
+            GlyphSet::Ascii => self.char,
+            GlyphSet::Symbols => constants::gui::glyphs::OBJECTS
+                .iter()
+                .find(|&&(ascii, _)| ascii == self.char)
+                .map_or(self.char, |&(_, symbol)| symbol),
+        }
     }
 
     pub fn clear(&self, con: &mut Console) {
         con.put_char(self.x, self.y, ' ', BackgroundFlag::None);
+
+        if let Some((x, y)) = self.last_seen {
+            con.put_char(x, y, ' ', BackgroundFlag::None);
+        }
+    }
+
+    /// The object's color, tinted to reflect the first status it's currently under
+    pub fn status_color(&self) -> Color {
+        use constants::effects;
+
+        match self.effects.first() {
+            Some(effect) => colors::lerp(
+                self.color,
+                effects::color(effect.kind),
+                effects::STATUS_TINT,
+            ),
+            None => self.color,
+        }
+    }
+
+    /// How fast this acts right now, normal speed being 100
+    pub fn speed(&self) -> i32 {
+        use constants::speed;
+
+        let mut current = self.fighter.map_or(speed::NORMAL, |f| f.speed);
+        if self.has_effect(EffectKind::Haste) {
+            current = current * speed::HASTED_PERCENT / 100;
+        }
+        if self.has_effect(EffectKind::Slow) {
+            current = current * speed::SLOWED_PERCENT / 100;
+        }
+        current
+    }
+
+    pub fn has_effect(&self, kind: EffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub fn add_effect(&mut self, kind: EffectKind, turns: i32, game: &mut Game) {
+        effects::apply(&mut self.effects, kind, turns);
+        game.log.add(
+            constants::effects::create_applied_message(&self.name, kind),
+            constants::effects::color(kind),
+        );
+    }
+
+    /// Shifts a monster's color toward red when it outclasses the player and toward grey
+    /// when it is hardly worth the trouble, comparing the damage each deals to the other
+    pub fn threat_color(&self, player: &GameObject, game: &Game) -> Color {
+        use constants::gui::threat;
+
+        let color = self.status_color();
+        if self.fighter.is_none() || self.ai.is_none() {
+            return color;
+        }
+
+        let damage_taken = self.power(game) - player.defense(game);
+        let damage_dealt = cmp::max(1, player.power(game) - self.defense(game));
+        let danger = damage_taken as f32 / damage_dealt as f32;
+
+        if danger >= threat::DANGEROUS {
+            colors::lerp(color, threat::DANGEROUS_COLOR, threat::TINT)
+        } else if danger <= threat::TRIVIAL {
+            colors::lerp(color, threat::TRIVIAL_COLOR, threat::TINT)
+        } else {
+            color
+        }
+    }
+
+    /// The object's name, followed by any statuses it's currently under
+    pub fn display_name(&self) -> String {
+        self.effects.iter().fold(self.name.clone(), |name, effect| {
+            format!(
+                "{} {}",
+                name,
+                constants::effects::create_status_label(effect.kind, effect.turns_left)
+            )
+        })
     }
 
     pub fn pos(&self) -> (i32, i32) {
@@ -124,11 +295,32 @@ impl GameObject {
         ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
     }
 
-    pub fn take_damage(&mut self, damage: i32, mut game: &mut Game) -> Option<i32> {
+    /// Hurts the object, returning the experience it was worth if that killed it.
+    /// `by_player` says whether the player gets the credit for the kill.
+    pub fn take_damage(&mut self, damage: i32, by_player: bool, game: &mut Game) -> Option<i32> {
+        self.take_hit(damage, by_player, false, game)
+    }
+
+    /// `take_damage` for a blow that may have landed critically, which its damage
+    /// number shows
+    pub fn take_hit(
+        &mut self,
+        damage: i32,
+        by_player: bool,
+        critical: bool,
+        mut game: &mut Game,
+    ) -> Option<i32> {
         // apply damage if possible
         if let Some(fighter) = self.fighter.as_mut() {
             if damage > 0 {
                 fighter.hp -= damage;
+                game.damage_popups.push(DamagePopup {
+                    x: self.x,
+                    y: self.y,
+                    amount: damage,
+                    critical,
+                    frames_left: constants::gui::DAMAGE_POPUP_FRAMES,
+                });
             }
         }
 
@@ -136,6 +328,13 @@ impl GameObject {
         if let Some(fighter) = self.fighter {
             if fighter.hp <= 0 {
                 self.alive = false;
+                game.publish(GameEvent::EntityDied {
+                    name: self.name.clone(),
+                    enemy: self.enemy.clone(),
+                    xp: fighter.xp,
+                    player: fighter.on_death == DeathCallback::Player,
+                    by_player,
+                });
                 fighter.on_death.callback(self, &mut game);
                 return Some(fighter.xp);
             }
@@ -145,22 +344,63 @@ impl GameObject {
     }
 
     pub fn attack(&mut self, target: &mut GameObject, mut game: &mut Game) {
+        use constants::combat;
+
+        // a fumble still spends the attacker's turn
+        if game.rng.gen::<f32>() < combat::FUMBLE_CHANCE {
+            game.log.add(
+                combat::create_fumble_message(&self.name, &target.name),
+                combat::FUMBLE_COLOR,
+            );
+            return;
+        }
+
         // A simple formula for attack damage
-        let damage = self.power(game) - target.defense(game);
+        let critical = game.rng.gen::<f32>() < self.crit_chance(game);
+        let mut damage = self.power(game) - target.defense(game);
+        if critical {
+            damage *= combat::CRIT_MULTIPLIER;
+        }
+        game.publish(GameEvent::Attacked {
+            attacker: self.name.clone(),
+            target: target.name.clone(),
+            damage: cmp::max(damage, 0),
+            critical,
+        });
 
         if damage > 0 {
             // Make the target take some damage
-            game.log.add(
-                format!(
-                    "{} attacks {} for {} hit points",
-                    self.name, target.name, damage
-                ),
-                colors::WHITE,
-            );
-            if let Some(xp) = target.take_damage(damage, &mut game) {
+            if critical {
+                game.log.add(
+                    combat::create_crit_message(&self.name, &target.name, damage),
+                    combat::CRIT_COLOR,
+                );
+            } else {
+                game.log.add(
+                    format!(
+                        "{} attacks {} for {} hit points",
+                        self.name, target.name, damage
+                    ),
+                    colors::WHITE,
+                );
+            }
+            if target.fighter.map(|f| f.on_death) == Some(DeathCallback::Player) {
+                game.hurt_by = Some(self.name.clone());
+            }
+            let by_player = self.fighter.map(|f| f.on_death) == Some(DeathCallback::Player);
+            if let Some(xp) = target.take_hit(damage, by_player, critical, &mut game) {
                 // give xp to fighter. Only relevant if player, but no need to check.
                 self.fighter.as_mut().unwrap().xp += xp;
-            };
+            } else if let Some(on_hit) = self
+                .enemy
+                .as_ref()
+                .and_then(|name| monsters::find(&game.bestiary, name))
+                .and_then(|monster| monster.on_hit)
+            {
+                if game.rng.gen::<f32>() < on_hit.chance {
+                    target.add_effect(on_hit.effect, on_hit.turns, game);
+                }
+            }
         } else {
             game.log.add(
                 format!(
@@ -199,6 +439,14 @@ impl GameObject {
                     format!("Equipped {} on {}.", self.name, equipment.slot),
                     colors::LIGHT_GREEN,
                 );
+                // wearing it is enough to tell how it feels
+                equipment.blessing_known = true;
+                if equipment.blessing == Blessing::Cursed {
+                    log.add(
+                        constants::curses::create_stuck_message(&self.name),
+                        constants::curses::COLOR,
+                    );
+                }
             }
         } else {
             log.add(
@@ -208,15 +456,23 @@ impl GameObject {
         }
     }
 
-    pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) {
+    /// Takes the equipment off, returning whether it's off now. Cursed gear stays on.
+    pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) -> bool {
         if self.item.is_none() {
             log.add(
                 format!("Can't dequip {:?} because it's not an Item.", self),
                 colors::RED,
             );
-            return;
+            return false;
         };
         if let Some(ref mut equipment) = self.equipment {
+            if equipment.is_stuck() {
+                log.add(
+                    constants::curses::create_cant_remove_message(&self.name),
+                    constants::curses::COLOR,
+                );
+                return false;
+            }
             if equipment.equipped {
                 equipment.equipped = false;
                 log.add(
@@ -224,21 +480,19 @@ impl GameObject {
                     colors::LIGHT_YELLOW,
                 );
             }
+            true
         } else {
             log.add(
                 format!("Can't dequip {:?} because it's not an Equipment.", self),
                 colors::RED,
             );
+            false
         }
     }
 
     pub fn power(&self, game: &Game) -> i32 {
         let base_power = self.fighter.map_or(0, |f| f.base_power);
-        let bonus_power: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(|e| e.power_bonus)
-            .sum();
+        let bonus_power: i32 = self.get_all_equipped(game).iter().map(|e| e.power()).sum();
 
         base_power + bonus_power
     }
@@ -248,7 +502,7 @@ impl GameObject {
         let bonus_defense: i32 = self
             .get_all_equipped(game)
             .iter()
-            .map(|e| e.defense_bonus)
+            .map(|e| e.defense())
             .sum();
 
         base_defense + bonus_defense
@@ -256,11 +510,22 @@ impl GameObject {
 
     pub fn max_hp(&self, game: &Game) -> i32 {
         let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
-        let bonus_max_hp: i32 = self.get_all_equipped(game).iter().map(|e| e.hp_bonus).sum();
+        let bonus_max_hp: i32 = self.get_all_equipped(game).iter().map(|e| e.max_hp()).sum();
 
         base_max_hp + bonus_max_hp
     }
 
+    /// How likely an attack is to land a critical hit. Everyone starts from the same
+    /// base, the player's gear adds to it.
+    pub fn crit_chance(&self, game: &Game) -> f32 {
+        let bonus: f32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.crit_bonus)
+            .sum();
+        constants::combat::BASE_CRIT_CHANCE + bonus
+    }
+
     pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
         if self.name == constants::player_base::NAME {
             game.inventory
@@ -272,6 +537,96 @@ impl GameObject {
             vec![]
         }
     }
+
+    pub fn builder(
+        x: i32,
+        y: i32,
+        char: char,
+        name: &str,
+        color: Color,
+        blocks: bool,
+    ) -> GameObjectBuilder {
+        GameObjectBuilder {
+            object: GameObject::new(x, y, char, name, color, blocks),
+        }
+    }
+}
+
+/// Sets up the optional parts of a `GameObject` in one chain instead of assigning
+/// fields one at a time after `GameObject::new`
+struct GameObjectBuilder {
+    object: GameObject,
+}
+
+impl GameObjectBuilder {
+    pub fn with_fighter(mut self, fighter: Fighter) -> Self {
+        self.object.fighter = Some(fighter);
+        self
+    }
+
+    pub fn with_ai(mut self, ai: Ai) -> Self {
+        self.object.ai = Some(ai);
+        self
+    }
+
+    pub fn with_item(mut self, item: Item) -> Self {
+        self.object.item = Some(item);
+        self
+    }
+
+    pub fn with_equipment(mut self, equipment: Equipment) -> Self {
+        self.object.equipment = Some(equipment);
+        self
+    }
+
+    pub fn with_enemy(mut self, enemy: &str) -> Self {
+        self.object.enemy = Some(enemy.into());
+        self
+    }
+
+    pub fn with_script(mut self, script: &str) -> Self {
+        self.object.script = Some(script.into());
+        self
+    }
+
+    pub fn with_npc(mut self, npc: Npc) -> Self {
+        self.object.npc = Some(npc);
+        self
+    }
+
+    pub fn with_weight(mut self, weight: i32) -> Self {
+        self.object.weight = weight;
+        self
+    }
+
+    pub fn with_quantity(mut self, quantity: i32) -> Self {
+        self.object.quantity = quantity;
+        self
+    }
+
+    pub fn with_gold(mut self, gold: i32) -> Self {
+        self.object.gold = gold;
+        self
+    }
+
+    pub fn alive(mut self) -> Self {
+        self.object.alive = true;
+        self
+    }
+
+    pub fn always_visible(mut self) -> Self {
+        self.object.always_visible = true;
+        self
+    }
+
+    pub fn locked(mut self) -> Self {
+        self.object.locked = true;
+        self
+    }
+
+    pub fn build(self) -> GameObject {
+        self.object
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -282,12 +637,22 @@ struct Fighter {
     base_power: i32,
     on_death: DeathCallback,
     xp: i32,
+    // how fast this acts, and the energy saved up toward its next action
+    #[serde(default = "normal_speed")]
+    speed: i32,
+    #[serde(default)]
+    energy: i32,
+}
+
+fn normal_speed() -> i32 {
+    constants::speed::NORMAL
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 enum DeathCallback {
     Player,
     Monster,
+    Boss,
 }
 
 impl DeathCallback {
@@ -296,6 +661,7 @@ impl DeathCallback {
         let callback: fn(&mut GameObject, &mut Game) = match self {
             Player => player_death,
             Monster => monster_death,
+            Boss => boss_death,
         };
 
         callback(object, &mut game);
@@ -305,9 +671,19 @@ impl DeathCallback {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Ai {
     Basic,
-    Confused {
-        previous_ai: Box<Ai>,
+    Idle {
+        home: (i32, i32),
+    },
+    Chasing {
+        last_seen: (i32, i32),
         num_turns: i32,
+        // where an idle monster lived before the chase, which it goes back to minding
+        #[serde(default)]
+        home: Option<(i32, i32)>,
+    },
+    // minds the shop and never attacks, whatever the player does
+    Shopkeeper {
+        home: (i32, i32),
     },
 }
 
@@ -316,6 +692,10 @@ struct Tile {
     blocked: bool,
     block_sight: bool,
     explored: bool,
+    cracked: bool,
+    trap: Option<Trap>,
+    // a locked door, opened by the key found on the same dungeon level
+    lock: Option<DungeonLevel>,
 }
 
 impl Tile {
@@ -324,6 +704,9 @@ impl Tile {
             blocked: false,
             block_sight: false,
             explored: false,
+            cracked: false,
+            trap: None,
+            lock: None,
         }
     }
 
@@ -332,6 +715,25 @@ impl Tile {
             blocked: true,
             block_sight: true,
             explored: false,
+            cracked: false,
+            trap: None,
+            lock: None,
+        }
+    }
+
+    /// A wall weak enough to be dug through
+    pub fn cracked_wall() -> Self {
+        Tile {
+            cracked: true,
+            ..Tile::wall()
+        }
+    }
+
+    /// A door that stays shut until the player brings the key from this level
+    pub fn locked_door(level: DungeonLevel) -> Self {
+        Tile {
+            lock: Some(level),
+            ..Tile::wall()
         }
     }
 }
@@ -342,8 +744,101 @@ enum Item {
     Lightning,
     Confuse,
     Fireball,
+    Recall,
+    Identify,
+    RemoveCurse,
+    Dig,
+    Muddle,
+    Boomerang,
+    Bow,
+    Crossbow,
+    Arrows,
+    // everyone starts with one, it never turns up in the dungeon
+    Dagger,
     Sword,
     Shield,
+    LeatherArmor,
+    Helmet,
+    Boots,
+    // the unique drop of a fallen boss
+    Crown,
+    // opens the locked doors on the dungeon level it was found on
+    Key(DungeonLevel),
+    Amulet,
+    // a scroll or potion that does whatever its script says, the object names the script
+    Scripted,
+}
+
+impl Item {
+    /// Whether using the item costs the player their turn. Recall does, so whatever waits
+    /// where it sets the player down gets to act before they do.
+    pub fn takes_turn(self) -> bool {
+        matches!(self, Item::Recall | Item::Arrows)
+    }
+
+    /// Whether several of the item share one inventory slot. Those are the ones
+    /// nobody can tell apart, gear and the kept wand each take their own.
+    pub fn stacks(self) -> bool {
+        matches!(
+            self,
+            Item::Heal
+                | Item::Lightning
+                | Item::Confuse
+                | Item::Fireball
+                | Item::Recall
+                | Item::Identify
+                | Item::RemoveCurse
+                | Item::Muddle
+                | Item::Arrows
+                | Item::Scripted
+        )
+    }
+
+    /// How far and how hard a launcher shoots, None for anything that isn't one
+    pub fn launcher_stats(self) -> Option<(f32, i32)> {
+        use constants::gear::{bow, crossbow};
+
+        match self {
+            Item::Bow => Some((bow::RANGE, bow::DAMAGE)),
+            Item::Crossbow => Some((crossbow::RANGE, crossbow::DAMAGE)),
+            _ => None,
+        }
+    }
+
+    /// What the item does, shown when examining it in the inventory
+    pub fn description(self) -> String {
+        use constants::consumables::scrolls::{fireball, identify, remove_curse};
+        use constants::descriptions;
+        use constants::gear::{arrows, boomerang};
+
+        match self {
+            Item::Heal => descriptions::create_heal(HEAL_AMOUNT),
+            Item::Lightning => descriptions::create_lightning(LIGHTNING_RANGE, LIGHTNING_DAMAGE),
+            Item::Confuse => descriptions::create_confuse(CONFUSE_RANGE, CONFUSE_NUM_TURNS),
+            Item::Fireball => descriptions::create_fireball(fireball::RADIUS, fireball::DAMAGE),
+            Item::Identify => identify::DESCRIPTION.to_string(),
+            Item::RemoveCurse => remove_curse::DESCRIPTION.to_string(),
+            Item::Recall => descriptions::RECALL.to_string(),
+            Item::Dig => descriptions::DIG.to_string(),
+            Item::Muddle => descriptions::MUDDLE.to_string(),
+            Item::Boomerang => descriptions::create_boomerang(boomerang::RANGE, boomerang::DAMAGE),
+            Item::Bow | Item::Crossbow => {
+                let (range, damage) = self.launcher_stats().unwrap();
+                descriptions::create_launcher(range, damage)
+            }
+            Item::Arrows => descriptions::create_arrows(arrows::RECOVER_CHANCE),
+            Item::Dagger => descriptions::DAGGER.to_string(),
+            Item::Sword => descriptions::SWORD.to_string(),
+            Item::Shield => descriptions::SHIELD.to_string(),
+            Item::LeatherArmor => descriptions::LEATHER_ARMOR.to_string(),
+            Item::Helmet => descriptions::HELMET.to_string(),
+            Item::Boots => descriptions::BOOTS.to_string(),
+            Item::Crown => descriptions::CROWN.to_string(),
+            Item::Key(level) => descriptions::create_key(level.branch.name(), level.depth),
+            Item::Amulet => constants::amulet::DESCRIPTION.to_string(),
+            Item::Scripted => constants::scripts::UNKNOWN_DESCRIPTION.to_string(),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -353,11 +848,87 @@ struct Equipment {
     power_bonus: i32,
     defense_bonus: i32,
     hp_bonus: i32,
+    #[serde(default)]
+    blessing: Blessing,
+    // the player has worn or identified it, and knows whether it's cursed
+    #[serde(default)]
+    blessing_known: bool,
+    // added to the wearer's chance of landing a critical hit
+    #[serde(default)]
+    crit_bonus: f32,
+}
+
+/// Blessed gear is a little better than it looks, cursed gear worse, and once worn it
+/// can't be taken off again until the curse is lifted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum Blessing {
+    Blessed,
+    #[default]
+    Uncursed,
+    Cursed,
+}
+
+impl Blessing {
+    pub fn name(self) -> &'static str {
+        use constants::curses;
+
+        match self {
+            Blessing::Blessed => curses::BLESSED,
+            Blessing::Uncursed => curses::UNCURSED,
+            Blessing::Cursed => curses::CURSED,
+        }
+    }
+}
+
+impl Equipment {
+    pub fn power(&self) -> i32 {
+        self.blessed(self.power_bonus)
+    }
+
+    pub fn defense(&self) -> i32 {
+        self.blessed(self.defense_bonus)
+    }
+
+    pub fn max_hp(&self) -> i32 {
+        self.blessed(self.hp_bonus)
+    }
+
+    // only the bonuses the gear has at all get better or worse
+    fn blessed(&self, bonus: i32) -> i32 {
+        use constants::curses;
+
+        match self.blessing {
+            Blessing::Blessed if bonus > 0 => bonus + curses::BLESSED_BOOST,
+            Blessing::Cursed if bonus > 0 => bonus - curses::CURSED_PENALTY,
+            _ => bonus,
+        }
+    }
+
+    /// Cursed gear stays on once it's worn
+    pub fn is_stuck(&self) -> bool {
+        self.equipped && self.blessing == Blessing::Cursed
+    }
+
+    /// True if no bonus is better than `other`'s and at least one is worse
+    pub fn is_strictly_worse_than(&self, other: &Equipment) -> bool {
+        let bonuses = [
+            (self.power_bonus, other.power_bonus),
+            (self.defense_bonus, other.defense_bonus),
+            (self.hp_bonus, other.hp_bonus),
+        ];
+
+        let (my_crit, their_crit) = (self.crit_bonus, other.crit_bonus);
+        bonuses.iter().all(|(mine, theirs)| mine <= theirs)
+            && my_crit <= their_crit
+            && (bonuses.iter().any(|(mine, theirs)| mine < theirs) || my_crit < their_crit)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 enum Slot {
     Head,
+    Torso,
+    Feet,
     RightHand,
     LeftHand,
 }
@@ -368,14 +939,100 @@ impl std::fmt::Display for Slot {
             Slot::LeftHand => write!(f, "left hand"),
             Slot::RightHand => write!(f, "right hand"),
             Slot::Head => write!(f, "head"),
+            Slot::Torso => write!(f, "body"),
+            Slot::Feet => write!(f, "feet"),
+        }
+    }
+}
+
+/// Picked when starting a run, it sets the player's starting stats and gear and how
+/// much each level up is worth
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Class {
+    Warrior,
+    Rogue,
+    Mage,
+}
+
+impl Class {
+    pub const ALL: [Class; 3] = [Class::Warrior, Class::Rogue, Class::Mage];
+
+    pub fn stats(self) -> &'static constants::classes::ClassStats {
+        use constants::classes;
+
+        match self {
+            Class::Warrior => &classes::WARRIOR,
+            Class::Rogue => &classes::ROGUE,
+            Class::Mage => &classes::MAGE,
+        }
+    }
+
+    pub fn max_mana(self, level: i32) -> i32 {
+        cmp::max(
+            0,
+            constants::spells::max_mana(level) + self.stats().mana_bonus,
+        )
+    }
+
+    /// What the class carries in on the first floor, the dagger everyone gets first
+    pub fn starting_items(self) -> Vec<GameObject> {
+        let equipped = |kind: Item| {
+            let mut item = create_item(kind, 0, 0);
+            if let Some(equipment) = item.equipment.as_mut() {
+                equipment.equipped = true;
+            }
+            item
+        };
+
+        let mut items = vec![equipped(Item::Dagger)];
+        items.extend(match self {
+            Class::Warrior => vec![equipped(Item::Sword)],
+            Class::Rogue => vec![equipped(Item::Bow), create_item(Item::Arrows, 0, 0)],
+            Class::Mage => vec![create_item(Item::Heal, 0, 0), create_item(Item::Heal, 0, 0)],
+        });
+        items
+    }
+}
+
+/// The scroll effects the player can also cast from memory, paying mana instead of
+/// using up a scroll
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Spell {
+    Lightning,
+    Confuse,
+    Fireball,
+}
+
+impl Spell {
+    pub const ALL: [Spell; 3] = [Spell::Lightning, Spell::Confuse, Spell::Fireball];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Spell::Lightning => "Lightning Bolt",
+            Spell::Confuse => "Confusion",
+            Spell::Fireball => "Fireball",
+        }
+    }
+
+    pub fn mana_cost(self) -> i32 {
+        use constants::spells;
+
+        match self {
+            Spell::Lightning => spells::LIGHTNING_COST,
+            Spell::Confuse => spells::CONFUSE_COST,
+            Spell::Fireball => spells::FIREBALL_COST,
         }
     }
 }
 
+/// Friendly townsfolk and fixtures, bumping into one uses it instead of attacking
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Enemies {
-    Orc,
-    Troll,
+enum Npc {
+    Healer,
+    Stash,
+    Shopkeeper,
+    // takes back the training of every level gained, for a price, so it can be chosen again
+    Shrine,
 }
 
 struct Transition {
@@ -401,6 +1058,97 @@ struct Tcod {
     panel: Offscreen,
     fov: FovMap,
     mouse: Mouse,
+    // the map tile drawn in the top left corner of the screen
+    camera: (i32, i32),
+    // tiles to pick out on the map, e.g. valid targets while aiming
+    highlighted: Vec<(i32, i32)>,
+    // tiles tinted while targeting to show how far the spell reaches and what it hits
+    overlay: Vec<((i32, i32), Color)>,
+    layout: Layout,
+    settings: Settings,
+    keybindings: Keybindings,
+    replay: Replay,
+}
+
+/// The game in a tcod window, played with the keyboard and mouse
+impl Frontend for Tcod {
+    fn fov(&self) -> &FovMap {
+        &self.fov
+    }
+
+    fn fov_mut(&mut self) -> &mut FovMap {
+        &mut self.fov
+    }
+
+    fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    fn replay_mut(&mut self) -> &mut Replay {
+        &mut self.replay
+    }
+
+    fn render(&mut self, objects: &Objects, game: &mut Game) {
+        draw_all(self, objects, game);
+    }
+
+    fn clear_map(&mut self) {
+        self.con.clear();
+    }
+
+    fn highlight(&mut self, tiles: Vec<(i32, i32)>) {
+        self.highlighted = tiles;
+    }
+
+    fn next_input(&mut self) -> Option<Input> {
+        if self.replay.is_playing() {
+            replay_input(self)
+        } else {
+            Some(read_input(self))
+        }
+    }
+
+    fn menu(&mut self, header: &str, options: &[&str], width: i32) -> Option<usize> {
+        show_menu(header, options, width, self)
+    }
+
+    fn pick_tile(
+        &mut self,
+        objects: &Objects,
+        game: &mut Game,
+        max_range: Option<f32>,
+        blast_radius: Option<i32>,
+    ) -> Option<(i32, i32)> {
+        aim_with_mouse(self, objects, game, max_range, blast_radius)
+    }
+
+    // the viewers wait on the keyboard, which a replay being watched leaves alone
+    fn look_around(&mut self, objects: &Objects, game: &mut Game) {
+        if !self.replay.is_playing() {
+            look_around(objects, game, self);
+        }
+    }
+
+    fn show_message_history(&mut self, log: &Messages) {
+        if !self.replay.is_playing() {
+            show_message_history(log, self);
+        }
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        if !self.replay.is_playing() {
+            let fullscreen = self.root.is_fullscreen();
+            self.root.set_fullscreen(!fullscreen);
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.root.window_closed()
+    }
 }
 
 trait MessageLog {
@@ -418,32 +1166,383 @@ struct Game {
     map: Map,
     log: Messages,
     inventory: Vec<GameObject>,
-    dungeon_level: u32,
+    dungeon_level: DungeonLevel,
+    turns: u32,
+    #[serde(skip)]
+    damage_popups: Vec<DamagePopup>,
+    // all gameplay randomness draws from here so a run can be replayed
+    seed: u32,
+    #[serde(default = "unseeded_rng")]
+    rng: RunRng,
+    floor_theme: FloorTheme,
+    // the player backed out of the level up menu, don't ask again until they take a turn
+    level_up_postponed: bool,
+    // set while the player's movement directions are scrambled
+    scrambled: Option<Scramble>,
+    performance: Performance,
+    // the monster definitions, read from their file whenever a game starts or loads
+    #[serde(skip)]
+    bestiary: Vec<MonsterDef>,
+    // the scripted items, read from their file along with the monsters
+    #[serde(skip)]
+    scripts: Vec<ScriptDef>,
+    class: Class,
+    mana: i32,
+    gold: i32,
+    // loot dropped by monsters that died since the last turn, the death callback can't
+    // reach the object list so it's put on the floor afterwards
+    #[serde(skip)]
+    drops: Vec<((i32, i32), Loot)>,
+    // what's left for sale in the current floor's shop
+    shop_stock: Vec<Item>,
+    // every other floor the player has been on, just as they left it
+    floors: Vec<StoredFloor>,
+    // the player made it out of the dungeon with the Amulet
+    won: bool,
+    // the day's shared dungeon, played at a fixed difficulty and scored on its own
+    daily: bool,
+    // the turn the game was last saved on without the player asking
+    #[serde(skip)]
+    autosaved_at: u32,
+    // for the morgue file: monsters slain, the deepest floor seen and what last hurt
+    // the player
+    kills: u32,
+    deepest: u32,
+    #[serde(skip)]
+    hurt_by: Option<String>,
+    // the rest of the path the player is walking after clicking on the map
+    #[serde(skip)]
+    travel: Vec<(i32, i32)>,
+    // structured record of the run, kept beside the message log rather than saved with it
+    #[serde(skip)]
+    events: EventLog,
+    // everything the player has done this run, so it can be watched again once it's over
+    #[serde(default)]
+    inputs: Vec<Input>,
+    #[serde(default)]
+    achievements: Vec<Achievement>,
+    // what the potions and scrolls look like this run, and which ones the player knows
+    #[serde(default)]
+    identification: Identification,
+    // what the town stash held when the run began, so a replay of it opens the same one
+    #[serde(default)]
+    starting_stash: Vec<GameObject>,
+    // the floor and spot a Scroll of Recall carried the player up to town from, where
+    // the next one read in town takes them back to
+    #[serde(default)]
+    recall_return: Option<(DungeonLevel, (i32, i32))>,
 }
 
-#[derive(Clone, Copy, Debug)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
+impl Game {
+    /// Hands an event to everything that follows the run: the statistics, the
+    /// achievements and the message log. Whatever they publish in turn is handled
+    /// before this returns, so messages still come out in the order things happened.
+    fn publish(&mut self, event: GameEvent) {
+        self.events.publish(event);
+        while let Some(event) = self.events.next_unhandled() {
+            count_event(&event, self);
+            unlock_achievements(&event, self);
+            log_event(&event, self);
+        }
+    }
 }
 
-impl Rect {
-    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
-        Rect {
-            x1: x,
-            y1: y,
-            x2: x + w,
-            y2: y + h,
+/// Keeps the run's statistics, as the morgue file and the scores report them
+fn count_event(event: &GameEvent, game: &mut Game) {
+    match *event {
+        GameEvent::EntityDied {
+            player: false,
+            by_player: true,
+            ..
+        } => game.kills += 1,
+        GameEvent::FloorChanged {
+            branch,
+            dungeon_level,
+        } => {
+            let depth = DungeonLevel {
+                branch,
+                depth: dungeon_level,
+            }
+            .absolute_depth();
+            game.deepest = cmp::max(game.deepest, depth);
         }
+        _ => {}
     }
+}
 
-    pub fn center(&self) -> (i32, i32) {
-        let center_x = (self.x1 + self.x2) / 2;
-        let center_y = (self.y1 + self.y2) / 2;
-
-        (center_x, center_y)
+fn unlock_achievements(event: &GameEvent, game: &mut Game) {
+    if let Some(achievement) = Achievement::earned_by(event, game.kills) {
+        if !game.achievements.contains(&achievement) {
+            game.achievements.push(achievement);
+            game.events
+                .publish(GameEvent::AchievementUnlocked { achievement });
+        }
+    }
+}
+
+/// Tells the player about the events worth a message
+fn log_event(event: &GameEvent, game: &mut Game) {
+    use constants::gui::menus::{inventory, level_up, next_level};
+
+    match event {
+        GameEvent::EntityDied { player: true, .. } => game.log.add("You died!", colors::RED),
+        GameEvent::EntityDied {
+            name,
+            enemy,
+            xp,
+            by_player,
+            ..
+        } => {
+            let flavor = enemy
+                .as_ref()
+                .and_then(|enemy| monsters::find(&game.bestiary, enemy))
+                .and_then(|definition| definition.death_flavor.clone())
+                .unwrap_or_else(|| constants::monsters::GENERIC_DEATH_FLAVOR.to_string());
+            // another monster's kill is only worth telling the player about
+            let message = if *by_player {
+                format!("{} {}! You gain {} experience points.", name, flavor, xp)
+            } else {
+                format!("{} {}!", name, flavor)
+            };
+            game.log.add(message, colors::ORANGE);
+        }
+        GameEvent::ItemPickedUp { item, quantity } => game.log.add(
+            inventory::create_pickup_message(item, *quantity),
+            colors::GREEN,
+        ),
+        GameEvent::LeveledUp { level } => game
+            .log
+            .add(level_up::create_log_message(*level), colors::YELLOW),
+        GameEvent::StairsDescended {
+            first_visit: false, ..
+        } => game
+            .log
+            .add(next_level::RETURN_LOG_MESSAGE, next_level::NEXT_LEVEL_COLOR),
+        GameEvent::StairsDescended { .. } => {
+            game.log
+                .add(next_level::REST_LOG_MESSAGE, next_level::REST_COLOR);
+            game.log.add(
+                next_level::NEXT_LEVEL_LOG_MESSAGE,
+                next_level::NEXT_LEVEL_COLOR,
+            );
+        }
+        GameEvent::AchievementUnlocked { achievement } => game.log.add(
+            constants::achievements::create_unlocked_message(achievement.name()),
+            constants::achievements::COLOR,
+        ),
+        _ => {}
+    }
+}
+
+/// Something a dying monster leaves on the floor
+#[derive(Clone, Copy, Debug)]
+enum Loot {
+    Gold(i32),
+    Item(Item),
+    // a fallen boss opens the stairs it was guarding. The death callback can't reach
+    // the stairs themselves, so this is left for `drop_loot` like the rest.
+    Unseal,
+}
+
+/// A floor the player has left, kept so it can be put back exactly as it was
+#[derive(Serialize, Deserialize)]
+struct StoredFloor {
+    dungeon_level: DungeonLevel,
+    map: Map,
+    // everything on the floor except the player
+    objects: Vec<GameObject>,
+    theme: FloorTheme,
+    shop_stock: Vec<Item>,
+}
+
+/// The run's RNG. It counts every number it hands out and is saved as just that
+/// count, so a reloaded game winds a fresh generator forward to exactly where the
+/// saved one stopped and rolls the same as if it had never been interrupted.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "RngState", into = "RngState")]
+struct RunRng {
+    seed: u32,
+    draws: u64,
+    rng: XorShiftRng,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RngState {
+    seed: u32,
+    draws: u64,
+}
+
+impl Rng for RunRng {
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+}
+
+impl From<RngState> for RunRng {
+    fn from(state: RngState) -> Self {
+        let mut rng = run_rng(state.seed);
+        for _ in 0..state.draws {
+            rng.next_u32();
+        }
+        rng
+    }
+}
+
+impl From<RunRng> for RngState {
+    fn from(rng: RunRng) -> Self {
+        RngState {
+            seed: rng.seed,
+            draws: rng.draws,
+        }
+    }
+}
+
+fn run_rng(seed: u32) -> RunRng {
+    RunRng {
+        seed,
+        draws: 0,
+        // xorshift must never start from an all-zero state
+        rng: XorShiftRng::from_seed([seed, 0, 0x9E37_79B9, 0x7F4A_7C15]),
+    }
+}
+
+/// Builds the RNG a floor is generated from. Each floor gets its own stream from the
+/// run's seed, so a seed always makes the same dungeon whatever order it's explored in.
+fn floor_rng(seed: u32, level: DungeonLevel) -> XorShiftRng {
+    XorShiftRng::from_seed([seed, level.depth, level.branch as u32, 0x2545_F491])
+}
+
+fn unseeded_rng() -> RunRng {
+    run_rng(0)
+}
+
+/// The player's movement keys turned by a fixed number of eighth turns, so every
+/// direction is swapped for another in a consistent way until it wears off
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Scramble {
+    steps: usize,
+    turns_left: i32,
+}
+
+impl Scramble {
+    pub fn apply(self, dx: i32, dy: i32) -> (i32, i32) {
+        // the eight directions in clockwise order, starting from north
+        const DIRECTIONS: [(i32, i32); 8] = [
+            (0, -1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+            (0, 1),
+            (-1, 1),
+            (-1, 0),
+            (-1, -1),
+        ];
+
+        match DIRECTIONS
+            .iter()
+            .position(|&direction| direction == (dx, dy))
+        {
+            Some(index) => DIRECTIONS[(index + self.steps) % DIRECTIONS.len()],
+            None => (dx, dy),
+        }
+    }
+}
+
+/// How the run is going for the player. The adaptive difficulty reads this once a
+/// floor is done and nudges the factor that later floors are generated with.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Performance {
+    // scales monster numbers and stats, always kept within the difficulty bounds
+    factor: f32,
+    // the lowest the player's hp fell on this floor, as a fraction of max hp
+    lowest_hp: f32,
+    floor_started: u32,
+}
+
+impl Performance {
+    pub fn new() -> Self {
+        Performance {
+            factor: 1.0,
+            lowest_hp: 1.0,
+            floor_started: 0,
+        }
+    }
+
+    pub fn observe_hp(&mut self, hp: i32, max_hp: i32) {
+        if max_hp > 0 {
+            self.lowest_hp = self.lowest_hp.min(hp as f32 / max_hp as f32);
+        }
+    }
+
+    pub fn start_floor(&mut self, turns: u32) {
+        self.lowest_hp = 1.0;
+        self.floor_started = turns;
+    }
+
+    /// Judges the floor that was just finished and starts tracking a fresh one.
+    /// Returns the way the difficulty moved, if it moved at all.
+    pub fn assess_floor(&mut self, turns: u32) -> Option<cmp::Ordering> {
+        use constants::difficulty::*;
+
+        let previous = self.factor;
+        let fast_clear = turns - self.floor_started <= FAST_CLEAR_TURNS;
+        if self.lowest_hp < NEAR_DEATH {
+            self.factor -= STEP_DOWN;
+        } else if self.lowest_hp >= CRUISING && fast_clear {
+            self.factor += STEP_UP;
+        }
+        self.factor = self.factor.clamp(MIN_FACTOR, MAX_FACTOR);
+        self.start_floor(turns);
+
+        self.factor
+            .partial_cmp(&previous)
+            .filter(|&ordering| ordering != cmp::Ordering::Equal)
+    }
+}
+
+/// A damage number shown over a tile for a few frames after a hit
+#[derive(Clone, Copy, Debug)]
+struct DamagePopup {
+    x: i32,
+    y: i32,
+    amount: i32,
+    critical: bool,
+    frames_left: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+
+        (center_x, center_y)
+    }
+
+    /// Number of floor tiles inside the room's walls
+    pub fn area(&self) -> i32 {
+        (self.x2 - self.x1 - 1) * (self.y2 - self.y1 - 1)
+    }
+
+    pub fn monster_cap(&self) -> i32 {
+        cmp::max(1, self.area() / ROOM_AREA_PER_MONSTER)
     }
 
     pub fn intersects_with(&self, other: &Rect) -> bool {
@@ -462,162 +1561,315 @@ enum PlayerAction {
 }
 
 fn handle_keys(
-    key: Key,
-    mut tcod: &mut Tcod,
+    input: Input,
+    ui: &mut dyn Frontend,
     mut game: &mut Game,
-    objects: &mut Vec<GameObject>,
+    objects: &mut Objects,
 ) -> PlayerAction {
     use PlayerAction::*;
 
     let player_alive = objects[PLAYER].alive;
+    // clicks and idle frames don't press anything, they only keep a walk going
+    let (pressed, action) = match input {
+        Input::Key(action) => (true, action),
+        _ => (false, None),
+    };
 
-    match (key, player_alive) {
-        (Key { code: Up, .. }, true) | (Key { code: NumPad8, .. }, true) => {
-            player_move_or_attack(0, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: Down, .. }, true) | (Key { code: NumPad2, .. }, true) => {
-            player_move_or_attack(0, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: Left, .. }, true) | (Key { code: NumPad4, .. }, true) => {
-            player_move_or_attack(-1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: Right, .. }, true) | (Key { code: NumPad6, .. }, true) => {
-            player_move_or_attack(1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: Home, .. }, true) | (Key { code: NumPad7, .. }, true) => {
-            player_move_or_attack(-1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: PageUp, .. }, true) | (Key { code: NumPad9, .. }, true) => {
-            player_move_or_attack(1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: End, .. }, true) | (Key { code: NumPad1, .. }, true) => {
-            player_move_or_attack(-1, 1, game, objects);
-            TookTurn
+    // a stunned player loses whatever they tried to do that turn
+    if player_alive
+        && objects[PLAYER].has_effect(EffectKind::Stun)
+        && pressed
+        && action != Some(Action::Exit)
+    {
+        game.travel.clear();
+        game.log
+            .add(constants::effects::STUNNED, colors::LIGHT_BLUE);
+        return TookTurn;
+    }
+
+    // any key press takes back control from a click on the map
+    if pressed {
+        game.travel.clear();
+    } else if !game.travel.is_empty() && player_alive {
+        return travel_step(game, objects, ui);
+    }
+
+    if let (Some(direction), true) = (action.and_then(Action::direction), player_alive) {
+        player_move_or_attack(direction.0, direction.1, game, objects, ui);
+        return TookTurn;
+    }
+
+    match (action, player_alive) {
+        (Some(Action::Rest), true) => {
+            TookTurn // do nothing, i.e. wait for the monster to come to you
         }
-        (Key { code: PageDown, .. }, true) | (Key { code: NumPad3, .. }, true) => {
-            player_move_or_attack(1, 1, game, objects);
-            TookTurn
+        (Some(Action::MessageHistory), _) => {
+            // look back through everything that's been logged, not just what fits the panel
+            ui.show_message_history(&game.log);
+            DidntTakeTurn
         }
-        (Key { code: NumPad5, .. }, true) => {
-            TookTurn // do nothing, i.e. wait for the monster to come to you
+        (Some(Action::PickUp), true) => {
+            // pick up an item, asking which one if there's a pile
+            pick_up_underfoot(objects, game, ui);
+            DidntTakeTurn
         }
-        (Key { printable: 'g', .. }, true) => {
-            // pick up an item
+        (Some(Action::Equip), true) => {
+            // pick up a piece of equipment and put it on straight away
             let item_id = objects
-                .iter()
-                .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
+                .find(|object| object.pos() == objects[PLAYER].pos() && object.equipment.is_some());
 
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, objects, game);
+            match item_id {
+                Some(item_id) => equip_from_ground(item_id, objects, game, ui),
+                None => game.log.add(
+                    constants::gui::menus::inventory::NOTHING_TO_EQUIP,
+                    colors::WHITE,
+                ),
             }
 
             DidntTakeTurn
         }
-        (Key { printable: 'i', .. }, true) => {
+        (Some(Action::Fire), true) => {
+            // shoot the wielded bow or crossbow
+            fire_launcher(objects, game, ui)
+        }
+        (Some(Action::Search), true) => {
+            // search any remains the player is standing on
+            let remains_id =
+                objects.find(|object| object.pos() == objects[PLAYER].pos() && object.searchable);
+
+            if let Some(remains_id) = remains_id {
+                search_remains(remains_id, objects, game);
+                TookTurn
+            } else {
+                game.log
+                    .add(constants::remains::NOTHING_TO_SEARCH, colors::WHITE);
+                DidntTakeTurn
+            }
+        }
+        (Some(Action::Inventory), true) => {
             // show the inventory: if an item is selected, use it
             let inventory_index = inventory_menu(
                 game,
                 "Press the key next to an item to use it, or any other to cancel. \n",
-                &mut tcod,
+                ui,
             );
 
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, objects, tcod, game)
+            match inventory_index {
+                Some(inventory_index) => use_item(inventory_index, objects, ui, game),
+                None => DidntTakeTurn,
             }
-
-            DidntTakeTurn
         }
-        (Key { printable: 'd', .. }, true) => {
+        (Some(Action::Drop), true) => {
             // show the inventory; if an item is selected, drop it
             let inventory_index = inventory_menu(
                 game,
                 "Press the key next to an item to drop it, or any other to cancel.\n",
-                &mut tcod,
+                ui,
             );
             if let Some(inventory_index) = inventory_index {
                 drop_item(inventory_index, &mut game, objects);
             }
             DidntTakeTurn
         }
-        (Key { printable: 'c', .. }, true) => {
+        (Some(Action::Look), true) => {
+            // move a cursor around the map to see what's there
+            ui.look_around(objects, game);
+            DidntTakeTurn
+        }
+        (Some(Action::Examine), true) => {
+            // show the inventory; if an item is selected, describe it without using it
+            let inventory_index = inventory_menu(
+                game,
+                "Press the key next to an item to examine it, or any other to cancel.\n",
+                ui,
+            );
+            if let Some(inventory_index) = inventory_index {
+                let description = describe_item(&game.inventory[inventory_index], game);
+                msgbox(&description, constants::gui::INVENTORY_WIDTH, ui);
+            }
+            DidntTakeTurn
+        }
+        (Some(Action::WaitTurns), true) => {
+            // wait out several turns in a row
+            use constants::gui::menus::wait;
+
+            let options: Vec<String> = wait::TURN_CHOICES
+                .iter()
+                .map(|&turns| wait::create_option(turns))
+                .collect();
+            if let Some(choice) = menu(wait::HEADER, &options, wait::WIDTH, ui) {
+                wait_turns(wait::TURN_CHOICES[choice], objects, ui, game);
+            }
+            DidntTakeTurn
+        }
+        (Some(Action::Cast), true) => {
+            // cast a spell
+            use constants::spells;
+
+            let options: Vec<String> = Spell::ALL
+                .iter()
+                .map(|spell| spells::create_option(spell.name(), spell.mana_cost()))
+                .collect();
+            let header = spells::create_header(game.mana);
+            match menu(&header, &options, spells::WIDTH, ui) {
+                Some(choice) => cast_spell(Spell::ALL[choice], objects, game, ui),
+                None => DidntTakeTurn,
+            }
+        }
+        (Some(Action::Character), true) => {
             // show character information
             let player = &objects[PLAYER];
             let level = player.level;
             let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
             if let Some(fighter) = player.fighter.as_ref() {
-                let msg = format!(
-                    "Character Information: \n* Level: {} \n* Experience: {} \n* Experience to level up: {} \n\n* Maximum HP: {} \n* Attack: {} \n* Defense: {} \n",
-                    level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game)
+                let mut msg = format!(
+                    "Character Information: \n* Class: {} \n* Level: {} \n* Experience: {} \n* Experience to level up: {} \n\n* Maximum HP: {} \n* Attack: {} \n* Defense: {} \n",
+                    game.class.stats().name, level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game)
                 );
-                msgbox(&msg, constants::gui::CHARACTER_SCREEN_WIDTH, &mut tcod);
+                if ui.settings().adaptive_difficulty {
+                    msg += &constants::difficulty::create_character_line(game.performance.factor);
+                }
+                msg += &constants::gui::menus::seed::create_character_line(game.seed);
+                msgbox(&msg, constants::gui::CHARACTER_SCREEN_WIDTH, ui);
             }
 
             DidntTakeTurn
         }
-        (Key { printable: '<', .. }, true) => {
-            // go down the stairs if the player is on them
-            let player_on_stairs = objects
-                .iter()
-                .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "stairs");
-
-            if player_on_stairs {
-                next_level(tcod, objects, game);
+        (Some(Action::Descend), true) => {
+            // go down the stairs if the player is on them, or into the branch they lead to.
+            // Once the Amulet is taken the only way left is up.
+            let going_down = player_on_stairs(objects) || branch_underfoot(objects).is_some();
+            if going_down && carrying_amulet(game) {
+                game.log
+                    .add(constants::amulet::ONLY_UP, constants::amulet::COLOR);
+            } else if stairs_locked(objects) {
+                game.log
+                    .add(constants::boss::SEALED, constants::boss::SLAIN_COLOR);
+            } else if player_on_stairs(objects) {
+                next_level(game.dungeon_level.below(), ui, objects, game);
+            } else if let Some(branch) = branch_underfoot(objects) {
+                next_level(DungeonLevel { branch, depth: 1 }, ui, objects, game);
             }
             DidntTakeTurn
         }
-        (
-            Key {
-                code: Enter,
-                alt: true,
-                ..
-            },
-            _,
-        ) => {
-            let fullscreen = tcod.root.is_fullscreen();
-            tcod.root.set_fullscreen(!fullscreen);
+        (Some(Action::Climb), true) => {
+            // climb back up to the floor above, or to town from the first floor
+            if player_on_up_stairs(objects) {
+                previous_level(ui, objects, game);
+            }
+            DidntTakeTurn
+        }
+        (Some(Action::Fullscreen), _) => {
+            ui.toggle_fullscreen();
             DidntTakeTurn
         }
-        (Key { code: Escape, .. }, _) => Exit,
+        (Some(Action::Exit), _) => Exit,
         _ => DidntTakeTurn,
     }
 }
 
-fn render_all(tcod: &mut Tcod, game_objects: &[GameObject], game: &mut Game) {
+/// Brings the player's field of view up to date, which monsters noticing the player
+/// goes by, then shows the game
+fn render_all(ui: &mut dyn Frontend, game_objects: &Objects, game: &mut Game) {
     // originally checked if user moved, but that caused a bug: every action was delayed by one turn. No observable adverse effects from removing the check.
     let player = &game_objects[PLAYER];
-    tcod.fov
-        .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+    let (light_walls, algorithm) = (ui.settings().fov_light_walls, ui.settings().fov_algorithm);
+    ui.fov_mut()
+        .compute_fov(player.x, player.y, TORCH_RADIUS, light_walls, algorithm);
+    ui.render(game_objects, game);
+}
 
+fn draw_all(tcod: &mut Tcod, game_objects: &Objects, game: &mut Game) {
     // Go through all tiles and set their background color
     for y in 0..constants::gui::MAP_HEIGHT {
         for x in 0..constants::gui::MAP_WIDTH {
             // check if it's a wall by checking if it blocks sight
             let visible = tcod.fov.is_in_fov(x, y);
             let is_wall = game.map[x as usize][y as usize].block_sight;
+            let cracked = game.map[x as usize][y as usize].cracked;
             let color = match (visible, is_wall) {
                 // Outside FOV
+                (false, true) if cracked => COLOR_DARK_CRACKED_WALL,
                 (false, true) => COLOR_DARK_WALL,
                 (false, false) => COLOR_DARK_GROUND,
                 // Inside FOV
+                (true, true) if cracked => COLOR_LIGHT_CRACKED_WALL,
                 (true, true) => COLOR_LIGHT_WALL,
                 (true, false) => COLOR_LIGHT_GROUND,
             };
 
-            let explored = &mut game.map[x as usize][y as usize].explored;
-
+            let tile = &mut game.map[x as usize][y as usize];
             if visible {
-                *explored = true;
+                tile.explored = true;
+            }
+            if !tile.explored {
+                continue;
+            }
+
+            match tcod.settings.glyphs {
+                GlyphSet::Ascii => {
+                    tcod.con
+                        .set_char_background(x, y, color, BackgroundFlag::Set);
+                }
+                // walls become lines drawn over the floor color
+                GlyphSet::Symbols if is_wall => {
+                    let ground = if visible {
+                        COLOR_LIGHT_GROUND
+                    } else {
+                        COLOR_DARK_GROUND
+                    };
+                    let glyph = map::wall_glyph(&game.map, x, y);
+                    tcod.con.put_char_ex(x, y, glyph, color, ground);
+                }
+                GlyphSet::Symbols => {
+                    tcod.con.put_char_ex(x, y, ' ', color, color);
+                }
             }
 
-            if *explored {
+            if game.map[x as usize][y as usize].lock.is_some() {
+                tcod.con
+                    .set_default_foreground(constants::locks::DOOR_COLOR);
                 tcod.con
-                    .set_char_background(x, y, color, BackgroundFlag::Set);
+                    .put_char(x, y, constants::locks::DOOR_SYMBOL, BackgroundFlag::None);
+            }
+
+            if let Some(trap) = game.map[x as usize][y as usize].trap {
+                if trap.revealed {
+                    tcod.con
+                        .set_default_foreground(constants::traps::color(trap.kind));
+                    tcod.con
+                        .put_char(x, y, constants::traps::SYMBOL, BackgroundFlag::None);
+                }
+            }
+        }
+    }
+
+    for &((x, y), color) in &tcod.overlay {
+        tcod.con
+            .set_char_background(x, y, color, BackgroundFlag::Set);
+    }
+
+    for &(x, y) in &tcod.highlighted {
+        tcod.con
+            .set_char_background(x, y, constants::gui::HIGHLIGHT_COLOR, BackgroundFlag::Set);
+    }
+
+    // Draw a faded marker where monsters that slipped out of view were last seen
+    if constants::gui::SHOW_LAST_SEEN {
+        for object in game_objects.iter().filter(|obj| obj.ai.is_some()) {
+            if let Some((x, y)) = object.last_seen {
+                if !tcod.fov.is_in_fov(object.x, object.y)
+                    && game.map[x as usize][y as usize].explored
+                {
+                    tcod.con
+                        .set_default_foreground(object.color * constants::gui::LAST_SEEN_FADE);
+                    tcod.con.put_char(
+                        x,
+                        y,
+                        object.glyph(tcod.settings.glyphs),
+                        BackgroundFlag::None,
+                    );
+                }
             }
         }
     }
@@ -634,31 +1886,76 @@ fn render_all(tcod: &mut Tcod, game_objects: &[GameObject], game: &mut Game) {
     to_draw.sort_by(|item1, item2| item1.blocks.cmp(&item2.blocks));
     // Draw the items in the list
     for object in to_draw {
-        // only render if in FOV
-        object.draw(&mut tcod.con);
+        // remembered objects outside FOV are drawn darker, like the tiles beneath them
+        let dimmed = !tcod.fov.is_in_fov(object.x, object.y);
+        let color = match game.identification.appearance(&object.name) {
+            Some(appearance) if object.item.is_some() => appearance.color,
+            _ if tcod.settings.threat_tint => object.threat_color(&game_objects[PLAYER], game),
+            _ => object.status_color(),
+        };
+        if object.item.is_some() && items_at(object.pos(), game_objects).len() > 1 {
+            // a pile is drawn as one marker, otherwise whichever item came last hides the rest
+            object.draw_as(
+                &mut tcod.con,
+                constants::pile::SYMBOL,
+                constants::pile::COLOR,
+                dimmed,
+            );
+        } else {
+            object.draw(&mut tcod.con, color, dimmed, tcod.settings.glyphs);
+        }
     }
 
-    // Blit onto the actual screen
+    // Blit the part of the map around the player onto the actual screen
+    tcod.camera = camera_origin(game_objects[PLAYER].pos());
+    let (camera_x, camera_y) = tcod.camera;
     blit(
         &tcod.con,
-        (0, 0),
-        (constants::gui::SCREEN_WIDTH, constants::gui::SCREEN_HEIGHT),
+        (camera_x, camera_y),
+        (constants::gui::VIEW_WIDTH, constants::gui::VIEW_HEIGHT),
         &mut tcod.root,
         (0, 0),
         1.0,
         1.0,
     );
 
+    // Damage numbers go straight onto the root console so the next blit wipes them
+    for popup in &mut game.damage_popups {
+        if tcod.fov.is_in_fov(popup.x, popup.y) {
+            let color = if popup.critical {
+                constants::gui::CRITICAL_POPUP_COLOR
+            } else {
+                constants::gui::DAMAGE_POPUP_COLOR
+            };
+            tcod.root.set_default_foreground(color);
+            let y = if popup.y > 0 {
+                popup.y - 1
+            } else {
+                popup.y + 1
+            };
+            tcod.root.print_ex(
+                popup.x - camera_x,
+                y - camera_y,
+                BackgroundFlag::None,
+                TextAlignment::Center,
+                popup.amount.to_string(),
+            );
+        }
+        popup.frames_left -= 1;
+    }
+    game.damage_popups.retain(|popup| popup.frames_left > 0);
+
     tcod.panel.set_default_background(colors::BLACK);
     tcod.panel.clear();
 
     // Print the game messages, one line at a time
-    let mut y = constants::gui::MSG_HEIGHT as i32;
+    let layout = tcod.layout;
+    let mut y = layout.msg_height;
 
     for &(ref msg, color) in game.log.iter().rev() {
-        let msg_height =
-            tcod.panel
-                .get_height_rect(constants::gui::MSG_X, y, constants::gui::MSG_WIDTH, 0, msg);
+        let msg_height = tcod
+            .panel
+            .get_height_rect(layout.msg_x, y, layout.msg_width, 0, msg);
         y -= msg_height;
 
         if y < 0 {
@@ -667,7 +1964,7 @@ fn render_all(tcod: &mut Tcod, game_objects: &[GameObject], game: &mut Game) {
 
         tcod.panel.set_default_foreground(color);
         tcod.panel
-            .print_rect(constants::gui::MSG_X, y, constants::gui::MSG_WIDTH, 0, msg);
+            .print_rect(layout.msg_x, y, layout.msg_width, 0, msg);
     }
 
     // Show the players stats
@@ -687,57 +1984,147 @@ fn render_all(tcod: &mut Tcod, game_objects: &[GameObject], game: &mut Game) {
         colors::DARKER_RED,
     );
 
+    render_bar(
+        &mut tcod.panel,
+        1,
+        constants::gui::MANA_Y,
+        constants::gui::BAR_WIDTH,
+        "MP",
+        game.mana,
+        game.class.max_mana(player.level),
+        colors::LIGHT_BLUE,
+        colors::DARKER_BLUE,
+    );
+
     tcod.panel.print_ex(
         1,
         3,
         BackgroundFlag::None,
         TextAlignment::Left,
-        format!("Dungeon Level: {}", game.dungeon_level),
+        if game.dungeon_level.is_town() {
+            constants::town::NAME.to_string()
+        } else {
+            game.dungeon_level.label()
+        },
     );
 
-    // Display the names of the objects under th mouse
-    tcod.panel.set_default_foreground(colors::LIGHT_GREY);
+    tcod.panel.set_default_foreground(constants::gold::COLOR);
     tcod.panel.print_ex(
         1,
-        0,
+        constants::gui::GOLD_Y,
         BackgroundFlag::None,
         TextAlignment::Left,
-        get_names_under_mouse(tcod.mouse, game_objects, &tcod.fov),
+        constants::gold::create_counter(game.gold),
     );
 
+    render_statuses(&mut tcod.panel, &player_statuses(game_objects, game));
+
+    // Display the names of the objects under th mouse, or while watching a replay how
+    // to control it
+    if tcod.replay.is_playing() {
+        tcod.panel.set_default_foreground(constants::replay::COLOR);
+        tcod.panel.print_ex(
+            1,
+            0,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            constants::replay::create_status(
+                tcod.replay.speed(),
+                constants::replay::DELAYS_MS.len(),
+            ),
+        );
+    } else {
+        tcod.panel.set_default_foreground(colors::LIGHT_GREY);
+        tcod.panel.print_ex(
+            1,
+            0,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            get_names_under_mouse(mouse_tile(tcod), game_objects, &tcod.fov, game),
+        );
+    }
+
     blit(
         &tcod.panel,
         (0, 0),
-        (constants::gui::SCREEN_WIDTH, constants::gui::PANEL_HEIGHT),
+        (constants::gui::SCREEN_WIDTH, layout.panel_height),
         &mut tcod.root,
-        (0, constants::gui::PANEL_Y),
+        (0, layout.panel_y),
         1.0,
         1.0,
     );
 
     // Make the console actually visible
     tcod.root.flush();
+
+    // Clear the GameObjects once their position is moved to the visible screen.
+    // If we do this earlier or later we won't erase the last pos.
+    for object in game_objects.iter() {
+        object.clear(&mut tcod.con);
+    }
 }
 
-fn create_map(objects: &mut Vec<GameObject>, level: u32) -> Map {
+/// Remember where each monster in view is standing, and forget the spot once the
+/// player can see that it's been vacated.
+fn remember_monsters(objects: &mut Objects, fov_map: &FovMap) {
+    for object in objects
+        .iter_mut()
+        .filter(|obj| obj.ai.is_some() && obj.npc.is_none())
+    {
+        if fov_map.is_in_fov(object.x, object.y) {
+            object.last_seen = Some(object.pos());
+        } else if let Some((x, y)) = object.last_seen {
+            if fov_map.is_in_fov(x, y) {
+                object.last_seen = None;
+            }
+        }
+    }
+}
+
+/// What generation put on a floor, for anything that wants to talk about it afterwards
+struct FloorReport {
+    // the xp of every monster spawned, a rough measure of how dangerous the floor is
+    danger: i32,
+    // what the floor's shop has for sale, empty if there is no shop
+    shop_stock: Vec<Item>,
+}
+
+fn create_map(
+    objects: &mut Objects,
+    dungeon_level: DungeonLevel,
+    theme: FloorTheme,
+    difficulty: f32,
+    bestiary: &[MonsterDef],
+    scripts: &[ScriptDef],
+    rng: &mut XorShiftRng,
+) -> (Map, FloorReport) {
+    if dungeon_level.is_boss_floor() {
+        let boss_number = dungeon_level.depth / constants::boss::EVERY;
+        // a monsters file without any bosses just gets an ordinary floor here
+        if let Some(boss) = monsters::boss_for(bestiary, boss_number) {
+            return create_arena(objects, boss, difficulty);
+        }
+    }
+
+    // everything spawned goes by how far below town the floor is, whichever branch it's in
+    let level = dungeon_level.absolute_depth();
+    let branch = dungeon_level.branch;
     let mut map = vec![
         vec![Tile::wall(); constants::gui::MAP_HEIGHT as usize];
         constants::gui::MAP_WIDTH as usize
     ];
     let mut rooms = vec![];
 
-    // Player is the first element, remove everything else.
-    // NOTE: works only when the player is the first object!
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
+    // the player carries over to the new floor, everything else goes
+    objects.drain_except(PLAYER);
 
     for _ in 0..MAX_ROOMS {
         // Random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
 
-        let x = rand::thread_rng().gen_range(0, constants::gui::MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, constants::gui::MAP_HEIGHT - h);
+        let x = rng.gen_range(0, constants::gui::MAP_WIDTH - w);
+        let y = rng.gen_range(0, constants::gui::MAP_HEIGHT - h);
 
         let new_room = Rect::new(x, y, w, h);
         let failed = rooms
@@ -747,7 +2134,8 @@ fn create_map(objects: &mut Vec<GameObject>, level: u32) -> Map {
         if !failed {
             // There are no intersections so we can process this
             create_room(new_room, &mut map);
-            place_objects(new_room, &map, objects, level);
+            place_items(new_room, &map, objects, level, branch, scripts, rng);
+            place_gold(new_room, &map, objects, level, rng);
 
             let (center_x, center_y) = new_room.center();
 
@@ -756,7 +2144,7 @@ fn create_map(objects: &mut Vec<GameObject>, level: u32) -> Map {
             } else {
                 let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
 
-                if rand::random() {
+                if rng.gen() {
                     create_h_tunnel(prev_x, center_x, prev_y, &mut map);
                     create_v_tunnel(prev_y, center_y, center_x, &mut map);
                 } else {
@@ -769,1149 +2157,5558 @@ fn create_map(objects: &mut Vec<GameObject>, level: u32) -> Map {
         }
     }
 
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    let mut stairs = GameObject::new(
-        last_room_x,
-        last_room_y,
-        '<',
-        "stairs",
-        colors::WHITE,
-        false,
+    // the shop is kept free of monsters, whatever else generation put there stays
+    let shop_room = map::shops::choose_room(&rooms, level, rng);
+    let monster_rooms: Vec<Rect> = rooms
+        .iter()
+        .enumerate()
+        .filter(|&(room_id, _)| Some(room_id) != shop_room)
+        .map(|(_, &room)| room)
+        .collect();
+    let danger = place_monsters(
+        &monster_rooms,
+        objects,
+        level,
+        theme,
+        difficulty,
+        bestiary,
+        rng,
     );
+    place_traps(&rooms, shop_room, &mut map, level, rng);
+
+    let mut shop_stock = vec![];
+    if let Some(room_id) = shop_room {
+        use constants::shop;
+
+        let (x, y) = map::shops::counter(rooms[room_id]);
+        objects.retain(|object| object.pos() != (x, y));
+        objects.insert(
+            GameObject::builder(x, y, shop::SYMBOL, shop::NAME, shop::COLOR, true)
+                .with_ai(Ai::Shopkeeper { home: (x, y) })
+                .with_npc(Npc::Shopkeeper)
+                .build(),
+        );
+        shop_stock = map::shops::roll_stock(level, branch, rng);
+    }
 
-    stairs.always_visible = true;
-    objects.push(stairs);
-
-    map
-}
+    crack_walls(&mut map, rng);
 
-fn create_room(room: Rect, map: &mut Map) {
-    // These ranges need to be exclusive on both sides, so x+1..x works just fine
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
-        }
+    // the bottom floor of a branch is a dead end, and the bottom of the main dungeon
+    // is where the Amulet lies
+    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+    if dungeon_level.is_final_floor() {
+        objects.insert(create_item(Item::Amulet, last_room_x, last_room_y));
+    } else if dungeon_level.has_stairs_down() {
+        let stairs = GameObject::builder(
+            last_room_x,
+            last_room_y,
+            '<',
+            "stairs",
+            colors::WHITE,
+            false,
+        )
+        .always_visible()
+        .build();
+        objects.insert(stairs);
     }
-}
+    place_branch_stairs(&rooms, shop_room, objects, dungeon_level, rng);
 
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    for x in cmp::min(x1, x2)..=cmp::max(x1, x2) {
-        map[x as usize][y as usize] = Tile::empty();
+    // every floor leads back up from where the player arrives, the first one to town
+    let (x, y) = objects[PLAYER].pos();
+    let up_stairs = GameObject::builder(
+        x,
+        y,
+        '>',
+        constants::gui::menus::next_level::UP_STAIRS_NAME,
+        colors::WHITE,
+        false,
+    )
+    .always_visible()
+    .build();
+    objects.insert(up_stairs);
+
+    // the room chain should always connect, but never strand the player if it somehow doesn't
+    let start = objects[PLAYER].pos();
+    if map::find_path(&map, start, (last_room_x, last_room_y)).is_none() {
+        create_h_tunnel(start.0, last_room_x, start.1, &mut map);
+        create_v_tunnel(start.1, last_room_y, last_room_x, &mut map);
     }
+
+    lock_vault(&rooms, shop_room, &mut map, objects, dungeon_level, rng);
+
+    (map, FloorReport { danger, shop_stock })
 }
 
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    for y in cmp::min(y1, y2)..=cmp::max(y1, y2) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
-}
-
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<GameObject>, level: u32) {
-    let max_monsters = from_dungeon_level(
-        &[
-            Transition::new(1, 2),
-            Transition::new(4, 3),
-            Transition::new(6, 5),
-        ],
-        level,
-    );
-
-    let troll_chance = from_dungeon_level(
-        &[
-            Transition::new(3, 15),
-            Transition::new(5, 30),
-            Transition::new(7, 60),
-        ],
-        level,
-    );
-
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
+/// Builds the town from its fixed layout. Nothing hostile lives here, so there are
+/// no monster or item spawns, just the townsfolk and the stairs down.
+/// Lays out the hand-made boss arena, with the boss standing between the player and
+/// the stairs down
+fn create_arena(
+    objects: &mut Objects,
+    boss_kind: &MonsterDef,
+    difficulty: f32,
+) -> (Map, FloorReport) {
+    use constants::boss;
+    use constants::gui::menus::next_level;
 
-    for _ in 0..num_monsters {
-        // Choose Random spot
-        let mut x: i32;
-        let mut y: i32;
-        loop {
-            x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-            y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+    let mut map = vec![
+        vec![Tile::wall(); constants::gui::MAP_HEIGHT as usize];
+        constants::gui::MAP_WIDTH as usize
+    ];
 
-            if !objects.iter().any(|item| item.x == x && item.y == y) {
-                break;
-            };
-        }
+    objects.drain_except(PLAYER);
 
-        let monster_chances = &mut [
-            Weighted {
-                weight: 80,
-                item: Enemies::Orc,
-            },
-            Weighted {
-                weight: troll_chance,
-                item: Enemies::Troll,
-            },
-        ];
+    // center the layout on the map
+    let offset_x = (constants::gui::MAP_WIDTH - boss::LAYOUT[0].len() as i32) / 2;
+    let offset_y = (constants::gui::MAP_HEIGHT - boss::LAYOUT.len() as i32) / 2;
+    let mut danger = 0;
 
-        let monster_choice = WeightedChoice::new(monster_chances);
-
-        let mut monster = match monster_choice.ind_sample(&mut rand::thread_rng()) {
-            Enemies::Orc => {
-                let mut orc = GameObject::new(x, y, 'o', "Orc", colors::DESATURATED_GREEN, true);
-                orc.fighter = Some(Fighter {
-                    base_max_hp: 20,
-                    hp: 20,
-                    base_defense: 0,
-                    base_power: 4,
-                    on_death: DeathCallback::Monster,
-                    xp: 35,
-                });
-                orc.ai = Some(Ai::Basic);
-                orc
-            }
-            Enemies::Troll => {
-                let mut troll = GameObject::new(x, y, 'T', "Troll", colors::DARKER_GREEN, true);
-                troll.fighter = Some(Fighter {
-                    base_max_hp: 30,
-                    hp: 30,
-                    base_defense: 2,
-                    base_power: 8,
-                    on_death: DeathCallback::Monster,
-                    xp: 100,
-                });
-                troll.ai = Some(Ai::Basic);
-                troll
+    for (row, line) in boss::LAYOUT.iter().enumerate() {
+        for (column, symbol) in line.chars().enumerate() {
+            let x = offset_x + column as i32;
+            let y = offset_y + row as i32;
+            if symbol != '#' {
+                map[x as usize][y as usize] = Tile::empty();
             }
-        };
 
-        monster.alive = true;
-        objects.push(monster);
+            match symbol {
+                '@' => {
+                    objects[PLAYER].set_pos(x, y);
+                    objects.insert(
+                        GameObject::builder(
+                            x,
+                            y,
+                            '>',
+                            next_level::UP_STAIRS_NAME,
+                            colors::WHITE,
+                            false,
+                        )
+                        .always_visible()
+                        .build(),
+                    );
+                }
+                '<' => {
+                    objects.insert(
+                        GameObject::builder(x, y, '<', "stairs", colors::WHITE, false)
+                            .always_visible()
+                            .locked()
+                            .build(),
+                    );
+                }
+                'B' => {
+                    let mut monster = create_monster(boss_kind, x, y);
+                    if let Some(fighter) = monster.fighter.as_mut() {
+                        let scale = |stat: i32| (stat as f32 * difficulty).round() as i32;
+                        fighter.base_max_hp = scale(fighter.base_max_hp);
+                        fighter.hp = fighter.base_max_hp;
+                        fighter.base_power = scale(fighter.base_power);
+                        danger = fighter.xp;
+                    }
+                    objects.insert(monster);
+                }
+                _ => {}
+            }
+        }
     }
 
-    let max_items = from_dungeon_level(&[Transition::new(1, 1), Transition::new(4, 2)], level);
+    let report = FloorReport {
+        danger,
+        shop_stock: vec![],
+    };
+    (map, report)
+}
 
-    let item_chances = &mut [
-        Weighted {
-            weight: 35,
-            item: Item::Heal,
-        },
-        Weighted {
-            weight: from_dungeon_level(&[Transition::new(4, 25)], level),
-            item: Item::Lightning,
-        },
-        Weighted {
-            weight: from_dungeon_level(&[Transition::new(6, 25)], level),
-            item: Item::Fireball,
-        },
-        Weighted {
-            weight: from_dungeon_level(&[Transition::new(2, 10)], level),
-            item: Item::Confuse,
-        },
-        Weighted {
-            weight: from_dungeon_level(&[Transition::new(4, 5)], level),
-            item: Item::Sword,
-        },
-        Weighted {
-            weight: from_dungeon_level(&[Transition::new(8, 15)], level),
-            item: Item::Shield,
-        },
+fn create_town(objects: &mut Objects, rng: &mut XorShiftRng) -> (Map, FloorReport) {
+    use constants::{shop, town};
+
+    let mut map = vec![
+        vec![Tile::wall(); constants::gui::MAP_HEIGHT as usize];
+        constants::gui::MAP_WIDTH as usize
     ];
 
-    let num_items = rand::thread_rng().gen_range(0, max_items + 1);
+    objects.drain_except(PLAYER);
 
-    for _ in 0..num_items {
-        // choose random spot for this item
-        let mut x: i32;
-        let mut y: i32;
-        loop {
-            x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-            y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+    // center the layout on the map
+    let offset_x = (constants::gui::MAP_WIDTH - town::LAYOUT[0].len() as i32) / 2;
+    let offset_y = (constants::gui::MAP_HEIGHT - town::LAYOUT.len() as i32) / 2;
 
-            if !objects.iter().any(|item| item.x == x && item.y == y) {
-                break;
+    for (row, line) in town::LAYOUT.iter().enumerate() {
+        for (column, symbol) in line.chars().enumerate() {
+            let x = offset_x + column as i32;
+            let y = offset_y + row as i32;
+            if symbol != '#' {
+                map[x as usize][y as usize] = Tile::empty();
             }
-        }
-
-        let item_choice = WeightedChoice::new(item_chances);
 
-        if !is_blocked(x, y, map, objects) {
-            let mut item = match item_choice.ind_sample(&mut rand::thread_rng()) {
-                Item::Heal => {
-                    let mut object =
-                        GameObject::new(x, y, '!', "Healing Potion", colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    let mut object = GameObject::new(
-                        x,
-                        y,
-                        '#',
-                        "Scroll of Lightning Bolt",
-                        colors::LIGHT_YELLOW,
-                        false,
+            match symbol {
+                '@' => objects[PLAYER].set_pos(x, y),
+                '<' => {
+                    objects.insert(
+                        GameObject::builder(x, y, '<', "stairs", colors::WHITE, false)
+                            .always_visible()
+                            .build(),
                     );
-                    object.item = Some(Item::Lightning);
-                    object
                 }
-                Item::Fireball => {
-                    let mut object = GameObject::new(
-                        x,
-                        y,
-                        '#',
-                        "Scroll of Fireball",
-                        colors::LIGHT_YELLOW,
-                        false,
+                'S' => {
+                    objects.insert(
+                        GameObject::builder(x, y, '=', town::stash::NAME, town::stash::COLOR, true)
+                            .with_npc(Npc::Stash)
+                            .always_visible()
+                            .build(),
                     );
-                    object.item = Some(Item::Fireball);
-                    object
                 }
-                Item::Confuse => {
-                    let mut object = GameObject::new(
-                        x,
-                        y,
-                        '#',
-                        "Scroll of Confusion",
-                        colors::LIGHT_YELLOW,
-                        false,
+                'H' => {
+                    objects.insert(
+                        GameObject::builder(x, y, 'H', town::HEALER_NAME, town::HEALER_COLOR, true)
+                            .with_npc(Npc::Healer)
+                            .always_visible()
+                            .build(),
                     );
-                    object.item = Some(Item::Confuse);
-                    object
                 }
-                Item::Sword => {
-                    let mut object = GameObject::new(x, y, '/', "Sword", colors::SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::RightHand,
-                        power_bonus: 3,
-                        defense_bonus: 0,
-                        hp_bonus: 0,
-                    });
-                    object
+                'A' => {
+                    objects.insert(
+                        GameObject::builder(
+                            x,
+                            y,
+                            town::shrine::SYMBOL,
+                            town::shrine::NAME,
+                            town::shrine::COLOR,
+                            true,
+                        )
+                        .with_npc(Npc::Shrine)
+                        .always_visible()
+                        .build(),
+                    );
                 }
-                Item::Shield => {
-                    let mut object =
-                        GameObject::new(x, y, '[', "Shield", colors::DARKER_ORANGE, false);
-                    object.item = Some(Item::Shield);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::LeftHand,
-                        hp_bonus: 0,
-                        defense_bonus: 1,
-                        power_bonus: 0,
-                    });
-                    object
+                '$' => {
+                    objects.insert(
+                        GameObject::builder(x, y, shop::SYMBOL, shop::NAME, shop::COLOR, true)
+                            .with_ai(Ai::Shopkeeper { home: (x, y) })
+                            .with_npc(Npc::Shopkeeper)
+                            .always_visible()
+                            .build(),
+                    );
                 }
-            };
-            item.always_visible = true;
-            objects.push(item);
+                _ => {}
+            }
         }
     }
+
+    let report = FloorReport {
+        danger: 0,
+        shop_stock: map::shops::roll_stock(town::SHOP_STOCK_DEPTH, Branch::Main, rng),
+    };
+    (map, report)
 }
 
-fn is_blocked(x: i32, y: i32, map: &Map, objects: &[GameObject]) -> bool {
-    if map[x as usize][y as usize].blocked {
-        return true;
+fn is_map_boundary(x: i32, y: i32) -> bool {
+    x <= 0 || y <= 0 || x >= constants::gui::MAP_WIDTH - 1 || y >= constants::gui::MAP_HEIGHT - 1
+}
+
+/// Weakens a few of the walls bordering open floor so they can be dug through
+fn crack_walls(map: &mut Map, rng: &mut XorShiftRng) {
+    for x in 0..constants::gui::MAP_WIDTH {
+        for y in 0..constants::gui::MAP_HEIGHT {
+            if is_map_boundary(x, y) || !map[x as usize][y as usize].blocked {
+                continue;
+            }
+
+            let borders_floor = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .any(|&(dx, dy)| !map[(x + dx) as usize][(y + dy) as usize].blocked);
+
+            if borders_floor && rng.gen::<f32>() < CRACKED_WALL_CHANCE {
+                map[x as usize][y as usize] = Tile::cracked_wall();
+            }
+        }
     }
+}
 
-    objects
-        .iter()
-        .any(|object| object.blocks && object.pos() == (x, y))
+fn create_room(room: Rect, map: &mut Map) {
+    // These ranges need to be exclusive on both sides, so x+1..x works just fine
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
 }
 
-fn move_by(id: usize, dx: i32, dy: i32, game: &mut Game, objects: &mut [GameObject]) {
-    let (x, y) = objects[id].pos();
+fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    for x in cmp::min(x1, x2)..=cmp::max(x1, x2) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
 
-    if !is_blocked(x + dx, y + dy, &game.map, objects) {
-        objects[id].set_pos(x + dx, y + dy);
+fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    for y in cmp::min(y1, y2)..=cmp::max(y1, y2) {
+        map[x as usize][y as usize] = Tile::empty();
     }
 }
 
-fn move_towards(
-    id: usize,
-    target_x: i32,
-    target_y: i32,
-    mut game: &mut Game,
-    objects: &mut [GameObject],
-) {
-    // Vector from this object to the target and distance
-    let dx = target_x - objects[id].x;
-    let dy = target_y - objects[id].y;
-    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+/// Spreads a floor-wide monster budget over the rooms, never giving a room more
+/// monsters than its area allows, so packs can't all pile into one small room.
+/// The difficulty factor scales the budget and each monster's stats.
+/// Returns the combined xp of everything spawned.
+fn place_monsters(
+    rooms: &[Rect],
+    objects: &mut Objects,
+    level: u32,
+    theme: FloorTheme,
+    difficulty: f32,
+    bestiary: &[MonsterDef],
+    rng: &mut XorShiftRng,
+) -> i32 {
+    use map::spawn_tables;
+
+    let budget = (spawn_tables::monster_budget(level) as f32 * difficulty).round() as u32;
+    let mut monster_chances = spawn_tables::monster_table(level, theme, bestiary);
+    // a monsters file with nothing that spawns this deep just leaves the floor empty
+    if monster_chances.iter().all(|chance| chance.weight == 0) {
+        return 0;
+    }
+    let monster_choice = WeightedChoice::new(&mut monster_chances);
 
-    // Normalize it to length 1 (preserving direction), then round it and convert to int so the movement is restricted to the grid
-    let dx = (dx as f32 / distance).round() as i32;
-    let dy = (dy as f32 / distance).round() as i32;
-    move_by(id, dx, dy, &mut game, objects);
-}
+    let num_monsters = rng.gen_range(budget / 2, budget + 1);
+    let mut room_counts = vec![0; rooms.len()];
+    let mut danger = 0;
 
-fn player_move_or_attack(dx: i32, dy: i32, mut game: &mut Game, objects: &mut [GameObject]) {
-    let x = objects[PLAYER].x + dx;
-    let y = objects[PLAYER].y + dy;
+    for _ in 0..num_monsters {
+        let open_rooms: Vec<usize> = (0..rooms.len())
+            .filter(|&room_id| room_counts[room_id] < rooms[room_id].monster_cap())
+            .collect();
 
-    let target_id = objects
-        .iter()
-        .position(|object| object.fighter.is_some() && object.pos() == (x, y));
+        if open_rooms.is_empty() {
+            break;
+        }
 
-    match target_id {
-        Some(target_id) => {
-            let (player, target) = mut_two(PLAYER, target_id, objects);
-            player.attack(target, &mut game);
+        let room_id = open_rooms[rng.gen_range(0, open_rooms.len())];
+        room_counts[room_id] += 1;
+        let room = rooms[room_id];
+
+        // Choose Random spot
+        let mut x: i32;
+        let mut y: i32;
+        loop {
+            x = rng.gen_range(room.x1 + 1, room.x2);
+            y = rng.gen_range(room.y1 + 1, room.y2);
+
+            if !objects.iter().any(|item| item.x == x && item.y == y) {
+                break;
+            };
+        }
+
+        let kind = &bestiary[monster_choice.ind_sample(rng)];
+        let mut monster = create_monster(kind, x, y);
+        if let Some(fighter) = monster.fighter.as_mut() {
+            let scale = |stat: i32| (stat as f32 * difficulty).round() as i32;
+            fighter.base_max_hp = scale(fighter.base_max_hp);
+            fighter.hp = fighter.base_max_hp;
+            fighter.base_power = scale(fighter.base_power);
         }
-        None => move_by(PLAYER, dx, dy, &mut game, objects),
+        danger += monster.fighter.map_or(0, |f| f.xp);
+        objects.insert(monster);
     }
+
+    danger
 }
 
-fn pick_item_up(object_id: usize, objects: &mut Vec<GameObject>, game: &mut Game) {
-    if game.inventory.len() >= 26 {
-        game.log.add(
-            format!(
-                "Your inventory is full, cannot pick up {}",
-                objects[object_id].name
-            ),
-            colors::RED,
-        );
+fn create_monster(kind: &MonsterDef, x: i32, y: i32) -> GameObject {
+    let ai = match kind.ai {
+        MonsterAi::Basic => Ai::Basic,
+        MonsterAi::Idle => Ai::Idle { home: (x, y) },
+    };
+    let on_death = if kind.boss {
+        DeathCallback::Boss
     } else {
-        let item = objects.swap_remove(object_id);
+        DeathCallback::Monster
+    };
 
-        game.log
-            .add(format!("You picked up a {}!", item.name), colors::GREEN);
+    GameObject::builder(x, y, kind.glyph, &kind.name, kind.color, true)
+        .with_fighter(Fighter {
+            base_max_hp: kind.hp,
+            hp: kind.hp,
+            base_defense: kind.defense,
+            base_power: kind.power,
+            on_death,
+            xp: kind.xp,
+            speed: kind.speed,
+            energy: 0,
+        })
+        .with_ai(ai)
+        .with_enemy(&kind.name)
+        .alive()
+        .build()
+}
+
+fn place_items(
+    room: Rect,
+    map: &Map,
+    objects: &mut Objects,
+    level: u32,
+    branch: Branch,
+    scripts: &[ScriptDef],
+    rng: &mut XorShiftRng,
+) {
+    use map::spawn_tables;
+
+    let max_items = spawn_tables::max_items(level);
+    let mut item_chances = spawn_tables::item_table(level, branch);
+    let mut script_chances = spawn_tables::script_table(level, scripts);
+    // the scripted items share one entry of the loot table, then one of them gets picked
+    let scripted_weight: u32 = script_chances.iter().map(|chance| chance.weight).sum();
+    if scripted_weight > 0 {
+        item_chances.push(Weighted {
+            weight: scripted_weight,
+            item: Item::Scripted,
+        });
+    }
+    let item_choice = WeightedChoice::new(&mut item_chances);
+    let script_choice = if scripted_weight > 0 {
+        Some(WeightedChoice::new(&mut script_chances))
+    } else {
+        None
+    };
+
+    let num_items = rng.gen_range(0, max_items + 1);
 
-        let index = game.inventory.len();
-        let slot = item.equipment.map(|e| e.slot);
-        game.inventory.push(item);
+    for _ in 0..num_items {
+        // choose random spot for this item
+        let mut x: i32;
+        let mut y: i32;
+        loop {
+            x = rng.gen_range(room.x1 + 1, room.x2);
+            y = rng.gen_range(room.y1 + 1, room.y2);
 
-        // Auto-equip if slot is open
-        if let Some(slot) = slot {
-            if get_equipped_in_slot(slot, game).is_none() {
-                game.inventory[index].equip(&mut game.log);
+            if !objects.iter().any(|item| item.x == x && item.y == y) {
+                break;
             }
         }
+
+        if !is_blocked(x, y, map, objects) {
+            let item = match (item_choice.ind_sample(rng), script_choice.as_ref()) {
+                (Item::Scripted, Some(script_choice)) => {
+                    create_scripted_item(&scripts[script_choice.ind_sample(rng)], x, y)
+                }
+                (kind, _) => roll_blessing(create_item(kind, x, y), rng),
+            };
+            objects.insert(item);
+        }
     }
 }
 
-fn ai_take_turn(
-    monster_id: usize,
-    objects: &mut [GameObject],
-    mut tcod: &mut Tcod,
-    mut game: &mut Game,
+/// Sometimes seals a room behind locked doors and leaves the key in another one. The
+/// doors go on every opening in the room's walls, so it's only done when the stairs and
+/// the key can both still be reached without passing through them.
+fn lock_vault(
+    rooms: &[Rect],
+    shop_room: Option<usize>,
+    map: &mut Map,
+    objects: &mut Objects,
+    level: DungeonLevel,
+    rng: &mut XorShiftRng,
 ) {
-    use Ai::*;
-
-    if let Some(ai) = objects[monster_id].ai.take() {
-        let new_ai = match ai {
-            Basic => ai_basic(monster_id, objects, &mut tcod, &mut game),
-            Confused {
-                previous_ai,
-                num_turns,
-            } => ai_confused(monster_id, objects, &mut game, previous_ai, num_turns),
-        };
+    use constants::locks;
 
-        objects[monster_id].ai = Some(new_ai)
+    // the first room is where the player starts and the last holds the stairs
+    if rooms.len() < 4 || rng.gen::<f32>() >= locks::VAULT_CHANCE {
+        return;
+    }
+    let vault_id = rng.gen_range(1, rooms.len() - 1);
+    if Some(vault_id) == shop_room {
+        return;
     }
-}
 
-fn ai_basic(
-    monster_id: usize,
-    objects: &mut [GameObject],
-    tcod: &mut Tcod,
-    mut game: &mut Game,
-) -> Ai {
-    // a basic monster takes its turn. If you can see it, it can see you.
-    let (monster_x, monster_y) = objects[monster_id].pos();
-    if tcod.fov.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &mut game, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
-            monster.attack(player, &mut game);
+    let vault = rooms[vault_id];
+    let mut doors = vec![];
+    for x in vault.x1..=vault.x2 {
+        for y in vault.y1..=vault.y2 {
+            let on_wall = x == vault.x1 || x == vault.x2 || y == vault.y1 || y == vault.y2;
+            if on_wall && !map[x as usize][y as usize].blocked {
+                doors.push((x, y));
+            }
         }
     }
+    if doors.is_empty() {
+        return;
+    }
 
-    Ai::Basic
+    let mut key_room_id = rng.gen_range(0, rooms.len() - 1);
+    if key_room_id >= vault_id {
+        key_room_id += 1;
+    }
+    let key_room = rooms[key_room_id];
+    let key_spot = (
+        rng.gen_range(key_room.x1 + 1, key_room.x2),
+        rng.gen_range(key_room.y1 + 1, key_room.y2),
+    );
+    if is_blocked(key_spot.0, key_spot.1, map, objects) {
+        return;
+    }
+
+    // every way down, into a branch included, has to stay open along with the last
+    // room and the key
+    let start = objects[PLAYER].pos();
+    let mut must_reach: Vec<(i32, i32)> = objects
+        .iter()
+        .filter(|object| object.char == '<')
+        .map(|object| object.pos())
+        .collect();
+    must_reach.push(rooms[rooms.len() - 1].center());
+    must_reach.push(key_spot);
+    let sealed = |tile| doors.contains(&tile);
+    if must_reach
+        .iter()
+        .any(|&spot| map::find_route(map, start, spot, sealed).is_none())
+    {
+        return;
+    }
+
+    for &(x, y) in &doors {
+        map[x as usize][y as usize] = Tile::locked_door(level);
+    }
+    objects.insert(create_item(Item::Key(level), key_spot.0, key_spot.1));
 }
 
-fn ai_confused(
-    monster_id: usize,
-    objects: &mut [GameObject],
-    mut game: &mut Game,
-    previous_ai: Box<Ai>,
-    num_turns: i32,
-) -> Ai {
-    if num_turns >= 0 {
-        // still confused, move in a random direction and decrease status duration
-        move_by(
-            monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            &mut game,
-            objects,
-        );
-        Ai::Confused {
-            previous_ai,
-            num_turns: num_turns - 1,
+/// Puts the stairs down into any side branch that splits off from this floor in one of
+/// the rooms between the first and the last
+fn place_branch_stairs(
+    rooms: &[Rect],
+    shop_room: Option<usize>,
+    objects: &mut Objects,
+    level: DungeonLevel,
+    rng: &mut XorShiftRng,
+) {
+    use constants::branches;
+
+    if level.branch != Branch::Main || rooms.len() < 3 {
+        return;
+    }
+
+    for &branch in Branch::SIDE
+        .iter()
+        .filter(|branch| branch.entry_depth() == level.depth)
+    {
+        let room_id = rng.gen_range(1, rooms.len() - 1);
+        if Some(room_id) == shop_room {
+            continue;
         }
-    } else {
-        // restore previous AI as this one gets cleared
-        game.log.add(
-            format!("The {} is no longer confused!", objects[monster_id].name),
-            colors::RED,
+
+        let (x, y) = rooms[room_id].center();
+        objects.insert(
+            GameObject::builder(
+                x,
+                y,
+                '<',
+                &branches::create_stairs_name(branch.name()),
+                branches::STAIRS_COLOR,
+                false,
+            )
+            .always_visible()
+            .build(),
         );
-        *previous_ai
     }
 }
 
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert!(first_index != second_index);
-    let split_at_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
-    } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
+/// Hides a trap in some of the rooms. The room the player starts in is left alone, and
+/// so is the shop, nobody would set up business on a trapped floor.
+fn place_traps(
+    rooms: &[Rect],
+    shop_room: Option<usize>,
+    map: &mut Map,
+    level: u32,
+    rng: &mut XorShiftRng,
+) {
+    use map::spawn_tables;
+
+    let mut trap_chances = spawn_tables::trap_table(level);
+    let trap_choice = WeightedChoice::new(&mut trap_chances);
+
+    for (room_id, room) in rooms.iter().enumerate().skip(1) {
+        if Some(room_id) == shop_room || rng.gen::<f32>() >= constants::traps::ROOM_CHANCE {
+            continue;
+        }
+
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        // the center is where the tunnels meet, and where the stairs go in the last room
+        if (x, y) != room.center() {
+            let kind = trap_choice.ind_sample(rng);
+            map[x as usize][y as usize].trap = Some(Trap::hidden(kind));
+        }
     }
 }
 
-fn player_death(player: &mut GameObject, game: &mut Game) {
-    // The game ended!
-    game.log.add("You died!", colors::RED);
+/// Sometimes leaves a pile of gold in the room, bigger the deeper the floor
+fn place_gold(room: Rect, map: &Map, objects: &mut Objects, level: u32, rng: &mut XorShiftRng) {
+    use constants::gold;
 
-    player.char = '%';
-    player.color = colors::DARK_RED;
-    player.name = "Corpse of player".to_string();
-}
+    if rng.gen::<f32>() >= gold::ROOM_CHANCE {
+        return;
+    }
 
-fn monster_death(monster: &mut GameObject, game: &mut Game) {
-    // Transform into corpse. Won't block, can't attack/be attacked, and doesn't move
-    game.log.add(
-        format!(
-            "{} is dead! You gain {} experience points.",
-            monster.name,
-            monster.fighter.unwrap().xp
-        ),
-        colors::ORANGE,
-    );
-    monster.char = '%';
-    monster.color = colors::DARK_RED;
-    monster.blocks = false;
-    monster.fighter = None;
-    monster.ai = None;
-    monster.name = format!("Remains of {}", monster.name);
+    let x = rng.gen_range(room.x1 + 1, room.x2);
+    let y = rng.gen_range(room.y1 + 1, room.y2);
+    if !is_blocked(x, y, map, objects) && !objects.iter().any(|object| object.pos() == (x, y)) {
+        let amount = rng.gen_range(1, gold::PILE_PER_LEVEL * level as i32 + 1);
+        objects.insert(create_gold(amount, x, y));
+    }
 }
 
-fn render_bar(
-    panel: &mut Offscreen,
-    x: i32,
-    y: i32,
-    total_width: i32,
-    name: &str,
-    value: i32,
-    maximum: i32,
-    bar_color: Color,
-    back_color: Color,
+fn create_item(kind: Item, x: i32, y: i32) -> GameObject {
+    let builder = match kind {
+        Item::Heal => {
+            GameObject::builder(x, y, '!', "Healing Potion", colors::VIOLET, false).with_weight(1)
+        }
+        Item::Lightning => GameObject::builder(
+            x,
+            y,
+            '#',
+            "Scroll of Lightning Bolt",
+            colors::LIGHT_YELLOW,
+            false,
+        )
+        .with_weight(1),
+        Item::Fireball => {
+            GameObject::builder(x, y, '#', "Scroll of Fireball", colors::LIGHT_YELLOW, false)
+                .with_weight(1)
+        }
+        Item::Confuse => GameObject::builder(
+            x,
+            y,
+            '#',
+            "Scroll of Confusion",
+            colors::LIGHT_YELLOW,
+            false,
+        )
+        .with_weight(1),
+        Item::Recall => {
+            use constants::consumables::scrolls::recall;
+            GameObject::builder(x, y, '#', recall::NAME, colors::LIGHT_YELLOW, false).with_weight(1)
+        }
+        Item::Identify => {
+            use constants::consumables::scrolls::identify;
+            GameObject::builder(x, y, '#', identify::NAME, colors::LIGHT_YELLOW, false)
+                .with_weight(1)
+        }
+        Item::RemoveCurse => {
+            use constants::consumables::scrolls::remove_curse;
+            GameObject::builder(x, y, '#', remove_curse::NAME, colors::LIGHT_YELLOW, false)
+                .with_weight(1)
+        }
+        Item::Dig => {
+            use constants::consumables::wands::digging;
+            GameObject::builder(x, y, '/', digging::NAME, digging::COLOR, false).with_weight(2)
+        }
+        Item::Muddle => {
+            use constants::consumables::potions::murky;
+            GameObject::builder(x, y, '!', murky::NAME, murky::COLOR, false).with_weight(1)
+        }
+        Item::Boomerang => {
+            use constants::gear::boomerang;
+            GameObject::builder(
+                x,
+                y,
+                boomerang::SYMBOL,
+                boomerang::NAME,
+                boomerang::COLOR,
+                false,
+            )
+            .with_weight(boomerang::WEIGHT)
+        }
+        Item::Bow => {
+            use constants::gear::bow;
+            GameObject::builder(x, y, bow::SYMBOL, bow::NAME, bow::COLOR, false)
+                .with_weight(bow::WEIGHT)
+                .with_equipment(Equipment {
+                    blessing: Blessing::Uncursed,
+                    blessing_known: false,
+                    crit_bonus: 0.0,
+                    equipped: false,
+                    slot: Slot::RightHand,
+                    power_bonus: 0,
+                    defense_bonus: 0,
+                    hp_bonus: 0,
+                })
+        }
+        Item::Crossbow => {
+            use constants::gear::crossbow;
+            GameObject::builder(
+                x,
+                y,
+                crossbow::SYMBOL,
+                crossbow::NAME,
+                crossbow::COLOR,
+                false,
+            )
+            .with_weight(crossbow::WEIGHT)
+            .with_equipment(Equipment {
+                blessing: Blessing::Uncursed,
+                blessing_known: false,
+                crit_bonus: 0.0,
+                equipped: false,
+                slot: Slot::RightHand,
+                power_bonus: 0,
+                defense_bonus: 0,
+                hp_bonus: 0,
+            })
+        }
+        Item::Arrows => {
+            use constants::gear::arrows;
+            GameObject::builder(x, y, arrows::SYMBOL, arrows::NAME, arrows::COLOR, false)
+                .with_weight(arrows::WEIGHT)
+                .with_quantity(arrows::BUNDLE)
+        }
+        Item::Dagger => {
+            use constants::gear::dagger;
+            GameObject::builder(x, y, dagger::SYMBOL, dagger::NAME, dagger::COLOR, false)
+                .with_weight(dagger::WEIGHT)
+                .with_equipment(Equipment {
+                    blessing: Blessing::Uncursed,
+                    blessing_known: false,
+                    crit_bonus: dagger::CRIT_BONUS,
+                    equipped: false,
+                    slot: Slot::LeftHand,
+                    hp_bonus: dagger::HP_BONUS,
+                    defense_bonus: dagger::DEFENSE_BONUS,
+                    power_bonus: dagger::POWER_BONUS,
+                })
+        }
+        Item::Sword => GameObject::builder(x, y, '/', "Sword", colors::SKY, false)
+            .with_weight(8)
+            .with_equipment(Equipment {
+                blessing: Blessing::Uncursed,
+                blessing_known: false,
+                crit_bonus: constants::combat::SWORD_CRIT_BONUS,
+                equipped: false,
+                slot: Slot::RightHand,
+                power_bonus: 3,
+                defense_bonus: 0,
+                hp_bonus: 0,
+            }),
+        Item::Shield => GameObject::builder(x, y, '[', "Shield", colors::DARKER_ORANGE, false)
+            .with_weight(10)
+            .with_equipment(Equipment {
+                blessing: Blessing::Uncursed,
+                blessing_known: false,
+                crit_bonus: 0.0,
+                equipped: false,
+                slot: Slot::LeftHand,
+                hp_bonus: 0,
+                defense_bonus: 1,
+                power_bonus: 0,
+            }),
+        Item::LeatherArmor => {
+            use constants::gear::leather_armor;
+            GameObject::builder(
+                x,
+                y,
+                leather_armor::SYMBOL,
+                leather_armor::NAME,
+                leather_armor::COLOR,
+                false,
+            )
+            .with_weight(leather_armor::WEIGHT)
+            .with_equipment(Equipment {
+                blessing: Blessing::Uncursed,
+                blessing_known: false,
+                crit_bonus: 0.0,
+                equipped: false,
+                slot: Slot::Torso,
+                hp_bonus: 0,
+                defense_bonus: leather_armor::DEFENSE_BONUS,
+                power_bonus: 0,
+            })
+        }
+        Item::Helmet => {
+            use constants::gear::helmet;
+            GameObject::builder(x, y, helmet::SYMBOL, helmet::NAME, helmet::COLOR, false)
+                .with_weight(helmet::WEIGHT)
+                .with_equipment(Equipment {
+                    blessing: Blessing::Uncursed,
+                    blessing_known: false,
+                    crit_bonus: 0.0,
+                    equipped: false,
+                    slot: Slot::Head,
+                    hp_bonus: 0,
+                    defense_bonus: helmet::DEFENSE_BONUS,
+                    power_bonus: 0,
+                })
+        }
+        Item::Boots => {
+            use constants::gear::boots;
+            GameObject::builder(x, y, boots::SYMBOL, boots::NAME, boots::COLOR, false)
+                .with_weight(boots::WEIGHT)
+                .with_equipment(Equipment {
+                    blessing: Blessing::Uncursed,
+                    blessing_known: false,
+                    crit_bonus: 0.0,
+                    equipped: false,
+                    slot: Slot::Feet,
+                    hp_bonus: 0,
+                    defense_bonus: boots::DEFENSE_BONUS,
+                    power_bonus: 0,
+                })
+        }
+        Item::Crown => {
+            use constants::gear::crown;
+            GameObject::builder(x, y, crown::SYMBOL, crown::NAME, crown::COLOR, false)
+                .with_weight(crown::WEIGHT)
+                .with_equipment(Equipment {
+                    blessing: Blessing::Uncursed,
+                    blessing_known: false,
+                    crit_bonus: 0.0,
+                    equipped: false,
+                    slot: Slot::Head,
+                    hp_bonus: crown::HP_BONUS,
+                    defense_bonus: crown::DEFENSE_BONUS,
+                    power_bonus: crown::POWER_BONUS,
+                })
+        }
+        Item::Key(level) => {
+            use constants::locks;
+
+            let name = locks::create_key_name(level.branch.name(), level.depth);
+            GameObject::builder(x, y, locks::KEY_SYMBOL, &name, locks::KEY_COLOR, false)
+        }
+        Item::Amulet => {
+            use constants::amulet;
+
+            GameObject::builder(x, y, amulet::SYMBOL, amulet::NAME, amulet::COLOR, false)
+                .with_weight(1)
+        }
+        Item::Scripted => unreachable!("scripted items are made from their definition"),
+    };
+
+    builder.with_item(kind).always_visible().build()
+}
+
+fn create_scripted_item(definition: &ScriptDef, x: i32, y: i32) -> GameObject {
+    GameObject::builder(
+        x,
+        y,
+        definition.glyph,
+        &definition.name,
+        definition.color,
+        false,
+    )
+    .with_weight(1)
+    .with_item(Item::Scripted)
+    .with_script(&definition.name)
+    .always_visible()
+    .build()
+}
+
+/// Gear found lying around might have been blessed or cursed by whoever left it there
+fn roll_blessing(mut item: GameObject, rng: &mut impl Rng) -> GameObject {
+    use constants::curses;
+
+    if let Some(equipment) = item.equipment.as_mut() {
+        let roll = rng.gen::<f32>();
+        equipment.blessing = if roll < curses::CURSED_CHANCE {
+            Blessing::Cursed
+        } else if roll < curses::CURSED_CHANCE + curses::BLESSED_CHANCE {
+            Blessing::Blessed
+        } else {
+            Blessing::Uncursed
+        };
+    }
+    item
+}
+
+/// The true names of every potion and scroll a run can turn up, the built-in ones and
+/// the scripted ones alike, which have to be identified before the player knows them
+fn unidentified_kinds(scripts: &[ScriptDef]) -> (Vec<String>, Vec<String>) {
+    let built_in = [
+        Item::Heal,
+        Item::Muddle,
+        Item::Lightning,
+        Item::Fireball,
+        Item::Confuse,
+        Item::Recall,
+        Item::Identify,
+        Item::RemoveCurse,
+    ]
+    .iter()
+    .map(|&kind| create_item(kind, 0, 0));
+    let scripted = scripts
+        .iter()
+        .map(|definition| create_scripted_item(definition, 0, 0));
+
+    let (potions, scrolls): (Vec<GameObject>, Vec<GameObject>) = built_in
+        .chain(scripted)
+        .partition(|item| item.char == constants::identify::POTION_SYMBOL);
+    let names = |items: Vec<GameObject>| items.into_iter().map(|item| item.name).collect();
+    (names(potions), names(scrolls))
+}
+
+fn is_blocked(x: i32, y: i32, map: &Map, objects: &Objects) -> bool {
+    if map[x as usize][y as usize].blocked {
+        return true;
+    }
+
+    objects
+        .iter()
+        .any(|object| object.blocks && object.pos() == (x, y))
+}
+
+fn move_by(id: EntityId, dx: i32, dy: i32, game: &mut Game, objects: &mut Objects) {
+    let (x, y) = objects[id].pos();
+
+    if !is_blocked(x + dx, y + dy, &game.map, objects) {
+        objects[id].set_pos(x + dx, y + dy);
+        game.publish(GameEvent::Moved {
+            name: objects[id].name.clone(),
+            from: (x, y),
+            to: (x + dx, y + dy),
+        });
+        spring_trap(id, objects, game);
+    }
+}
+
+/// Sets off whatever trap is hidden under the object that just stepped there. Only
+/// fighters are heavy enough to trip one, and it stays revealed once it has gone off.
+fn spring_trap(id: EntityId, objects: &mut Objects, game: &mut Game) {
+    use constants::traps;
+
+    let (x, y) = objects[id].pos();
+    let trap = match game.map[x as usize][y as usize].trap {
+        Some(trap) if objects[id].fighter.is_some() && objects[id].alive => trap,
+        _ => return,
+    };
+    game.map[x as usize][y as usize].trap = Some(Trap {
+        revealed: true,
+        ..trap
+    });
+
+    // the player hears about traps monsters set off only when it happens in plain view
+    let seen = id == PLAYER
+        || (objects[PLAYER].distance(x, y) <= TORCH_RADIUS as f32
+            && map::has_line_of_sight(objects[PLAYER].pos(), (x, y), &game.map));
+    if seen {
+        game.log.add(
+            traps::create_triggered_message(&objects[id].name, trap.kind),
+            traps::color(trap.kind),
+        );
+    }
+
+    let (damage, effect) = match trap.kind {
+        TrapKind::Spike => (traps::SPIKE_DAMAGE, None),
+        TrapKind::Dart => (
+            traps::DART_DAMAGE,
+            Some((EffectKind::Poison, traps::DART_POISON_TURNS)),
+        ),
+        TrapKind::Pit => (
+            traps::PIT_DAMAGE,
+            Some((EffectKind::Stun, traps::PIT_STUN_TURNS)),
+        ),
+        TrapKind::Alarm => {
+            // everything within earshot comes running to the noise
+            for monster in objects.iter_mut().filter(|object| {
+                object.alive && object.distance(x, y) <= traps::ALARM_RADIUS as f32
+            }) {
+                let home = match monster.ai {
+                    Some(Ai::Basic) => None,
+                    Some(Ai::Idle { home }) => Some(home),
+                    _ => continue,
+                };
+                monster.ai = Some(Ai::Chasing {
+                    last_seen: (x, y),
+                    num_turns: traps::ALARM_CHASE_TURNS,
+                    home,
+                });
+            }
+            (0, None)
+        }
+    };
+
+    if let Some((kind, turns)) = effect {
+        if seen {
+            objects[id].add_effect(kind, turns, game);
+        } else {
+            effects::apply(&mut objects[id].effects, kind, turns);
+        }
+    }
+    if damage > 0 {
+        if id == PLAYER {
+            game.hurt_by = Some(constants::morgue::create_trap_cause(trap.kind));
+        }
+        if let Some(xp) = objects[id].take_damage(damage, true, game) {
+            // the kill message promises the experience, even if the trap did the work
+            if id != PLAYER {
+                objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+            }
+        }
+    }
+}
+
+fn move_towards(
+    id: EntityId,
+    target_x: i32,
+    target_y: i32,
+    mut game: &mut Game,
+    objects: &mut Objects,
+) {
+    // Follow a route around walls and other monsters when there is one
+    let (x, y) = objects[id].pos();
+    let occupied = |tile| {
+        objects
+            .iter()
+            .any(|object| object.blocks && object.pos() == tile)
+    };
+    let step = map::find_route(&game.map, (x, y), (target_x, target_y), occupied)
+        .and_then(|route| route.first().cloned());
+    if let Some((step_x, step_y)) = step {
+        move_by(id, step_x - x, step_y - y, game, objects);
+        return;
+    }
+
+    // Otherwise head straight for it. Vector from this object to the target and distance
+    let dx = target_x - objects[id].x;
+    let dy = target_y - objects[id].y;
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    // Normalize it to length 1 (preserving direction), then round it and convert to int so the movement is restricted to the grid
+    let dx = (dx as f32 / distance).round() as i32;
+    let dy = (dy as f32 / distance).round() as i32;
+    move_by(id, dx, dy, &mut game, objects);
+}
+
+fn player_move_or_attack(
+    dx: i32,
+    dy: i32,
+    mut game: &mut Game,
+    objects: &mut Objects,
+    ui: &mut dyn Frontend,
 ) {
-    // Calculate the width of the bar
-    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+    // while scrambled, every key sends the player the same wrong way
+    let (dx, dy) = match game.scrambled {
+        Some(scramble) => scramble.apply(dx, dy),
+        None => (dx, dy),
+    };
+
+    let x = objects[PLAYER].x + dx;
+    let y = objects[PLAYER].y + dy;
+
+    if game.map[x as usize][y as usize].lock.is_some() {
+        try_unlock(x, y, game, ui);
+        return;
+    }
+
+    let npc = objects
+        .iter()
+        .find(|object| object.pos() == (x, y))
+        .and_then(|object| object.npc);
+    if let Some(npc) = npc {
+        talk_to(npc, objects, game, ui);
+        return;
+    }
+
+    // only something still alive and fighting is worth swinging at
+    let target_id =
+        objects.find(|object| object.fighter.is_some() && object.alive && object.pos() == (x, y));
+
+    match target_id {
+        Some(target_id) => {
+            let (player, target) = objects.pair_mut(PLAYER, target_id);
+            player.attack(target, &mut game);
+        }
+        None => {
+            move_by(PLAYER, dx, dy, game, objects);
+            if objects[PLAYER].pos() == (x, y) {
+                collect_gold(objects, game);
+                if let Some(message) = describe_underfoot(objects, game) {
+                    game.log.add(message, colors::LIGHT_GREY);
+                }
+                if items_at((x, y), objects).len() > 1 {
+                    pick_up_underfoot(objects, game, ui);
+                }
+            }
+        }
+    }
+}
+
+/// Opens a locked door if the player carries its key. Whether the key is used up
+/// doing so is up to the settings file.
+fn try_unlock(x: i32, y: i32, game: &mut Game, ui: &mut dyn Frontend) {
+    use constants::locks;
+
+    let level = game.map[x as usize][y as usize].lock.unwrap();
+    let key = game
+        .inventory
+        .iter()
+        .position(|item| item.item == Some(Item::Key(level)));
+
+    match key {
+        None => game.log.add(locks::LOCKED, colors::LIGHT_GREY),
+        Some(key) => {
+            game.map[x as usize][y as usize] = Tile {
+                explored: true,
+                ..Tile::empty()
+            };
+            ui.fov_mut().set(x, y, true, true);
+
+            if ui.settings().keep_keys {
+                game.log.add(locks::UNLOCKED, locks::DOOR_COLOR);
+            } else {
+                game.inventory.remove(key);
+                game.log.add(locks::UNLOCKED_KEY_USED, locks::DOOR_COLOR);
+            }
+        }
+    }
+}
+
+fn talk_to(npc: Npc, objects: &mut Objects, game: &mut Game, ui: &mut dyn Frontend) {
+    use constants::town;
+
+    match npc {
+        Npc::Healer => {
+            let player = &mut objects[PLAYER];
+            let max_hp = player.max_hp(game);
+            if player.fighter.map_or(0, |f| f.hp) >= max_hp {
+                game.log
+                    .add(town::HEALER_NOTHING_TO_MEND, colors::LIGHT_GREY);
+            } else {
+                player.heal(max_hp, game);
+                game.log.add(town::HEALER_MENDS, colors::LIGHT_VIOLET);
+            }
+        }
+        Npc::Stash => open_stash(game, ui),
+        Npc::Shopkeeper => open_shop(game, ui),
+        Npc::Shrine => visit_shrine(objects, game, ui),
+    }
+}
+
+/// Puts the player's stats back to where their class started and lets them spend
+/// every level gained since over again. Nothing is paid or changed unless they
+/// see it through to the last point.
+fn visit_shrine(objects: &mut Objects, game: &mut Game, ui: &mut dyn Frontend) {
+    use constants::town::shrine;
+
+    let points = objects[PLAYER].level - 1;
+    if points <= 0 {
+        game.log.add(shrine::NOTHING_TO_UNLEARN, colors::LIGHT_GREY);
+        return;
+    }
+    let cost = shrine::COST_PER_LEVEL * points;
+    if game.gold < cost {
+        game.log
+            .add(shrine::create_too_poor_message(cost), colors::RED);
+        return;
+    }
+    if !confirm(&shrine::create_question(cost, points), ui) {
+        return;
+    }
+
+    let class = game.class.stats();
+    let mut fighter = match objects[PLAYER].fighter {
+        Some(fighter) => fighter,
+        None => return,
+    };
+    let hp = fighter.hp;
+    fighter.base_max_hp = class.hp;
+    fighter.base_power = class.power;
+    fighter.base_defense = class.defense;
+    for point in 1..=points {
+        let title = shrine::create_title(point, points);
+        match choose_stat(&title, &fighter, class, ui) {
+            Some(choice) => train_stat(choice, &mut fighter, class),
+            None => {
+                game.log.add(shrine::CANCELLED, colors::LIGHT_GREY);
+                return;
+            }
+        }
+    }
+
+    // the wounds stay, only the training changes
+    fighter.hp = hp;
+    game.gold -= cost;
+    let player = &mut objects[PLAYER];
+    player.fighter = Some(fighter);
+    let max_hp = player.max_hp(game);
+    if let Some(fighter) = player.fighter.as_mut() {
+        fighter.hp = cmp::min(fighter.hp, max_hp);
+    }
+    game.log.add(shrine::RETRAINED, shrine::COLOR);
+}
+
+/// Lists what the shopkeeper has left and sells the chosen item, if the player can
+/// pay for it and has somewhere to put it
+fn open_shop(game: &mut Game, ui: &mut dyn Frontend) {
+    use constants::shop;
+
+    if game.shop_stock.is_empty() {
+        game.log.add(shop::SOLD_OUT, colors::LIGHT_GREY);
+        return;
+    }
+
+    let mut wares: Vec<GameObject> = game
+        .shop_stock
+        .iter()
+        .map(|&kind| create_item(kind, 0, 0))
+        .collect();
+    let options: Vec<String> = wares
+        .iter()
+        .map(|ware| {
+            let price = map::shops::price(ware.item.unwrap());
+            shop::create_option(&item_name(ware, game), ware.quantity, price)
+        })
+        .collect();
+
+    let choice = menu(&shop::create_header(game.gold), &options, shop::WIDTH, ui);
+    if let Some(choice) = choice {
+        let price = map::shops::price(game.shop_stock[choice]);
+        if game.gold < price {
+            game.log.add(shop::NOT_ENOUGH_GOLD, colors::RED);
+            return;
+        }
+
+        let ware = wares.swap_remove(choice);
+        let stack = find_stack(&ware, game);
+        if stack.is_none() && game.inventory.len() >= ui.settings().inventory_capacity {
+            game.log.add(shop::INVENTORY_FULL, colors::RED);
+            return;
+        }
+
+        game.gold -= price;
+        game.shop_stock.remove(choice);
+        game.log.add(
+            shop::create_bought_message(&item_name(&ware, game), price),
+            colors::LIGHT_GREEN,
+        );
+        match stack {
+            Some(index) => game.inventory[index].quantity += ware.quantity,
+            None => game.inventory.push(ware),
+        }
+    }
+}
+
+/// Moves items between the inventory and the stash, which is written back to its own
+/// file after every transfer so it survives the run ending. A replay works on the stash
+/// it was recorded with and leaves the file alone.
+fn open_stash(game: &mut Game, ui: &mut dyn Frontend) {
+    use constants::town::stash;
+
+    let stash_items = match ui.replay_mut().stash_mut() {
+        Some(recorded) => Ok(recorded.clone()),
+        None => persistence::load_stash(),
+    };
+    let mut stash_items = match stash_items {
+        Ok(items) => items,
+        Err(_) => {
+            game.log.add(stash::UNREADABLE, colors::RED);
+            return;
+        }
+    };
+
+    let choice = menu(
+        &stash::create_header(stash_items.len()),
+        &[stash::DEPOSIT, stash::WITHDRAW],
+        stash::WIDTH,
+        ui,
+    );
+
+    match choice {
+        Some(0) => {
+            if stash_items.len() >= stash::CAPACITY {
+                game.log.add(stash::FULL, colors::RED);
+                return;
+            }
+
+            let inventory_index = inventory_menu(game, stash::DEPOSIT_HEADER, ui);
+            if let Some(inventory_index) = inventory_index {
+                if game.inventory[inventory_index].equipment.is_some()
+                    && !game.inventory[inventory_index].dequip(&mut game.log)
+                {
+                    return;
+                }
+                let item = game.inventory.remove(inventory_index);
+                game.log.add(
+                    stash::create_deposit_message(&item_name(&item, game)),
+                    colors::LIGHT_GREY,
+                );
+                stash_items.push(item);
+            }
+        }
+        Some(1) => {
+            if stash_items.is_empty() {
+                game.log.add(stash::EMPTY, colors::RED);
+                return;
+            }
+            if game.inventory.len() >= ui.settings().inventory_capacity {
+                game.log.add(stash::INVENTORY_FULL, colors::RED);
+                return;
+            }
+
+            let names: Vec<String> = stash_items
+                .iter()
+                .map(|item| item_name(item, game))
+                .collect();
+            let stash_index = menu(stash::WITHDRAW_HEADER, &names, stash::WIDTH, ui);
+            if let Some(stash_index) = stash_index {
+                let item = stash_items.remove(stash_index);
+                game.log.add(
+                    stash::create_withdraw_message(&item_name(&item, game)),
+                    colors::LIGHT_GREY,
+                );
+                match find_stack(&item, game) {
+                    Some(index) => game.inventory[index].quantity += item.quantity,
+                    None => game.inventory.push(item),
+                }
+            }
+        }
+        _ => return,
+    }
+
+    match ui.replay_mut().stash_mut() {
+        Some(recorded) => *recorded = stash_items,
+        None => {
+            if persistence::save_stash(&stash_items).is_err() {
+                game.log.add(stash::UNWRITABLE, colors::RED);
+            }
+        }
+    }
+}
+
+/// Lists whatever the player is standing on, leaving out the stairs which get their own hint
+fn describe_underfoot(objects: &Objects, game: &Game) -> Option<String> {
+    let names: Vec<String> = objects
+        .entries()
+        .filter(|&(id, object)| {
+            id != PLAYER
+                && object.pos() == objects[PLAYER].pos()
+                && object.name != "stairs"
+                && object.name != constants::gui::menus::next_level::UP_STAIRS_NAME
+                && stairs_branch(object).is_none()
+        })
+        .map(|(_, object)| item_name(object, game))
+        .collect();
+
+    if names.is_empty() {
+        None
+    } else {
+        Some(constants::underfoot::create_message(&names))
+    }
+}
+
+/// Puts what monsters dropped this turn on the floor. Gold landing on a pile already
+/// lying there just grows it, so a crowded corridor doesn't fill up with separate heaps.
+fn drop_loot(objects: &mut Objects, game: &mut Game) {
+    for (pos, loot) in game.drops.drain(..) {
+        match loot {
+            Loot::Gold(amount) => {
+                match objects.find(|object| object.pos() == pos && object.gold > 0) {
+                    Some(pile_id) => objects[pile_id].gold += amount,
+                    None => {
+                        objects.insert(create_gold(amount, pos.0, pos.1));
+                    }
+                }
+            }
+            Loot::Item(kind) => {
+                objects.insert(roll_blessing(
+                    create_item(kind, pos.0, pos.1),
+                    &mut game.rng,
+                ));
+            }
+            Loot::Unseal => {
+                for object in objects.iter_mut().filter(|object| object.name == "stairs") {
+                    object.locked = false;
+                }
+            }
+        }
+    }
+}
+
+fn create_gold(amount: i32, x: i32, y: i32) -> GameObject {
+    use constants::gold;
+
+    GameObject::builder(x, y, gold::SYMBOL, gold::NAME, gold::COLOR, false)
+        .with_gold(amount)
+        .build()
+}
+
+/// Gold doesn't take up a slot or weigh anything, so it's scooped up just by walking over it
+fn collect_gold(objects: &mut Objects, game: &mut Game) {
+    let player_pos = objects[PLAYER].pos();
+    while let Some(pile) = objects
+        .find(|object| object.pos() == player_pos && object.gold > 0)
+        .and_then(|pile_id| objects.remove(pile_id))
+    {
+        game.gold += pile.gold;
+        game.log.add(
+            constants::gold::create_pickup_message(pile.gold),
+            constants::gold::COLOR,
+        );
+    }
+}
+
+/// Ids of every item lying on the given tile
+fn items_at(pos: (i32, i32), objects: &Objects) -> Vec<EntityId> {
+    objects
+        .entries()
+        .filter(|&(_, object)| object.pos() == pos && object.item.is_some())
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Picks up the item the player is standing on. When items are piled up the player
+/// chooses which one to take, so nothing stays hidden under the others.
+fn pick_up_underfoot(objects: &mut Objects, game: &mut Game, ui: &mut dyn Frontend) {
+    use constants::pile;
+
+    let mut item_ids = items_at(objects[PLAYER].pos(), objects);
+    match item_ids.len() {
+        0 => {}
+        1 => pick_item_up(item_ids[0], objects, game, ui),
+        _ => {
+            item_ids.truncate(pile::MAX_LISTED);
+            let names: Vec<String> = item_ids
+                .iter()
+                .map(|&id| item_name(&objects[id], game))
+                .collect();
+            let choice = menu(pile::HEADER, &names, constants::gui::INVENTORY_WIDTH, ui);
+            if let Some(choice) = choice {
+                pick_item_up(item_ids[choice], objects, game, ui);
+            }
+        }
+    }
+}
+
+fn player_on_stairs(objects: &Objects) -> bool {
+    objects
+        .iter()
+        .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "stairs")
+}
+
+/// Whether the stairs the player is standing on are still sealed
+fn stairs_locked(objects: &Objects) -> bool {
+    objects.iter().any(|object| {
+        object.pos() == objects[PLAYER].pos() && object.name == "stairs" && object.locked
+    })
+}
+
+/// The side branch whose stairs the player is standing on, if any
+fn branch_underfoot(objects: &Objects) -> Option<Branch> {
+    objects
+        .iter()
+        .filter(|object| object.pos() == objects[PLAYER].pos())
+        .find_map(stairs_branch)
+}
+
+fn stairs_branch(object: &GameObject) -> Option<Branch> {
+    Branch::SIDE
+        .iter()
+        .copied()
+        .find(|branch| object.name == constants::branches::create_stairs_name(branch.name()))
+}
+
+fn carrying_amulet(game: &Game) -> bool {
+    game.inventory
+        .iter()
+        .any(|item| item.item == Some(Item::Amulet))
+}
+
+fn player_on_up_stairs(objects: &Objects) -> bool {
+    objects.iter().any(|object| {
+        object.pos() == objects[PLAYER].pos()
+            && object.name == constants::gui::menus::next_level::UP_STAIRS_NAME
+    })
+}
+
+fn living_monsters(objects: &Objects) -> usize {
+    objects
+        .iter()
+        .filter(|object| object.ai.is_some() && object.alive)
+        .count()
+}
+
+fn pick_item_up(object_id: EntityId, objects: &mut Objects, game: &mut Game, ui: &dyn Frontend) {
+    use constants::gui::menus::inventory;
+
+    // stackable items join the ones already carried instead of taking another slot
+    let capacity = ui.settings().inventory_capacity;
+    let stack = find_stack(&objects[object_id], game);
+    if stack.is_none() && game.inventory.len() >= capacity {
+        game.log.add(
+            inventory::create_full_message(capacity, &item_name(&objects[object_id], game)),
+            colors::RED,
+        );
+        return;
+    }
+
+    let was_encumbered = is_encumbered(objects, game);
+    let item = match objects.remove(object_id) {
+        Some(item) => item,
+        None => return,
+    };
+
+    game.publish(GameEvent::ItemPickedUp {
+        item: item_name(&item, game),
+        quantity: item.quantity,
+    });
+    if item.item == Some(Item::Amulet) {
+        game.log
+            .add(constants::amulet::TAKEN, constants::amulet::COLOR);
+    }
+
+    let index = match stack {
+        Some(index) => {
+            game.inventory[index].quantity += item.quantity;
+            index
+        }
+        None => {
+            let index = game.inventory.len();
+            let slot = item.equipment.map(|e| e.slot);
+            game.inventory.push(item);
+
+            // Auto-equip if slot is open
+            if let Some(slot) = slot {
+                if get_equipped_in_slot(slot, game).is_none() {
+                    game.inventory[index].equip(&mut game.log);
+                }
+            }
+
+            index
+        }
+    };
+
+    if !was_encumbered && is_encumbered(objects, game) {
+        game.log.add(
+            constants::encumbrance::create_pickup_warning(&item_name(&game.inventory[index], game)),
+            colors::YELLOW,
+        );
+    }
+}
+
+/// The inventory stack this item would join, if it stacks and one is already carried
+fn find_stack(item: &GameObject, game: &Game) -> Option<usize> {
+    let kind = item.item.filter(|kind| kind.stacks())?;
+    game.inventory
+        .iter()
+        .position(|carried| carried.item == Some(kind) && carried.script == item.script)
+}
+
+/// Picks up the equipment and wears it. pick_item_up already fills an empty slot, so
+/// only a swap is left over, and that goes through the usual downgrade confirmation.
+fn equip_from_ground(
+    object_id: EntityId,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) {
+    let inventory_before = game.inventory.len();
+    pick_item_up(object_id, objects, game, ui);
+    if game.inventory.len() == inventory_before {
+        // the inventory was full, nothing was picked up
+        return;
+    }
+
+    let inventory_id = inventory_before;
+    if matches!(game.inventory[inventory_id].equipment, Some(equipment) if !equipment.equipped) {
+        toggle_equipment(inventory_id, objects, game, ui);
+    }
+}
+
+/// Loot is only rolled when remains are searched, so corpses don't litter the floor
+fn search_remains(remains_id: EntityId, objects: &mut Objects, game: &mut Game) {
+    use constants::remains;
+
+    objects[remains_id].searchable = false;
+    let (x, y) = objects[remains_id].pos();
+
+    if game.rng.gen::<f32>() < remains::LOOT_CHANCE {
+        let mut item_chances = map::spawn_tables::item_table(
+            game.dungeon_level.absolute_depth(),
+            game.dungeon_level.branch,
+        );
+        let item_choice = WeightedChoice::new(&mut item_chances);
+        let item = create_item(item_choice.ind_sample(&mut game.rng), x, y);
+        let item = roll_blessing(item, &mut game.rng);
+
+        game.log.add(
+            remains::create_found_message(&item_name(&item, game), &objects[remains_id].name),
+            colors::LIGHT_GREEN,
+        );
+        objects.insert(item);
+    } else {
+        game.log.add(
+            remains::create_empty_message(&objects[remains_id].name),
+            colors::WHITE,
+        );
+    }
+}
+
+fn carried_weight(game: &Game) -> i32 {
+    game.inventory
+        .iter()
+        .map(|item| item.weight * item.quantity)
+        .sum()
+}
+
+fn carry_capacity(objects: &Objects, game: &Game) -> i32 {
+    use constants::encumbrance;
+    encumbrance::BASE_CAPACITY + objects[PLAYER].power(game) * encumbrance::CAPACITY_PER_POWER
+}
+
+fn is_encumbered(objects: &Objects, game: &Game) -> bool {
+    carried_weight(game) > carry_capacity(objects, game)
+}
+
+fn player_speed(objects: &Objects, game: &Game) -> i32 {
+    let speed = objects[PLAYER].speed();
+    let speed = if is_encumbered(objects, game) {
+        speed * constants::encumbrance::SPEED_PERCENT / 100
+    } else {
+        speed
+    };
+    cmp::max(speed, 1)
+}
+
+/// Gives every monster the energy it builds up while the player takes a turn. That
+/// depends on how fast it is next to the player, a player twice as fast as a monster
+/// only leaves it enough for half a move.
+fn gain_energy(objects: &mut Objects, game: &Game) {
+    let player_speed = player_speed(objects, game);
+    for (id, monster) in objects.entries_mut() {
+        if id == PLAYER || monster.ai.is_none() {
+            continue;
+        }
+        let speed = monster.speed();
+        if let Some(fighter) = monster.fighter.as_mut() {
+            fighter.energy += speed * constants::speed::ACTION_COST / player_speed;
+        }
+    }
+}
+
+/// Lets every monster with enough energy saved up act once, returning whether any did
+fn monsters_take_turn(objects: &mut Objects, ui: &mut dyn Frontend, game: &mut Game) -> bool {
+    use constants::speed::ACTION_COST;
+
+    let mut acted = false;
+    for id in objects.ids() {
+        // the player's death was already fully resolved, nobody else acts this turn
+        if !objects[PLAYER].alive {
+            break;
+        }
+
+        // anything taken out since the turn started just doesn't get to act
+        let ready = objects.get(id).is_some_and(|monster| {
+            monster.ai.is_some() && monster.fighter.is_some_and(|f| f.energy >= ACTION_COST)
+        });
+        if ready {
+            if let Some(fighter) = objects[id].fighter.as_mut() {
+                fighter.energy -= ACTION_COST;
+            }
+            ai_take_turn(id, objects, ui, game);
+            acted = true;
+        }
+    }
+    acted
+}
+
+fn ai_take_turn(
+    monster_id: EntityId,
+    objects: &mut Objects,
+    ui: &mut dyn Frontend,
+    game: &mut Game,
+) {
+    use Ai::*;
+
+    // stunned monsters lose the turn, confused ones stumble about whatever they had planned
+    if objects[monster_id].has_effect(EffectKind::Stun) {
+        return;
+    }
+    if objects[monster_id].has_effect(EffectKind::Confusion) {
+        ai_confused(monster_id, objects, game);
+        return;
+    }
+
+    if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = match ai {
+            Basic => ai_basic(monster_id, objects, ui, game, None),
+            Idle { home } => ai_idle(monster_id, objects, ui, game, home),
+            Chasing {
+                last_seen,
+                num_turns,
+                home,
+            } => ai_chasing(monster_id, objects, ui, game, last_seen, num_turns, home),
+            Shopkeeper { home } => ai_shopkeeper(monster_id, objects, game, home),
+        };
+
+        objects[monster_id].ai = Some(new_ai)
+    }
+}
+
+/// `home` is where an idle monster lived, kept through the chase this sets off
+fn ai_basic(
+    monster_id: EntityId,
+    objects: &mut Objects,
+    ui: &mut dyn Frontend,
+    mut game: &mut Game,
+    home: Option<(i32, i32)>,
+) -> Ai {
+    // a basic monster takes its turn. If you can see it, it can see you.
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if ui.fov().is_in_fov(monster_x, monster_y) {
+        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+            let (player_x, player_y) = objects[PLAYER].pos();
+            move_towards(monster_id, player_x, player_y, &mut game, objects);
+        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
+            let (monster, player) = objects.pair_mut(monster_id, PLAYER);
+            monster.attack(player, &mut game);
+        }
+
+        // remember where the player was, in case they duck out of sight
+        return Ai::Chasing {
+            last_seen: objects[PLAYER].pos(),
+            num_turns: CHASE_NUM_TURNS,
+            home,
+        };
+    }
+
+    Ai::Basic
+}
+
+fn ai_idle(
+    monster_id: EntityId,
+    objects: &mut Objects,
+    ui: &mut dyn Frontend,
+    game: &mut Game,
+    home: (i32, i32),
+) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if ui.fov().is_in_fov(monster_x, monster_y) {
+        return ai_basic(monster_id, objects, ui, game, Some(home));
+    }
+
+    // a chase can end far from home, so walk back before wandering again
+    let (home_x, home_y) = home;
+    if objects[monster_id].distance(home_x, home_y) > WANDER_RADIUS {
+        move_towards(monster_id, home_x, home_y, game, objects);
+        return Ai::Idle { home };
+    }
+
+    // occasionally shuffle around, but never stray far from home
+    if game.rng.gen::<f32>() < WANDER_CHANCE {
+        let dx = game.rng.gen_range(-1, 2);
+        let dy = game.rng.gen_range(-1, 2);
+        let distance_from_home =
+            (((monster_x + dx - home_x).pow(2) + (monster_y + dy - home_y).pow(2)) as f32).sqrt();
+
+        if distance_from_home <= WANDER_RADIUS {
+            move_by(monster_id, dx, dy, game, objects);
+        }
+    }
+
+    Ai::Idle { home }
+}
+
+fn ai_chasing(
+    monster_id: EntityId,
+    objects: &mut Objects,
+    ui: &mut dyn Frontend,
+    game: &mut Game,
+    last_seen: (i32, i32),
+    num_turns: i32,
+    home: Option<(i32, i32)>,
+) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if ui.fov().is_in_fov(monster_x, monster_y) {
+        // the player is back in sight, fight as usual
+        return ai_basic(monster_id, objects, ui, game, home);
+    }
+
+    if num_turns <= 0 || objects[monster_id].pos() == last_seen {
+        // lost the trail. A monster with a home goes back to minding it, one without
+        // settles down wherever the chase ended.
+        return Ai::Idle {
+            home: home.unwrap_or_else(|| objects[monster_id].pos()),
+        };
+    }
+
+    let (target_x, target_y) = last_seen;
+    move_towards(monster_id, target_x, target_y, game, objects);
+    Ai::Chasing {
+        last_seen,
+        num_turns: num_turns - 1,
+        home,
+    }
+}
+
+/// Stays behind the counter, walking back to it if something ever moved the
+/// shopkeeper away
+fn ai_shopkeeper(
+    monster_id: EntityId,
+    objects: &mut Objects,
+    game: &mut Game,
+    home: (i32, i32),
+) -> Ai {
+    if objects[monster_id].pos() != home {
+        move_towards(monster_id, home.0, home.1, game, objects);
+    }
+
+    Ai::Shopkeeper { home }
+}
+
+/// Moves a confused monster in a random direction. Its own AI is left alone, so it
+/// picks up where it left off once the confusion wears off.
+fn ai_confused(monster_id: EntityId, objects: &mut Objects, game: &mut Game) {
+    let dx = game.rng.gen_range(-1, 2);
+    let dy = game.rng.gen_range(-1, 2);
+    let (x, y) = objects[monster_id].pos();
+
+    // stumbling into any fighter, the player included, turns the step into an attack
+    let target_id = objects
+        .entries()
+        .find(|&(id, object)| {
+            id != monster_id && object.fighter.is_some() && object.pos() == (x + dx, y + dy)
+        })
+        .map(|(id, _)| id);
+
+    match target_id {
+        Some(target_id) => {
+            let (monster, target) = objects.pair_mut(monster_id, target_id);
+            monster.attack(target, game);
+        }
+        None => move_by(monster_id, dx, dy, game, objects),
+    }
+}
+
+fn player_death(player: &mut GameObject, _game: &mut Game) {
+    // The game ended!
+    player.char = '%';
+    player.color = colors::DARK_RED;
+    player.name = "Corpse of player".to_string();
+}
+
+fn monster_death(monster: &mut GameObject, game: &mut Game) {
+    // the coins it carried spill out where it fell
+    if game.rng.gen::<f32>() < constants::gold::DROP_CHANCE {
+        let most = cmp::max(
+            monster.fighter.unwrap().xp / constants::gold::XP_PER_COIN,
+            1,
+        );
+        let amount = game.rng.gen_range(1, most + 1);
+        game.drops.push((monster.pos(), Loot::Gold(amount)));
+    }
+
+    // Transform into corpse. Won't block, can't attack/be attacked, and doesn't move
+    monster.char = '%';
+    monster.color = colors::DARK_RED;
+    monster.blocks = false;
+    monster.fighter = None;
+    monster.ai = None;
+    monster.effects.clear();
+    monster.searchable = true;
+    monster.name = format!("Remains of {}", monster.name);
+}
+
+/// A boss goes down like any other monster, but always leaves a prize behind
+fn boss_death(boss: &mut GameObject, game: &mut Game) {
+    use constants::boss;
+
+    let gold = boss::GOLD_PER_DEPTH * game.dungeon_level.absolute_depth() as i32;
+    game.drops.push((boss.pos(), Loot::Item(Item::Crown)));
+    game.drops.push((boss.pos(), Loot::Gold(gold)));
+
+    let name = boss.name.clone();
+    monster_death(boss, game);
+    game.drops.push((boss.pos(), Loot::Unseal));
+    game.log
+        .add(boss::create_slain_message(&name), boss::SLAIN_COLOR);
+}
+
+fn render_bar(
+    panel: &mut Offscreen,
+    x: i32,
+    y: i32,
+    total_width: i32,
+    name: &str,
+    value: i32,
+    maximum: i32,
+    bar_color: Color,
+    back_color: Color,
+) {
+    // keep passing glitches like negative hp from drawing a broken bar
+    let maximum = cmp::max(maximum, 0);
+    let value = value.clamp(0, maximum);
+    let bar_width = bar_width(value, maximum, total_width);
+
+    // Render the background
+    panel.set_default_background(back_color);
+    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+
+    // Render the Bar
+    panel.set_default_background(bar_color);
+    if bar_width > 0 {
+        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+    }
+
+    panel.set_default_foreground(colors::WHITE);
+    panel.print_ex(
+        x + total_width / 2,
+        y,
+        BackgroundFlag::None,
+        TextAlignment::Center,
+        &format!("{}: {}/{}", name, value, maximum),
+    );
+}
+
+/// How much of a bar `total_width` wide is filled in. Values below zero or past the
+/// maximum fill none or all of it, and an empty maximum gets an empty bar.
+fn bar_width(value: i32, maximum: i32, total_width: i32) -> i32 {
+    if maximum <= 0 {
+        return 0;
+    }
+    let value = value.clamp(0, maximum);
+    (value as f32 / maximum as f32 * total_width as f32) as i32
+}
+
+/// Everything currently affecting the player, as a short label and a color
+fn player_statuses(objects: &Objects, game: &Game) -> Vec<(String, Color)> {
+    let mut statuses = vec![];
+
+    if let Some(scramble) = game.scrambled {
+        use constants::consumables::potions::murky;
+        statuses.push((
+            murky::create_status_label(scramble.turns_left),
+            murky::COLOR,
+        ));
+    }
+
+    for effect in &objects[PLAYER].effects {
+        statuses.push((
+            constants::effects::create_panel_label(effect.kind, effect.turns_left),
+            constants::effects::color(effect.kind),
+        ));
+    }
+
+    if is_encumbered(objects, game) {
+        statuses.push((
+            constants::encumbrance::STATUS_LABEL.to_string(),
+            colors::YELLOW,
+        ));
+    }
+
+    statuses
+}
+
+/// Lists the statuses on the row below the HP bar, summarizing whatever doesn't fit
+fn render_statuses(panel: &mut Offscreen, statuses: &[(String, Color)]) {
+    use constants::gui::{BAR_WIDTH, STATUS_Y};
+
+    let mut x = 1;
+    for (index, (label, color)) in statuses.iter().enumerate() {
+        let remaining = statuses.len() - index;
+        let overflow = format!("+{}", remaining);
+        let is_last = remaining == 1;
+        let width = label.len() as i32;
+
+        // keep room for the "+N" marker unless this is the final label
+        let needed = if is_last {
+            width
+        } else {
+            width + 1 + overflow.len() as i32
+        };
+        if x + needed > 1 + BAR_WIDTH {
+            panel.set_default_foreground(colors::LIGHT_GREY);
+            panel.print_ex(
+                x,
+                STATUS_Y,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                overflow,
+            );
+            break;
+        }
+
+        panel.set_default_foreground(*color);
+        panel.print_ex(
+            x,
+            STATUS_Y,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            label,
+        );
+        x += width + 1;
+    }
+}
+
+/// Keeps the player centered on screen, except near the edges of the map where the
+/// view stops scrolling so it never shows anything past them
+fn camera_origin((x, y): (i32, i32)) -> (i32, i32) {
+    use constants::gui::{MAP_HEIGHT, MAP_WIDTH, VIEW_HEIGHT, VIEW_WIDTH};
+
+    (
+        (x - VIEW_WIDTH / 2).clamp(0, MAP_WIDTH - VIEW_WIDTH),
+        (y - VIEW_HEIGHT / 2).clamp(0, MAP_HEIGHT - VIEW_HEIGHT),
+    )
+}
+
+/// The map tile under the mouse, or None when it's over the panel
+fn mouse_tile(tcod: &Tcod) -> Option<(i32, i32)> {
+    use constants::gui::{VIEW_HEIGHT, VIEW_WIDTH};
+
+    let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+    if x < 0 || y < 0 || x >= VIEW_WIDTH || y >= VIEW_HEIGHT {
+        return None;
+    }
+
+    let (camera_x, camera_y) = tcod.camera;
+    Some((x + camera_x, y + camera_y))
+}
+
+/// Names whatever is in view on the tile, with a word on how hurt each monster is
+fn get_names_under_mouse(
+    tile: Option<(i32, i32)>,
+    objects: &Objects,
+    fov_map: &FovMap,
+    game: &Game,
+) -> String {
+    let names = objects
+        .entries()
+        .filter(|(_, obj)| Some(obj.pos()) == tile && fov_map.is_in_fov(obj.x, obj.y))
+        .map(|(id, obj)| match obj.fighter {
+            Some(fighter) if id != PLAYER => format!(
+                "{} ({})",
+                obj.display_name(),
+                constants::gui::condition(fighter.hp, obj.max_hp(game))
+            ),
+            _ if obj.item.is_some() => item_name(obj, game),
+            _ => obj.display_name(),
+        })
+        .collect::<Vec<_>>();
+
+    names.join(", ")
+}
+
+/// Moves a cursor over the map with the movement keys, describing whatever is under it,
+/// until Escape or the look key is pressed again
+fn look_around(objects: &Objects, game: &mut Game, tcod: &mut Tcod) {
+    use constants::gui::{MAP_HEIGHT, MAP_WIDTH, VIEW_WIDTH};
+
+    let mut cursor = objects[PLAYER].pos();
+
+    while !tcod.root.window_closed() {
+        tcod.highlighted = vec![cursor];
+        render_all(tcod, objects, game);
+
+        // the description goes over the top of the map, above everything else drawn there
+        tcod.root
+            .set_default_foreground(constants::look::TEXT_COLOR);
+        tcod.root.print_rect(
+            1,
+            0,
+            VIEW_WIDTH - 2,
+            0,
+            describe_tile(cursor, objects, game, &tcod.fov),
+        );
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        let (dx, dy) = match tcod.keybindings.action_for(key) {
+            Some(Action::Exit) | Some(Action::Look) => break,
+            Some(action) => action.direction().unwrap_or((0, 0)),
+            None => (0, 0),
+        };
+        cursor = (
+            (cursor.0 + dx).clamp(0, MAP_WIDTH - 1),
+            (cursor.1 + dy).clamp(0, MAP_HEIGHT - 1),
+        );
+    }
+
+    tcod.highlighted.clear();
+}
+
+/// What the look cursor reports for a tile: the names of whatever is in view there,
+/// then a line on each item, or on the tile itself when it's empty
+fn describe_tile((x, y): (i32, i32), objects: &Objects, game: &Game, fov: &FovMap) -> String {
+    use constants::look;
+
+    let tile = &game.map[x as usize][y as usize];
+    if !fov.is_in_fov(x, y) {
+        let text = if tile.explored {
+            look::OUT_OF_SIGHT
+        } else {
+            look::UNEXPLORED
+        };
+        return text.to_string();
+    }
+
+    let mut lines = vec![];
+    let names = get_names_under_mouse(Some((x, y)), objects, fov, game);
+    if !names.is_empty() {
+        lines.push(names);
+    }
+    for object in objects.iter().filter(|object| object.pos() == (x, y)) {
+        if object.item.is_some() {
+            lines.push(item_description(object, game));
+        }
+    }
+
+    if lines.is_empty() {
+        let text = match (tile.lock, tile.trap) {
+            (Some(_), _) => look::DOOR.to_string(),
+            (_, Some(trap)) if trap.revealed => look::create_trap_line(trap.kind),
+            _ if tile.blocked => look::WALL.to_string(),
+            _ => look::FLOOR.to_string(),
+        };
+        lines.push(text);
+    }
+
+    lines.join("\n")
+}
+
+/// Asks the player to pick one of the options, None if they back out. More options
+/// than there are letters are shown a page at a time, with entries to turn the page.
+fn menu<T: AsRef<str>>(
+    header: &str,
+    options: &[T],
+    width: i32,
+    ui: &mut dyn Frontend,
+) -> Option<usize> {
+    use constants::gui::menus::paging;
+
+    let options: Vec<&str> = options.iter().map(AsRef::as_ref).collect();
+    let pages = options.len().div_ceil(paging::PAGE_SIZE);
+    let mut page = 0;
+    loop {
+        let (shown, previous, next) = menu_page(options.len(), page);
+        let mut entries = options[shown.clone()].to_vec();
+        if next {
+            entries.push(paging::NEXT);
+        }
+        if previous {
+            entries.push(paging::PREVIOUS);
+        }
+        let paged_header = if options.len() > paging::LETTERS {
+            paging::create_header(header, page + 1, pages)
+        } else {
+            header.to_string()
+        };
+
+        // every page shown is its own choice, so a replay turns the same pages
+        let choice = ui.menu(&paged_header, &entries, width);
+        ui.replay_mut().record(Input::Menu(choice));
+        match choice {
+            Some(index) if index < shown.len() => return Some(shown.start + index),
+            Some(index) if next && index == shown.len() => page += 1,
+            Some(_) => page -= 1,
+            None => return None,
+        }
+    }
+}
+
+/// Which options go on a page of a menu, and whether there are pages before and
+/// after it. A menu that fits the letters is all on one page.
+fn menu_page(options: usize, page: usize) -> (Range<usize>, bool, bool) {
+    use constants::gui::menus::paging;
+
+    if options <= paging::LETTERS {
+        return (0..options, false, false);
+    }
+    let start = page * paging::PAGE_SIZE;
+    let end = cmp::min(options, start + paging::PAGE_SIZE);
+    (start..end, page > 0, end < options)
+}
+
+fn show_menu(header: &str, options: &[&str], width: i32, tcod: &mut Tcod) -> Option<usize> {
+    // calculate total height for the header (after auto-wrap) and one line per option
+    let header_height = if header.is_empty() {
+        0
+    } else {
+        tcod.root
+            .get_height_rect(0, 0, width, tcod.layout.screen_height, header)
+    };
+
+    let height = options.len() as i32 + header_height;
+
+    let mut window = Offscreen::new(width, height);
+
+    // print the header, with auto-wrap;
+    window.set_default_foreground(colors::WHITE);
+    window.print_rect_ex(
+        0,
+        0,
+        width,
+        height,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        header,
+    );
+
+    // print all the options
+    for (index, option_text) in options.iter().enumerate() {
+        // essentially ASCII math, probably a better way of approaching this entire menu
+        let menu_letter = (b'a' + index as u8) as char;
+        let text = format!("({}) {}", menu_letter, option_text);
+        window.print_ex(
+            0,
+            header_height + index as i32,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            text,
+        );
+    }
+
+    let x = constants::gui::SCREEN_WIDTH / 2 - width / 2;
+    let y = tcod.layout.screen_height / 2 - height / 2;
+    tcod::console::blit(
+        &window,
+        (0, 0),
+        (width, height),
+        &mut tcod.root,
+        (x, y),
+        1.0,
+        0.7,
+    );
+
+    // present the root console to the player and wait for a key press. A replay
+    // answers with whatever the player picked, once it's been on screen a moment.
+    tcod.root.flush();
+    if tcod.replay.is_playing() {
+        thread::sleep(tcod.replay.delay());
+        return tcod.replay.next_menu();
+    }
+    let key = tcod.root.wait_for_keypress(true);
+
+    // convert the ASCII code to an index; if it corresponds to an option, return it
+    if key.printable.is_alphabetic() {
+        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+        if index < options.len() {
+            Some(index)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+fn inventory_menu(game: &Game, header: &str, ui: &mut dyn Frontend) -> Option<usize> {
+    let options = if game.inventory.is_empty() {
+        vec!["Inventory is empty.".into()]
+    } else {
+        game.inventory
+            .iter()
+            .map(|item| inventory_label(item, game))
+            .collect()
+    };
+
+    let inventory_index = menu(header, &options, constants::gui::INVENTORY_WIDTH, ui);
+
+    // if an item was chosen, return it
+    if !game.inventory.is_empty() {
+        inventory_index
+    } else {
+        None
+    }
+}
+
+/// Collects the name, effect, gear bonuses and weight of an item into one message
+/// How an item is listed in the inventory, with its stack size or where it's worn
+fn inventory_label(item: &GameObject, game: &Game) -> String {
+    let name = item_name(item, game);
+    match item.equipment {
+        Some(equipment) if equipment.equipped => format!("{} (on {})", name, equipment.slot),
+        _ if item.quantity > 1 => format!("{} (x{})", name, item.quantity),
+        _ => name,
+    }
+}
+
+/// What the player calls an item: its true name, or what it looks like until they
+/// find out what it is
+fn item_name(item: &GameObject, game: &Game) -> String {
+    let name = match game.identification.appearance(&item.name) {
+        Some(appearance) => appearance.name.clone(),
+        None => item.name.clone(),
+    };
+    match item.equipment {
+        Some(equipment) if equipment.blessing_known => {
+            constants::curses::create_name(equipment.blessing.name(), &name)
+        }
+        _ => name,
+    }
+}
+
+/// What an item does. A scripted one says so in its definition, and one that hasn't
+/// been identified yet gives nothing away.
+fn item_description(object: &GameObject, game: &Game) -> String {
+    use constants::identify;
+
+    if !game.identification.is_known(&object.name) {
+        let text = if object.char == identify::POTION_SYMBOL {
+            identify::UNKNOWN_POTION
+        } else {
+            identify::UNKNOWN_SCROLL
+        };
+        return text.to_string();
+    }
+
+    let script = object
+        .script
+        .as_ref()
+        .and_then(|name| scripts::find(&game.scripts, name));
+    match (object.item, script) {
+        (_, Some(definition)) => definition.description.clone(),
+        (Some(item), None) => item.description(),
+        (None, None) => String::new(),
+    }
+}
+
+fn describe_item(object: &GameObject, game: &Game) -> String {
+    let mut text = format!("{}\n\n", item_name(object, game));
+
+    if object.item.is_some() {
+        text.push_str(&format!("{}\n", item_description(object, game)));
+    }
+
+    if let Some(equipment) = object.equipment {
+        text.push_str(&format!("\n* Slot: {}", equipment.slot));
+        // until the player knows about a blessing or curse, the gear looks like any other
+        let bonuses = if equipment.blessing_known {
+            [
+                ("Attack", equipment.power()),
+                ("Defense", equipment.defense()),
+                ("Maximum HP", equipment.max_hp()),
+            ]
+        } else {
+            [
+                ("Attack", equipment.power_bonus),
+                ("Defense", equipment.defense_bonus),
+                ("Maximum HP", equipment.hp_bonus),
+            ]
+        };
+        for (stat, bonus) in bonuses.iter().filter(|(_, bonus)| *bonus != 0) {
+            text.push_str(&format!("\n* {}: {:+}", stat, bonus));
+        }
+        if equipment.crit_bonus != 0.0 {
+            text.push_str(&format!(
+                "\n* Critical chance: {:+.0}%",
+                equipment.crit_bonus * 100.0
+            ));
+        }
+        if equipment.equipped {
+            text.push_str("\n* Currently equipped");
+        }
+        text.push('\n');
+    }
+
+    text.push_str(&format!("\n* Weight: {}\n", object.weight));
+    text
+}
+
+/// Uses an item from the inventory. Most items are free actions, but the ones that
+/// report `takes_turn` give the monsters their move afterwards.
+fn use_item(
+    inventory_id: usize,
+    objects: &mut Objects,
+    ui: &mut dyn Frontend,
+    game: &mut Game,
+) -> PlayerAction {
+    use Item::*;
+
+    // just call the "use_function" if it is defined
+    if let Some(item) = game.inventory[inventory_id].item {
+        let on_use = match item {
+            Heal | Lightning | Confuse | Fireball => cast_script,
+            Recall => cast_recall,
+            Identify => cast_identify,
+            RemoveCurse => cast_remove_curse,
+            Dig => cast_dig,
+            Muddle => drink_murky_potion,
+            Scripted => cast_script,
+            Boomerang => throw_boomerang,
+            // spent arrows can land on the floor, which needs the whole object list
+            Arrows => return fire_launcher(objects, game, ui),
+            Bow | Crossbow => toggle_equipment,
+            Dagger | Sword => toggle_equipment,
+            Shield => toggle_equipment,
+            LeatherArmor | Helmet | Boots | Crown => toggle_equipment,
+            Key(_) => {
+                game.log.add(constants::locks::USE_HINT, colors::WHITE);
+                return PlayerAction::DidntTakeTurn;
+            }
+            Amulet => {
+                game.log.add(constants::amulet::USE_HINT, colors::WHITE);
+                return PlayerAction::DidntTakeTurn;
+            }
+        };
+
+        let name = game.inventory[inventory_id].name.clone();
+        let looks = item_name(&game.inventory[inventory_id], game);
+        match on_use(inventory_id, objects, game, ui) {
+            UseResult::UsedUp => {
+                // use one up, and destroy the stack once it's empty
+                game.inventory[inventory_id].quantity -= 1;
+                if game.inventory[inventory_id].quantity <= 0 {
+                    game.inventory.remove(inventory_id);
+                }
+                learn_item(&name, &looks, game);
+                game.publish(GameEvent::ItemUsed { item: name });
+            }
+            UseResult::UsedAndKept => {
+                learn_item(&name, &looks, game);
+                game.publish(GameEvent::ItemUsed { item: name });
+            }
+            UseResult::Cancelled => {
+                game.log.add("Cancelled", colors::WHITE);
+                return PlayerAction::DidntTakeTurn;
+            }
+        }
+
+        if item.takes_turn() {
+            return PlayerAction::TookTurn;
+        }
+    } else {
+        game.log.add(
+            format!("The {} cannot be used.", game.inventory[inventory_id].name),
+            colors::WHITE,
+        );
+    }
+
+    PlayerAction::DidntTakeTurn
+}
+
+/// Finding out what an unidentified item does by using it names every one like it
+fn learn_item(name: &str, looks: &str, game: &mut Game) {
+    use constants::identify;
+
+    if game.identification.identify(name) {
+        game.log.add(
+            identify::create_learned_message(looks, name),
+            identify::LEARNED_COLOR,
+        );
+    }
+}
+
+fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Objects) {
+    if game.inventory[inventory_id].equipment.is_some()
+        && !game.inventory[inventory_id].dequip(&mut game.log)
+    {
+        return;
+    }
+    let mut item = game.inventory.remove(inventory_id);
+
+    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+
+    // the whole stack goes down together
+    game.log.add(
+        constants::gui::menus::inventory::create_drop_message(
+            &item_name(&item, game),
+            item.quantity,
+        ),
+        colors::YELLOW,
+    );
+
+    objects.insert(item);
+}
+
+/// Wraps up whatever the player just did. A turn spent moves the clock on and lets the
+/// monsters respond. Either way anything killed drops its loot and a level earned is
+/// granted, since spells that don't spend a turn can still kill.
+fn finish_action(took_turn: bool, objects: &mut Objects, game: &mut Game, ui: &mut dyn Frontend) {
+    if took_turn {
+        game.turns += 1;
+        game.level_up_postponed = false;
+        wear_off_scramble(game);
+        regenerate_mana(objects, game);
+        game.publish(GameEvent::TurnEnded { turn: game.turns });
+
+        if objects[PLAYER].alive {
+            monsters_respond(objects, ui, game);
+
+            let player = &objects[PLAYER];
+            let hp = player.fighter.map_or(0, |f| f.hp);
+            let max_hp = player.max_hp(game);
+            game.performance.observe_hp(hp, max_hp);
+        }
+    }
+
+    drop_loot(objects, game);
+
+    if !game.level_up_postponed {
+        level_up(objects, game, ui);
+    }
+}
+
+/// Counts down the murky potion's scrambled movement, setting the keys straight once
+/// it runs out
+fn wear_off_scramble(game: &mut Game) {
+    use constants::consumables::potions::murky;
+
+    if let Some(scramble) = game.scrambled.as_mut() {
+        scramble.turns_left -= 1;
+        if scramble.turns_left <= 0 {
+            game.scrambled = None;
+            game.log.add(murky::WORN_OFF, colors::LIGHT_GREY);
+        }
+    }
+}
+
+fn regenerate_mana(objects: &Objects, game: &mut Game) {
+    use constants::spells;
+
+    if game.turns.is_multiple_of(spells::REGEN_INTERVAL) {
+        game.mana = cmp::min(game.mana + 1, game.class.max_mana(objects[PLAYER].level));
+    }
+}
+
+/// Everything the monsters do in answer to one player turn. They act in rounds for as
+/// long as any has the energy, so a fast monster gets a second move after everyone had
+/// their first, and a slow one sits out every other turn.
+fn monsters_respond(objects: &mut Objects, ui: &mut dyn Frontend, game: &mut Game) {
+    gain_energy(objects, game);
+    while monsters_take_turn(objects, ui, game) {}
+
+    // effects tick at the end of the turn, so even a single turn of stun costs a move
+    tick_effects(objects, ui, game);
+}
+
+/// Applies a turn of every active status effect: damage over time first, then the
+/// countdown. Only the player's own and visible monsters' effects are announced.
+fn tick_effects(objects: &mut Objects, ui: &dyn Frontend, game: &mut Game) {
+    use constants::effects as messages;
+
+    for id in objects.ids() {
+        if !objects.contains(id) || objects[id].fighter.is_none() || objects[id].effects.is_empty()
+        {
+            continue;
+        }
+        let announce = id == PLAYER || ui.fov().is_in_fov(objects[id].x, objects[id].y);
+
+        let damaging: Vec<(EffectKind, i32)> = objects[id]
+            .effects
+            .iter()
+            .map(|effect| (effect.kind, effect.kind.damage_per_turn()))
+            .filter(|&(_, damage)| damage > 0)
+            .collect();
+        for (kind, damage) in damaging {
+            if announce {
+                game.log.add(
+                    messages::create_damage_message(&objects[id].name, kind, damage),
+                    messages::color(kind),
+                );
+            }
+            if id == PLAYER {
+                game.hurt_by = Some(constants::effects::damage_source(kind).to_string());
+            }
+            if let Some(xp) = objects[id].take_damage(damage, true, game) {
+                // whatever put the effect on a monster, it was the player's doing
+                if id != PLAYER {
+                    objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+                }
+                break;
+            }
+        }
+
+        for kind in effects::tick(&mut objects[id].effects) {
+            if announce {
+                game.log.add(
+                    messages::create_expired_message(&objects[id].name, kind),
+                    colors::LIGHT_GREY,
+                );
+            }
+        }
+    }
+}
+
+fn monster_in_view(objects: &Objects, ui: &dyn Frontend) -> bool {
+    objects
+        .iter()
+        .any(|object| object.ai.is_some() && object.alive && ui.fov().is_in_fov(object.x, object.y))
+}
+
+/// Stands still for up to `turns` turns, stopping early once a monster comes into
+/// view or the player gets hurt
+fn wait_turns(turns: u32, objects: &mut Objects, ui: &mut dyn Frontend, game: &mut Game) {
+    use constants::gui::menus::wait;
+
+    if monster_in_view(objects, ui) {
+        game.log.add(wait::ENEMY_IN_VIEW, colors::RED);
+        return;
+    }
+
+    let hp = |objects: &Objects| objects[PLAYER].fighter.map_or(0, |f| f.hp);
+    for waited in 1..=turns {
+        let hp_before = hp(objects);
+        finish_action(true, objects, game, ui);
+
+        if !objects[PLAYER].alive {
+            return;
+        }
+        if hp(objects) < hp_before || monster_in_view(objects, ui) {
+            game.log
+                .add(wait::create_interrupted_message(waited), colors::YELLOW);
+            return;
+        }
+    }
+
+    game.log
+        .add(wait::create_done_message(turns), colors::LIGHT_GREY);
+}
+
+/// Plans a walk to the clicked tile, which play_game then takes one turn at a time
+fn start_travel((x, y): (i32, i32), objects: &Objects, game: &mut Game, ui: &dyn Frontend) {
+    use constants::travel;
+
+    if !game.map[x as usize][y as usize].explored || (x, y) == objects[PLAYER].pos() {
+        return;
+    }
+
+    if monster_in_view(objects, ui) {
+        game.log.add(travel::ENEMY_IN_VIEW, colors::RED);
+        return;
+    }
+
+    match map::find_explored_path(&game.map, objects[PLAYER].pos(), (x, y)) {
+        Some(path) => game.travel = path,
+        None => game.log.add(travel::NO_PATH, colors::LIGHT_GREY),
+    }
+}
+
+/// Takes the next step of a planned walk, giving up as soon as an enemy shows up or
+/// something stands in the way
+fn travel_step(game: &mut Game, objects: &mut Objects, ui: &mut dyn Frontend) -> PlayerAction {
+    use constants::travel;
+
+    if monster_in_view(objects, ui) {
+        game.travel.clear();
+        game.log.add(travel::INTERRUPTED, colors::YELLOW);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    let next = game.travel.remove(0);
+    if is_blocked(next.0, next.1, &game.map, objects) {
+        game.travel.clear();
+        game.log.add(travel::BLOCKED, colors::LIGHT_GREY);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    let (x, y) = objects[PLAYER].pos();
+    player_move_or_attack(next.0 - x, next.1 - y, game, objects, ui);
+
+    // scrambled directions can send the player somewhere else, which ends the walk
+    if objects[PLAYER].pos() != next {
+        game.travel.clear();
+    }
+
+    PlayerAction::TookTurn
+}
+
+/// Every visible monster within range of the player
+fn monsters_in_range(max_range: i32, objects: &Objects, ui: &dyn Frontend) -> Vec<EntityId> {
+    objects
+        .entries()
+        .filter(|&(id, object)| {
+            id != PLAYER
+                && object.fighter.is_some()
+                && object.ai.is_some()
+                && ui.fov().is_in_fov(object.x, object.y)
+                && objects[PLAYER].distance_to(object) <= max_range as f32
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// return the position of a tile the player picks in their FOV (optionally in a
+/// range), or None if they back out
+fn target_tile(
+    ui: &mut dyn Frontend,
+    objects: &Objects,
+    game: &mut Game,
+    max_range: Option<f32>,
+    blast_radius: Option<i32>,
+) -> Option<(i32, i32)> {
+    let target = ui.pick_tile(objects, game, max_range, blast_radius);
+    ui.replay_mut().record(Input::Target(target));
+    target
+}
+
+/// return the position of a tile left-clicked in player's FOV (optionally in a
+/// range), or (None,None) if right-clicked. The reachable tiles are tinted while
+/// aiming, and so is the blast around the tile under the mouse for area spells.
+fn aim_with_mouse(
+    tcod: &mut Tcod,
+    objects: &Objects,
+    game: &mut Game,
+    max_range: Option<f32>,
+    blast_radius: Option<i32>,
+) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::Escape;
+
+    // the FOV doesn't change while aiming, so neither do the tiles in reach
+    render_all(tcod, objects, game);
+    let player_pos = objects[PLAYER].pos();
+    let in_reach: Vec<(i32, i32)> = match max_range {
+        Some(range) => tiles_within(player_pos, range.floor() as i32)
+            .into_iter()
+            .filter(|&(x, y)| {
+                tcod.fov.is_in_fov(x, y)
+                    && objects[PLAYER].distance(x, y) <= range
+                    && map::has_line_of_sight(player_pos, (x, y), &game.map)
+            })
+            .collect(),
+        None => vec![],
+    };
+
+    // a replay aims wherever the player did
+    if tcod.replay.is_playing() {
+        thread::sleep(tcod.replay.delay());
+        return tcod.replay.next_target();
+    }
+
+    let target = loop {
+        // tint the blast of the tile being aimed at over the reachable tiles
+        let (x, y) = mouse_tile(tcod).unwrap_or((-1, -1));
+        tcod.overlay = in_reach
+            .iter()
+            .map(|&tile| (tile, constants::gui::RANGE_COLOR))
+            .collect();
+        if let Some(radius) = blast_radius {
+            if x >= 0 && tcod.fov.is_in_fov(x, y) {
+                let blast = tiles_within((x, y), radius).into_iter().filter(|&tile| {
+                    !game.map[tile.0 as usize][tile.1 as usize].blocked
+                        && distance(tile, (x, y)) <= radius as f32
+                        && map::has_line_of_sight((x, y), tile, &game.map)
+                });
+                tcod.overlay
+                    .extend(blast.map(|tile| (tile, constants::gui::BLAST_COLOR)));
+            }
+        }
+
+        // render the screen. This erases the inventory and shows the names opf objects under the mouse.
+        tcod.root.flush();
+        let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
+        let mut key = None;
+        match event {
+            Some(Event::Mouse(m)) => tcod.mouse = m,
+            Some(Event::Key(k)) => key = Some(k),
+            None => {}
+        }
+
+        render_all(tcod, objects, game);
+
+        // accept the target if the played clicked in FOV and in case a range is specified, if it's in that range
+        let (x, y) = mouse_tile(tcod).unwrap_or((-1, -1));
+        let in_fov = x >= 0 && tcod.fov.is_in_fov(x, y);
+        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
+        let in_sight = in_fov && map::has_line_of_sight(objects[PLAYER].pos(), (x, y), &game.map);
+
+        if tcod.mouse.lbutton_pressed && in_sight && in_range {
+            break Some((x, y));
+        }
+
+        let escape = key.map_or(false, |k| k.code == Escape);
+        if tcod.mouse.rbutton_pressed || escape {
+            break None;
+        }
+    };
+
+    tcod.overlay.clear();
+    target
+}
+
+/// Every tile on the map in the square reaching `radius` tiles out from `center`
+fn tiles_within((center_x, center_y): (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+    use constants::gui::{MAP_HEIGHT, MAP_WIDTH};
+
+    let xs = cmp::max(center_x - radius, 0)..=cmp::min(center_x + radius, MAP_WIDTH - 1);
+    xs.flat_map(|x| {
+        (cmp::max(center_y - radius, 0)..=cmp::min(center_y + radius, MAP_HEIGHT - 1))
+            .map(move |y| (x, y))
+    })
+    .collect()
+}
+
+fn distance((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> f32 {
+    (((x1 - x2).pow(2) + (y1 - y2).pow(2)) as f32).sqrt()
+}
+
+fn target_monster(
+    ui: &mut dyn Frontend,
+    objects: &Objects,
+    game: &mut Game,
+    max_range: Option<f32>,
+) -> Option<EntityId> {
+    loop {
+        match target_tile(ui, objects, game, max_range, None) {
+            Some((x, y)) => {
+                // return the first clicked monster, otherwise continue looping
+                for (id, obj) in objects.entries() {
+                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
+                        return Some(id);
+                    }
+                }
+            }
+            None => return None,
+        }
+    }
+}
+
+/// Casts a spell through the same effect as its scroll. Mana is only spent once the
+/// spell actually goes off, backing out of the targeting costs nothing.
+fn cast_spell(
+    spell: Spell,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> PlayerAction {
+    use constants::spells;
+
+    if game.mana < spell.mana_cost() {
+        game.log
+            .add(spells::create_no_mana_message(spell.name()), colors::RED);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    let effect = match spell {
+        Spell::Lightning => Item::Lightning,
+        Spell::Confuse => Item::Confuse,
+        Spell::Fireball => Item::Fireball,
+    };
+
+    match run_effect(effect, spell.name(), objects, game, ui) {
+        UseResult::Cancelled => {
+            game.log.add("Cancelled", colors::WHITE);
+            PlayerAction::DidntTakeTurn
+        }
+        UseResult::UsedUp | UseResult::UsedAndKept => {
+            game.mana -= spell.mana_cost();
+            game.publish(GameEvent::ItemUsed {
+                item: spell.name().to_string(),
+            });
+            PlayerAction::TookTurn
+        }
+    }
+}
+
+/// The scrolls and spells whose effects are built-in scripts, with the numbers those
+/// scripts are run with. Anything else has its effect written out in Rust.
+fn built_in_script(item: Item) -> Option<(&'static str, Vec<(&'static str, Value)>)> {
+    use constants::consumables::scrolls::fireball;
+    use constants::scripts::built_in;
+
+    let script = match item {
+        Item::Heal => (built_in::HEAL, vec![("AMOUNT", Value::Int(HEAL_AMOUNT))]),
+        Item::Lightning => (
+            built_in::LIGHTNING,
+            vec![
+                ("RANGE", Value::Int(LIGHTNING_RANGE)),
+                ("DAMAGE", Value::Int(LIGHTNING_DAMAGE)),
+                (
+                    "STUN_TURNS",
+                    Value::Int(constants::effects::LIGHTNING_STUN_TURNS),
+                ),
+            ],
+        ),
+        Item::Confuse => (
+            built_in::CONFUSE,
+            vec![
+                ("RANGE", Value::Int(CONFUSE_RANGE)),
+                ("TURNS", Value::Int(CONFUSE_NUM_TURNS)),
+            ],
+        ),
+        Item::Fireball => (
+            built_in::FIREBALL,
+            vec![
+                ("RADIUS", Value::Int(fireball::RADIUS)),
+                ("DAMAGE", Value::Int(fireball::DAMAGE)),
+                (
+                    "BURN_TURNS",
+                    Value::Int(constants::effects::FIREBALL_BURN_TURNS),
+                ),
+                ("HITS_CASTER", Value::Bool(fireball::HITS_CASTER)),
+            ],
+        ),
+        _ => return None,
+    };
+    Some(script)
+}
+
+/// Uses a scroll or potion whose effect is a script, built in or from the item scripts
+/// file. One whose script has gone missing from the file does nothing and is kept.
+fn cast_script(
+    inventory_id: usize,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    let item = &game.inventory[inventory_id];
+    let kind = item.item.unwrap_or(Item::Scripted);
+    let name = item.name.clone();
+    let definition = item
+        .script
+        .as_ref()
+        .and_then(|name| scripts::find(&game.scripts, name))
+        .map(|definition| definition.compile());
+
+    match definition {
+        Some(Ok(script)) => run_script(&script, &[], &name, objects, game, ui),
+        _ => run_effect(kind, &name, objects, game, ui),
+    }
+}
+
+/// Runs the built-in script behind an item's effect, the one way scrolls and spells
+/// alike go off
+fn run_effect(
+    item: Item,
+    name: &str,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    let (source, globals) = match built_in_script(item) {
+        Some(script) => script,
+        None => {
+            game.log.add(constants::scripts::MISSING, colors::RED);
+            return UseResult::Cancelled;
+        }
+    };
+    let script = Script::compile(source).expect("built-in scripts are valid");
+    // a fireball is the one that can catch the player in it
+    let cause = match item {
+        Item::Fireball => constants::morgue::OWN_FIREBALL,
+        _ => name,
+    };
+    run_script(&script, &globals, cause, objects, game, ui)
+}
+
+/// Carries out a script. Backing out of picking a target cancels it before it does
+/// anything more, and one that goes wrong partway stops there but is still used up.
+fn run_script(
+    script: &Script,
+    globals: &[(&str, Value)],
+    cause: &str,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    let mut host = ScriptHost {
+        objects,
+        game,
+        ui,
+        cause,
+    };
+    let result = script.run(globals, &mut host);
+    host.ui.highlight(vec![]);
+
+    match result {
+        Ok(()) => UseResult::UsedUp,
+        Err(Halt::Cancelled) => UseResult::Cancelled,
+        Err(Halt::Error(error)) => {
+            host.game.log.add(
+                constants::scripts::create_failed_message(&error),
+                constants::scripts::FAILED_COLOR,
+            );
+            UseResult::UsedUp
+        }
+    }
+}
+
+/// What a script gets to see and do of the game while it runs
+struct ScriptHost<'a> {
+    objects: &'a mut Objects,
+    game: &'a mut Game,
+    ui: &'a mut dyn Frontend,
+    // what the morgue blames if the script kills the player
+    cause: &'a str,
+}
+
+/// The creature a script means, unless it has died or was never one to fight
+fn script_target(objects: &Objects, value: &Value) -> Result<Option<EntityId>, Halt> {
+    let id = value.as_entity()?;
+    let fighting = objects
+        .get(id)
+        .is_some_and(|target| target.alive && target.fighter.is_some());
+    Ok(if fighting { Some(id) } else { None })
+}
+
+impl Host for ScriptHost<'_> {
+    fn call(&mut self, function: &str, args: &[Value]) -> Result<Value, Halt> {
+        use constants::scripts::errors;
+
+        let (objects, game) = (&mut *self.objects, &mut *self.game);
+        let value = match function {
+            "player" => {
+                scripts::check_args(function, args, 0, 0)?;
+                Value::Entity(PLAYER)
+            }
+            "monsters_in_range" => {
+                scripts::check_args(function, args, 1, 1)?;
+                let monsters = monsters_in_range(args[0].as_int()?, objects, self.ui);
+                Value::List(monsters.into_iter().map(Value::Entity).collect())
+            }
+            "nearest_monster" => {
+                scripts::check_args(function, args, 1, 1)?;
+                let player = &objects[PLAYER];
+                monsters_in_range(args[0].as_int()?, objects, self.ui)
+                    .into_iter()
+                    .min_by_key(|&id| player.distance_to(&objects[id]) as i32)
+                    .map_or(Value::Unit, Value::Entity)
+            }
+            "pick_monster" => {
+                scripts::check_args(function, args, 1, 1)?;
+                let range = Some(args[0].as_int()? as f32);
+                match target_monster(self.ui, objects, game, range) {
+                    Some(id) => Value::Entity(id),
+                    None => return Err(Halt::Cancelled),
+                }
+            }
+            // one of the creatures given, which are picked out on the map meanwhile
+            "choose" => {
+                scripts::check_args(function, args, 2, 2)?;
+                let choices = match &args[0] {
+                    Value::List(choices) => choices.clone(),
+                    other => vec![other.clone()],
+                };
+                let range = Some(args[1].as_int()? as f32);
+                let mut tiles = vec![];
+                for choice in &choices {
+                    if let Some(target) = objects.get(choice.as_entity()?) {
+                        tiles.push(target.pos());
+                    }
+                }
+                self.ui.highlight(tiles);
+                loop {
+                    match target_monster(self.ui, objects, game, range) {
+                        Some(id) if choices.contains(&Value::Entity(id)) => {
+                            self.ui.highlight(vec![]);
+                            break Value::Entity(id);
+                        }
+                        Some(_) => continue,
+                        None => return Err(Halt::Cancelled),
+                    }
+                }
+            }
+            // everything standing in the blast around a tile the player picks, walls
+            // shielding anything behind them
+            "pick_area" => {
+                scripts::check_args(function, args, 1, 2)?;
+                let radius = args[0].as_int()?;
+                let range = match args.get(1) {
+                    Some(range) => Some(range.as_int()? as f32),
+                    None => None,
+                };
+                let (x, y) = match target_tile(self.ui, objects, game, range, Some(radius)) {
+                    Some(tile) => tile,
+                    None => return Err(Halt::Cancelled),
+                };
+                let caught = objects
+                    .entries()
+                    .filter(|&(_, object)| {
+                        object.fighter.is_some()
+                            && object.distance(x, y) <= radius as f32
+                            && map::has_line_of_sight((x, y), object.pos(), &game.map)
+                    })
+                    .map(|(id, _)| Value::Entity(id))
+                    .collect();
+                Value::List(caught)
+            }
+            "confirm" => {
+                scripts::check_args(function, args, 1, 1)?;
+                Value::Bool(confirm(args[0].as_str()?, self.ui))
+            }
+            // whether the hit killed it
+            "damage" => {
+                scripts::check_args(function, args, 2, 2)?;
+                let amount = args[1].as_int()?;
+                let id = match script_target(objects, &args[0])? {
+                    Some(id) => id,
+                    None => return Ok(Value::Bool(false)),
+                };
+                if id == PLAYER {
+                    game.hurt_by = Some(self.cause.to_string());
+                }
+                let killed = match objects[id].take_damage(amount, true, game) {
+                    Some(xp) => {
+                        if id != PLAYER {
+                            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+                        }
+                        true
+                    }
+                    None => false,
+                };
+                Value::Bool(killed)
+            }
+            "heal" => {
+                scripts::check_args(function, args, 2, 2)?;
+                let amount = args[1].as_int()?;
+                if let Some(id) = script_target(objects, &args[0])? {
+                    objects[id].heal(amount, game);
+                }
+                Value::Unit
+            }
+            "status" => {
+                scripts::check_args(function, args, 3, 3)?;
+                let name = args[1].as_str()?;
+                let effect: EffectKind = serde_json::from_value(name.into())
+                    .map_err(|_| Halt::Error(errors::create_unknown_effect_error(name)))?;
+                let turns = args[2].as_int()?;
+                if let Some(id) = script_target(objects, &args[0])? {
+                    objects[id].add_effect(effect, turns, game);
+                }
+                Value::Unit
+            }
+            "message" => {
+                scripts::check_args(function, args, 1, 2)?;
+                let color = match args.get(1) {
+                    Some(name) => {
+                        let name = name.as_str()?;
+                        constants::scripts::COLORS
+                            .iter()
+                            .find(|&&(color, _)| color == name)
+                            .map(|&(_, color)| color)
+                            .ok_or_else(|| Halt::Error(errors::create_unknown_color_error(name)))?
+                    }
+                    None => constants::scripts::MESSAGE_COLOR,
+                };
+                game.log.add(args[0].to_string(), color);
+                Value::Unit
+            }
+            "name" => {
+                scripts::check_args(function, args, 1, 1)?;
+                let name = objects
+                    .get(args[0].as_entity()?)
+                    .map_or("", |target| target.name.as_str());
+                Value::Str(name.to_string())
+            }
+            "hp" | "max_hp" => {
+                scripts::check_args(function, args, 1, 1)?;
+                let hp = match objects.get(args[0].as_entity()?) {
+                    Some(target) if function == "max_hp" => target.max_hp(game),
+                    Some(target) => target.fighter.map_or(0, |fighter| fighter.hp),
+                    None => 0,
+                };
+                Value::Int(hp)
+            }
+            "alive" => {
+                scripts::check_args(function, args, 1, 1)?;
+                Value::Bool(script_target(objects, &args[0])?.is_some())
+            }
+            // a whole number from the low to the high one, both included
+            "random" => {
+                scripts::check_args(function, args, 2, 2)?;
+                let (low, high) = (args[0].as_int()?, args[1].as_int()?);
+                if high < low {
+                    Value::Int(low)
+                } else {
+                    Value::Int(game.rng.gen_range(low, high + 1))
+                }
+            }
+            "cancel" => {
+                scripts::check_args(function, args, 0, 0)?;
+                return Err(Halt::Cancelled);
+            }
+            _ => return Err(Halt::Error(errors::create_unknown_function_error(function))),
+        };
+        Ok(value)
+    }
+}
+
+/// Names one unidentified item the player carries, of their choosing, or tells whether
+/// a piece of gear is cursed
+fn cast_identify(
+    inventory_id: usize,
+    _objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    use constants::identify;
+
+    // the scroll itself gets identified by being read, it doesn't need choosing
+    let own_name = game.inventory[inventory_id].name.clone();
+    let unknown: Vec<usize> = (0..game.inventory.len())
+        .filter(|&index| {
+            let item = &game.inventory[index];
+            let unknown_blessing = matches!(item.equipment, Some(e) if !e.blessing_known);
+            unknown_blessing || (item.name != own_name && !game.identification.is_known(&item.name))
+        })
+        .collect();
+    if unknown.is_empty() {
+        game.log.add(identify::NOTHING_TO_IDENTIFY, colors::RED);
+        return UseResult::Cancelled;
+    }
+
+    let options: Vec<String> = unknown
+        .iter()
+        .map(|&index| inventory_label(&game.inventory[index], game))
+        .collect();
+    let choice = menu(
+        identify::HEADER,
+        &options,
+        constants::gui::INVENTORY_WIDTH,
+        ui,
+    );
+
+    match choice {
+        Some(choice) => {
+            let item = &mut game.inventory[unknown[choice]];
+            if let Some(equipment) = item.equipment.as_mut() {
+                equipment.blessing_known = true;
+                let message = constants::curses::create_identified_message(
+                    &item.name,
+                    equipment.blessing.name(),
+                );
+                game.log.add(message, identify::LEARNED_COLOR);
+                return UseResult::UsedUp;
+            }
+            let item = &game.inventory[unknown[choice]];
+            let (name, looks) = (item.name.clone(), item_name(item, game));
+            learn_item(&name, &looks, game);
+            UseResult::UsedUp
+        }
+        None => UseResult::Cancelled,
+    }
+}
+
+/// Uncurses all the gear the player carries. The scroll is read up even with nothing
+/// cursed, since the player can't tell that beforehand.
+fn cast_remove_curse(
+    _inventory_id: usize,
+    _objects: &mut Objects,
+    game: &mut Game,
+    _ui: &mut dyn Frontend,
+) -> UseResult {
+    use constants::consumables::scrolls::remove_curse;
+
+    let mut lifted = 0;
+    for equipment in game
+        .inventory
+        .iter_mut()
+        .filter_map(|item| item.equipment.as_mut())
+    {
+        if equipment.blessing == Blessing::Cursed {
+            equipment.blessing = Blessing::Uncursed;
+            equipment.blessing_known = true;
+            lifted += 1;
+        }
+    }
+
+    if lifted == 0 {
+        game.log
+            .add(remove_curse::NOTHING_CURSED, remove_curse::COLOR);
+    } else {
+        game.log.add(
+            remove_curse::create_lifted_message(lifted),
+            remove_curse::COLOR,
+        );
+    }
+    UseResult::UsedUp
+}
+
+/// Read in the dungeon, carries the player up to town and remembers where they left.
+/// Read in town, takes them back down to that spot.
+fn cast_recall(
+    _inventory_id: usize,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    use constants::consumables::scrolls::recall;
+
+    if !game.dungeon_level.is_town() {
+        game.recall_return = Some((game.dungeon_level, objects[PLAYER].pos()));
+        change_floor(DungeonLevel::main(0), ui, objects, game);
+        // coming from a branch there are no stairs of its own to arrive on in town
+        let stairs = objects
+            .iter()
+            .skip(1)
+            .find(|object| object.name == "stairs")
+            .map(|object| object.pos());
+        if let Some((x, y)) = stairs {
+            objects[PLAYER].set_pos(x, y);
+        }
+        game.log.add(recall::TO_TOWN, recall::COLOR);
+        return UseResult::UsedUp;
+    }
+
+    let (level, (x, y)) = match game.recall_return {
+        Some(mark) => mark,
+        None => {
+            game.log.add(recall::NOWHERE_TO_RETURN, colors::RED);
+            return UseResult::Cancelled;
+        }
+    };
+    if carrying_amulet(game) {
+        game.log
+            .add(constants::amulet::ONLY_UP, constants::amulet::COLOR);
+        return UseResult::Cancelled;
+    }
+
+    change_floor(level, ui, objects, game);
+    game.recall_return = None;
+    // something standing on the spot since leaves the player on the stairs instead
+    if !is_blocked(x, y, &game.map, objects) {
+        objects[PLAYER].set_pos(x, y);
+    }
+    game.log.add(recall::BACK_DOWN, recall::COLOR);
+    UseResult::UsedUp
+}
+
+fn drink_murky_potion(
+    _inventory_id: usize,
+    _objects: &mut Objects,
+    game: &mut Game,
+    _ui: &mut dyn Frontend,
+) -> UseResult {
+    use constants::consumables::potions::murky;
+
+    let steps = murky::ROTATIONS[game.rng.gen_range(0, murky::ROTATIONS.len())];
+    game.scrambled = Some(Scramble {
+        steps,
+        turns_left: murky::NUM_TURNS,
+    });
+    game.log.add(murky::DRUNK, murky::COLOR);
+
+    UseResult::UsedUp
+}
+
+fn cast_dig(
+    _inventory_id: usize,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    use constants::consumables::wands::digging;
+
+    game.log
+        .add(digging::INSTRUCTIONS, digging::INSTRUCTION_COLOR);
+
+    // only the tiles right next to the player are in reach
+    let (x, y) = match target_tile(ui, objects, game, Some(digging::RANGE), None) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+
+    if is_map_boundary(x, y) {
+        game.log.add(digging::BOUNDARY, colors::RED);
+        return UseResult::Cancelled;
+    }
+
+    if !game.map[x as usize][y as usize].cracked {
+        game.log.add(digging::TOO_SOLID, colors::RED);
+        return UseResult::Cancelled;
+    }
+
+    game.map[x as usize][y as usize] = Tile::empty();
+    ui.fov_mut().set(x, y, true, true);
+    game.log.add(digging::CRUMBLES, digging::COLOR);
+
+    // the wand isn't spent, there are only so many cracked walls to use it on
+    UseResult::UsedAndKept
+}
+
+fn throw_boomerang(
+    _inventory_id: usize,
+    objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    use constants::gear::boomerang;
+
+    game.log
+        .add(boomerang::INSTRUCTIONS, boomerang::INSTRUCTION_COLOR);
+
+    let target = match target_tile(ui, objects, game, Some(boomerang::RANGE), None) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+
+    // the first fighter along the flight path takes the hit, anything behind it is spared
+    let hit_id = map::line_between(objects[PLAYER].pos(), target)
+        .into_iter()
+        .find_map(|tile| objects.find(|object| object.pos() == tile && object.fighter.is_some()));
+
+    match hit_id {
+        Some(monster_id) => {
+            game.log.add(
+                boomerang::create_hit_message(&objects[monster_id].name),
+                boomerang::COLOR,
+            );
+            if let Some(xp) = objects[monster_id].take_damage(boomerang::DAMAGE, true, game) {
+                objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+            }
+        }
+        None => game.log.add(boomerang::MISS, colors::LIGHT_GREY),
+    }
+
+    // it always comes back, so it stays in the inventory
+    UseResult::UsedAndKept
+}
+
+/// Shoots one arrow from the wielded launcher at a monster in range and in sight.
+/// The arrow is used up, but sometimes lands intact under the target.
+fn fire_launcher(objects: &mut Objects, game: &mut Game, ui: &mut dyn Frontend) -> PlayerAction {
+    use constants::gear::arrows;
+
+    let launcher = game.inventory.iter().find(|item| {
+        matches!(item.equipment, Some(equipment) if equipment.equipped)
+            && item.item.and_then(Item::launcher_stats).is_some()
+    });
+    let (launcher_name, (range, damage)) = match launcher {
+        Some(launcher) => (
+            launcher.name.clone(),
+            launcher.item.and_then(Item::launcher_stats).unwrap(),
+        ),
+        None => {
+            game.log.add(arrows::NO_LAUNCHER, colors::WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+    };
+
+    let quiver = match game
+        .inventory
+        .iter()
+        .position(|item| item.item == Some(Item::Arrows))
+    {
+        Some(quiver) => quiver,
+        None => {
+            game.log.add(arrows::NO_AMMO, colors::WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+    };
+
+    game.log
+        .add(arrows::INSTRUCTIONS, arrows::INSTRUCTION_COLOR);
+    let monster_id = match target_monster(ui, objects, game, Some(range)) {
+        Some(monster_id) => monster_id,
+        None => {
+            game.log.add("Cancelled", colors::WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+    };
+
+    game.inventory[quiver].quantity -= 1;
+    game.publish(GameEvent::ItemUsed {
+        item: game.inventory[quiver].name.clone(),
+    });
+    if game.inventory[quiver].quantity == 0 {
+        game.inventory.remove(quiver);
+    }
+
+    let (x, y) = objects[monster_id].pos();
+    game.log.add(
+        arrows::create_hit_message(&launcher_name, &objects[monster_id].name, damage),
+        arrows::COLOR,
+    );
+    if let Some(xp) = objects[monster_id].take_damage(damage, true, game) {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+    }
+
+    if game.rng.gen::<f32>() < arrows::RECOVER_CHANCE {
+        // land on any arrows already lying there rather than starting a new pile
+        match objects.find(|object| object.pos() == (x, y) && object.item == Some(Item::Arrows)) {
+            Some(stack_id) => objects[stack_id].quantity += 1,
+            None => {
+                let mut arrow = create_item(Item::Arrows, x, y);
+                arrow.quantity = 1;
+                objects.insert(arrow);
+            }
+        }
+    }
+
+    PlayerAction::TookTurn
+}
+
+fn toggle_equipment(
+    inventory_id: usize,
+    _objects: &mut Objects,
+    game: &mut Game,
+    ui: &mut dyn Frontend,
+) -> UseResult {
+    use constants::gui::menus::inventory;
+
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+
+    if equipment.equipped {
+        if !game.inventory[inventory_id].dequip(&mut game.log) {
+            return UseResult::Cancelled;
+        }
+    } else {
+        if let Some(old_equipment) = get_equipped_in_slot(equipment.slot, game) {
+            let old_item = &game.inventory[old_equipment];
+            if matches!(old_item.equipment, Some(old) if equipment.is_strictly_worse_than(&old)) {
+                let question = inventory::create_downgrade_question(
+                    &game.inventory[inventory_id].name,
+                    &old_item.name,
+                );
+                if !confirm(&question, ui) {
+                    return UseResult::Cancelled;
+                }
+            }
+
+            if !game.inventory[old_equipment].dequip(&mut game.log) {
+                return UseResult::Cancelled;
+            }
+        }
+
+        game.inventory[inventory_id].equip(&mut game.log);
+    }
+
+    UseResult::UsedAndKept
+}
+
+fn get_equipped_in_slot(slot: Slot, game: &Game) -> Option<usize> {
+    for (inventory_id, item) in game.inventory.iter().enumerate() {
+        if item
+            .equipment
+            .as_ref()
+            .map_or(false, |e| e.equipped && e.slot == slot)
+        {
+            return Some(inventory_id);
+        }
+    }
+    None
+}
+
+/// Returns a vaue that depends on current dungeon level. The table specifies what
+/// value occurs at each level, the default is 0
+fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
+/// Go down to the given level, the one below or the top of a branch
+fn next_level(level: DungeonLevel, ui: &mut dyn Frontend, objects: &mut Objects, game: &mut Game) {
+    // settle every level the player has earned before the floor changes underneath them
+    while level_up(objects, game, ui) {}
+
+    let first_visit = !is_floor_stored(level, game);
+    game.publish(GameEvent::StairsDescended {
+        branch: level.branch,
+        dungeon_level: level.depth,
+        first_visit,
+    });
+
+    // only a fresh floor is worth resting up for, and says anything about how the last
+    // one went
+    if first_visit {
+        adjust_difficulty(game, ui);
+
+        let player = &mut objects[PLAYER];
+        let heal_hp = player.max_hp(game) / 2;
+        player.heal(heal_hp, game);
+    }
+
+    change_floor(level, ui, objects, game);
+
+    if ui.settings().autosave_turns > 0 {
+        autosave(objects, game, ui);
+    }
+}
+
+/// Saves the run without leaving it, so a crash only loses the turns since. A failed
+/// autosave just gets a warning, the game goes on either way.
+fn autosave(objects: &Objects, game: &mut Game, ui: &dyn Frontend) {
+    game.autosaved_at = game.turns;
+    if save_run(objects, game, ui).is_err() {
+        game.log.add(constants::AUTOSAVE_FAILED, colors::RED);
+    }
+}
+
+/// Climb the up stairs to the floor above, which is town from the first floor
+fn previous_level(ui: &mut dyn Frontend, objects: &mut Objects, game: &mut Game) {
+    use constants::gui::menus::next_level;
+
+    let above = game.dungeon_level.above();
+    if !above.is_town() {
+        game.log
+            .add(next_level::CLIMB_LOG_MESSAGE, next_level::ON_STAIRS_COLOR);
+    } else if carrying_amulet(game) {
+        // out of the dungeon with the Amulet, the run is won
+        game.won = true;
+    }
+    change_floor(above, ui, objects, game);
+}
+
+/// Moves the player to another floor. One they've been on before comes back the way
+/// they left it, anything else is generated. Either way they arrive on the stairs
+/// leading back where they came from.
+fn change_floor(
+    level: DungeonLevel,
+    ui: &mut dyn Frontend,
+    objects: &mut Objects,
+    game: &mut Game,
+) {
+    use constants::gui::menus::next_level;
+
+    let previous = game.dungeon_level;
+    let descending = level.absolute_depth() > previous.absolute_depth();
+    store_floor(objects, game);
+    game.dungeon_level = level;
+    game.publish(GameEvent::FloorChanged {
+        branch: level.branch,
+        dungeon_level: level.depth,
+    });
+
+    if !restore_floor(level, objects, game) {
+        generate_floor(level, ui, objects, game);
+    }
+    if level.is_town() {
+        game.log.add(
+            constants::town::ARRIVAL_MESSAGE,
+            constants::town::ARRIVAL_COLOR,
+        );
+    }
+    if level.branch != previous.branch {
+        if let Some(message) = level.branch.arrival_message() {
+            game.log.add(message, constants::branches::ARRIVAL_COLOR);
+        }
+    }
+
+    // climbing out of a branch comes up its own stairs rather than the main ones
+    let arrival = if descending {
+        next_level::UP_STAIRS_NAME.to_string()
+    } else if level.branch != previous.branch {
+        constants::branches::create_stairs_name(previous.branch.name())
+    } else {
+        "stairs".to_string()
+    };
+    let stairs = objects
+        .iter()
+        .skip(1)
+        .find(|object| object.name == arrival)
+        .map(|object| object.pos());
+    if let Some((x, y)) = stairs {
+        objects[PLAYER].set_pos(x, y);
+    }
+
+    game.travel.clear();
+    game.damage_popups.clear();
+    initialize_fov(game, ui);
+}
+
+fn generate_floor(level: DungeonLevel, ui: &dyn Frontend, objects: &mut Objects, game: &mut Game) {
+    if level.is_town() {
+        game.floor_theme = FloorTheme::Mixed;
+        let (map, report) = create_town(objects, &mut floor_rng(game.seed, level));
+        game.map = map;
+        game.shop_stock = report.shop_stock;
+        return;
+    }
+
+    let difficulty = if ui.settings().adaptive_difficulty && !game.daily {
+        game.performance.factor
+    } else {
+        1.0
+    };
+    game.floor_theme = level.branch.theme(game.seed, level.depth);
+    let (map, report) = create_map(
+        objects,
+        level,
+        game.floor_theme,
+        difficulty,
+        &game.bestiary,
+        &game.scripts,
+        &mut floor_rng(game.seed, level),
+    );
+    game.map = map;
+    game.shop_stock = report.shop_stock.clone();
+    if level.is_boss_floor() {
+        game.log
+            .add(constants::boss::ARRIVAL, constants::boss::SLAIN_COLOR);
+    } else if let Some(arrival) = constants::floor_themes::arrival_message(game.floor_theme) {
+        game.log
+            .add(arrival, constants::floor_themes::ARRIVAL_COLOR);
+    }
+    announce_danger(&report, game);
+}
+
+fn is_floor_stored(level: DungeonLevel, game: &Game) -> bool {
+    game.floors.iter().any(|floor| floor.dungeon_level == level)
+}
+
+/// Packs the current floor away, leaving only the player in the object list
+fn store_floor(objects: &mut Objects, game: &mut Game) {
+    game.floors.push(StoredFloor {
+        dungeon_level: game.dungeon_level,
+        map: mem::take(&mut game.map),
+        objects: objects.drain_except(PLAYER),
+        theme: game.floor_theme,
+        shop_stock: mem::take(&mut game.shop_stock),
+    });
+}
+
+/// Puts back a floor stored earlier, returning false if the player was never on it
+fn restore_floor(level: DungeonLevel, objects: &mut Objects, game: &mut Game) -> bool {
+    let index = match game
+        .floors
+        .iter()
+        .position(|floor| floor.dungeon_level == level)
+    {
+        Some(index) => index,
+        None => return false,
+    };
+
+    let floor = game.floors.swap_remove(index);
+    game.map = floor.map;
+    for object in floor.objects {
+        objects.insert(object);
+    }
+    game.floor_theme = floor.theme;
+    game.shop_stock = floor.shop_stock;
+    true
+}
+
+/// Grants a single level if the player has the xp for it, returning whether one was gained
+fn level_up(objects: &mut Objects, game: &mut Game, ui: &mut dyn Frontend) -> bool {
+    use constants::gui::menus::level_up;
+
+    let player = &mut objects[PLAYER];
+    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+
+    // see if the player has enough xp
+    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
+        let fighter = player.fighter.as_mut().unwrap();
+        let class = game.class.stats();
+        let choice = choose_stat(level_up::TITLE, fighter, class, ui);
+
+        // backing out keeps the xp, the choice comes back after the player's next turn
+        let choice = match choice {
+            Some(choice) => choice,
+            None => {
+                game.level_up_postponed = true;
+                game.log.add(level_up::POSTPONED, colors::YELLOW);
+                return false;
+            }
+        };
+
+        // level up!
+        fighter.xp -= level_up_xp;
+        game.level_up_postponed = false;
+        train_stat(choice, fighter, class);
+
+        player.level += 1;
+        game.publish(GameEvent::LeveledUp {
+            level: player.level,
+        });
+
+        return true;
+    }
+
+    false
+}
+
+/// Asks which stat a level's training goes into, None if the player backs out
+fn choose_stat(
+    title: &str,
+    fighter: &Fighter,
+    class: &constants::classes::ClassStats,
+    ui: &mut dyn Frontend,
+) -> Option<usize> {
+    use constants::gui::menus::level_up;
+
+    menu(
+        title,
+        &[
+            level_up::create_constitution_option(class.level_hp, fighter.base_max_hp),
+            level_up::create_stength_option(class.level_power, fighter.base_power),
+            level_up::create_agility_option(class.level_defense, fighter.base_defense),
+        ],
+        level_up::WIDTH,
+        ui,
+    )
+}
+
+/// Puts one level's training into the stat picked by `choose_stat`
+fn train_stat(choice: usize, fighter: &mut Fighter, class: &constants::classes::ClassStats) {
+    match choice {
+        0 => {
+            fighter.base_max_hp += class.level_hp;
+            fighter.hp += class.level_hp;
+        }
+        1 => {
+            fighter.base_power += class.level_power;
+        }
+        2 => {
+            fighter.base_defense += class.level_defense;
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Asks which class to play. There is no way to back out, any other key just asks again.
+fn choose_class(ui: &mut dyn Frontend) -> Class {
+    use constants::classes;
+
+    let options: Vec<String> = Class::ALL
+        .iter()
+        .map(|class| classes::create_option(class.stats()))
+        .collect();
+
+    while !ui.is_closed() {
+        if let Some(choice) = menu(classes::HEADER, &options, classes::WIDTH, ui) {
+            return Class::ALL[choice];
+        }
+    }
+
+    Class::Warrior
+}
+
+fn new_game(ui: &mut dyn Frontend, seed: u32) -> (Objects, Game) {
+    let class = choose_class(ui);
+    start_run(class, seed, ui)
+}
+
+/// Starts the finished run over from the top: the same class in the same dungeon, and
+/// still the daily run if it was one. The settings, adaptive difficulty among them,
+/// stay as they were.
+fn restart_run(game: &Game, ui: &mut dyn Frontend) -> (Objects, Game) {
+    let (objects, mut fresh_game) = start_run(game.class, game.seed, ui);
+    fresh_game.daily = game.daily;
+    (objects, fresh_game)
+}
+
+/// Starts the day's run. Everyone playing it on the same day gets the same dungeon and
+/// the same class, so there's nothing to choose.
+fn new_daily_game(ui: &mut dyn Frontend) -> (Objects, Game) {
+    use constants::daily;
+
+    let day = persistence::timestamp() / daily::SECONDS_PER_DAY;
+    let class = Class::ALL[day as usize % Class::ALL.len()];
+    let seed = (day as u32).wrapping_mul(daily::SEED_MULTIPLIER);
+
+    let (objects, mut game) = start_run(class, seed, ui);
+    game.daily = true;
+    game.log
+        .add(daily::create_intro(class.stats().name), daily::COLOR);
+    (objects, game)
+}
+
+fn start_run(class: Class, seed: u32, ui: &mut dyn Frontend) -> (Objects, Game) {
+    use constants::player_base;
+
+    let stats = class.stats();
+    let player = GameObject::builder(
+        0,
+        0,
+        player_base::SYMBOL,
+        player_base::NAME,
+        player_base::COLOR,
+        true,
+    )
+    .with_fighter(Fighter {
+        base_max_hp: stats.hp,
+        hp: stats.hp,
+        base_defense: stats.defense,
+        base_power: stats.power,
+        on_death: DeathCallback::Player,
+        xp: 0,
+        speed: constants::speed::NORMAL,
+        energy: 0,
+    })
+    .alive()
+    .build();
+
+    let level = DungeonLevel::main(1);
+    let floor_theme = level.branch.theme(seed, level.depth);
+    let mut game_objects = Objects::default();
+    game_objects.insert(player);
+    // every run starts out at the normal difficulty
+    let performance = Performance::new();
+    let bestiary = monsters::load();
+    let scripts = scripts::load();
+    let (potions, scrolls) = unidentified_kinds(&scripts);
+    let identification = Identification::new(&potions, &scrolls, seed);
+    let (map, report) = create_map(
+        &mut game_objects,
+        level,
+        floor_theme,
+        performance.factor,
+        &bestiary,
+        &scripts,
+        &mut floor_rng(seed, level),
+    );
+    let mut game = Game {
+        map,
+        log: vec![],
+        inventory: vec![],
+        dungeon_level: level,
+        turns: 0,
+        damage_popups: vec![],
+        seed,
+        rng: run_rng(seed),
+        floor_theme,
+        level_up_postponed: false,
+        scrambled: None,
+        performance,
+        bestiary,
+        scripts,
+        class,
+        mana: class.max_mana(1),
+        gold: 0,
+        drops: vec![],
+        shop_stock: report.shop_stock.clone(),
+        floors: vec![],
+        won: false,
+        daily: false,
+        autosaved_at: 0,
+        kills: 0,
+        deepest: level.absolute_depth(),
+        hurt_by: None,
+        travel: vec![],
+        events: EventLog::default(),
+        inputs: vec![],
+        achievements: vec![],
+        identification,
+        // a replay brings the stash it was recorded with instead
+        starting_stash: if ui.replay().is_playing() {
+            vec![]
+        } else {
+            persistence::load_stash().unwrap_or_default()
+        },
+        recall_return: None,
+    };
+
+    game.inventory.extend(class.starting_items());
+    // the player knows their own kit, whatever it would look like found on the floor
+    for item in game.inventory.iter_mut() {
+        game.identification.identify(&item.name);
+        if let Some(equipment) = item.equipment.as_mut() {
+            equipment.blessing_known = true;
+        }
+    }
+
+    initialize_fov(&game, ui);
+
+    game.log.add(constants::gui::WELCOME_MESSAGE, colors::RED);
+    announce_danger(&report, &mut game);
+
+    (game_objects, game)
+}
+
+/// Settles the adaptive difficulty for the floor about to be generated, telling the
+/// player when it moves. With the setting off, floors are always made at the normal factor.
+fn adjust_difficulty(game: &mut Game, ui: &dyn Frontend) {
+    use constants::difficulty;
+
+    // the daily run has to be the same dungeon for everyone
+    if !ui.settings().adaptive_difficulty || game.daily {
+        return;
+    }
+
+    // time spent in town says nothing about how the dungeon is going
+    if game.dungeon_level.is_town() {
+        game.performance.start_floor(game.turns);
+        return;
+    }
+
+    match game.performance.assess_floor(game.turns) {
+        Some(cmp::Ordering::Greater) => game.log.add(difficulty::HARDER, difficulty::COLOR),
+        Some(_) => game.log.add(difficulty::EASIER, difficulty::COLOR),
+        None => {}
+    }
+}
+
+fn announce_danger(report: &FloorReport, game: &mut Game) {
+    use constants::danger;
+
+    let (message, color) = danger::describe(report.danger);
+    game.log
+        .add(danger::create_message(message, report.danger), color);
+}
+
+fn initialize_fov(game: &Game, ui: &mut dyn Frontend) {
+    for y in 0..constants::gui::MAP_HEIGHT {
+        for x in 0..constants::gui::MAP_WIDTH {
+            ui.fov_mut().set(
+                x,
+                y,
+                !game.map[x as usize][y as usize].block_sight,
+                !game.map[x as usize][y as usize].blocked,
+            );
+        }
+    }
+
+    ui.clear_map(); // Ensure there is no carry over when returning to main menu and starting a new game
+}
+
+/// Runs the game loop until the player leaves or the inputs run out, handing back the
+/// objects the run ended with
+fn play_game(mut game_objects: Objects, game: &mut Game, ui: &mut dyn Frontend) -> Objects {
+    // a replay brings its own inputs, a played run carries on with what it recorded so far
+    if !ui.replay().is_playing() {
+        ui.replay_mut().start_recording(mem::take(&mut game.inputs));
+    }
+
+    while !ui.is_closed() {
+        let input = match ui.next_input() {
+            Some(input) => input,
+            None => break,
+        };
+        // a frame spent waiting on the player changes nothing, so it isn't kept
+        if input != Input::Idle || (!game.travel.is_empty() && game_objects[PLAYER].alive) {
+            ui.replay_mut().record(input);
+        }
+
+        match input {
+            Input::Click(tile) if game_objects[PLAYER].alive => {
+                start_travel(tile, &game_objects, game, ui)
+            }
+            // the run was continued from a save, which forgets the walk the player was on
+            Input::Resumed => game.travel.clear(),
+            _ => {}
+        }
+
+        render_all(ui, &game_objects, game);
+        remember_monsters(&mut game_objects, ui.fov());
+
+        // Handle player movement
+        let previous_player_pos = game_objects[PLAYER].pos();
+        let already_won = game.won;
+        let action = handle_keys(input, ui, game, &mut game_objects);
+
+        // The player glyph hides the stairs, so point them out when stepped on
+        if game_objects[PLAYER].pos() != previous_player_pos && player_on_stairs(&game_objects) {
+            use constants::gui::menus::next_level;
+            game.log.add(
+                next_level::ON_STAIRS_LOG_MESSAGE,
+                next_level::ON_STAIRS_COLOR,
+            );
+            game.log.add(
+                next_level::create_remaining_message(living_monsters(&game_objects)),
+                next_level::ON_STAIRS_COLOR,
+            );
+        }
+        if game_objects[PLAYER].pos() != previous_player_pos {
+            if let Some(branch) = branch_underfoot(&game_objects) {
+                use constants::gui::menus::next_level;
+                game.log.add(
+                    constants::branches::create_on_stairs_message(branch.name()),
+                    next_level::ON_STAIRS_COLOR,
+                );
+            }
+        }
+        if game_objects[PLAYER].pos() != previous_player_pos && player_on_up_stairs(&game_objects) {
+            use constants::gui::menus::next_level;
+            let above = game.dungeon_level.above();
+            let destination = if above.is_town() {
+                None
+            } else {
+                Some(above.label())
+            };
+            game.log.add(
+                next_level::create_on_up_stairs_message(destination.as_deref()),
+                next_level::ON_STAIRS_COLOR,
+            );
+        }
+
+        // a recorded run that was saved and continued has its exits in the replay, the
+        // viewer stops it with escape instead
+        if action == PlayerAction::Exit && !ui.replay().is_playing() {
+            save_run(&game_objects, game, ui).unwrap();
+            break;
+        }
+
+        finish_action(
+            action == PlayerAction::TookTurn,
+            &mut game_objects,
+            game,
+            ui,
+        );
+
+        if ui.settings().event_log && game.events.flush_to_file().is_err() {
+            eprintln!("{}", constants::create_event_log_warning());
+        }
+
+        let autosave_turns = ui.settings().autosave_turns;
+        if autosave_turns > 0
+            && game_objects[PLAYER].alive
+            && game.turns >= game.autosaved_at + autosave_turns
+        {
+            autosave(&game_objects, game, ui);
+        }
+
+        if game.won && !already_won {
+            use constants::gui::menus::victory;
+
+            if !ui.replay().is_playing() {
+                record_score(&game_objects, game);
+                save_replay(game, ui);
+            }
+            render_all(ui, &game_objects, game);
+            let score = scores::Score::new(&game_objects, game);
+            let title = format!(
+                "{}{}",
+                victory::create_title(game.turns),
+                constants::hall_of_fame::create_breakdown(&score)
+            );
+            let choice = menu(
+                &title,
+                &[
+                    victory::KEEP_PLAYING,
+                    victory::PLAY_AGAIN,
+                    victory::MAIN_MENU,
+                ],
+                victory::WIDTH,
+                ui,
+            );
+
+            match choice {
+                Some(0) => {}
+                Some(1) => {
+                    let (objects, fresh_game) = restart_run(game, ui);
+                    game_objects = objects;
+                    *game = fresh_game;
+                    ui.replay_mut().start_recording(vec![]);
+                }
+                _ => {
+                    save_run(&game_objects, game, ui).unwrap();
+                    break;
+                }
+            }
+        }
+
+        if !game_objects[PLAYER].alive {
+            use constants::gui::menus::game_over;
+
+            // a won run was already recorded when the player got out
+            if !ui.replay().is_playing() {
+                if !game.won {
+                    record_score(&game_objects, game);
+                }
+                save_replay(game, ui);
+            }
+
+            // with permadeath the run is over for good, all that's left is its record
+            if ui.settings().permadeath {
+                persistence::delete_save();
+                match persistence::write_morgue(&game_objects, game) {
+                    Ok(file_name) => game.log.add(
+                        constants::morgue::create_written_message(&file_name),
+                        colors::LIGHT_GREY,
+                    ),
+                    Err(_) => game.log.add(constants::morgue::WRITE_FAILED, colors::RED),
+                }
+            }
+
+            // show the final state of the dungeon behind the game over menu
+            render_all(ui, &game_objects, game);
+            let score = scores::Score::new(&game_objects, game);
+            let title = format!(
+                "{}{}",
+                game_over::TITLE,
+                constants::hall_of_fame::create_breakdown(&score)
+            );
+            let choice = menu(
+                &title,
+                &[game_over::PLAY_AGAIN, game_over::MAIN_MENU],
+                game_over::WIDTH,
+                ui,
+            );
+
+            if choice == Some(0) {
+                let (objects, fresh_game) = restart_run(game, ui);
+                game_objects = objects;
+                *game = fresh_game;
+                ui.replay_mut().start_recording(vec![]);
+            } else {
+                if !ui.settings().permadeath {
+                    save_run(&game_objects, game, ui).unwrap();
+                }
+                break;
+            }
+        }
+    }
+
+    // Closing the window counts as "Save and Quit", same as escape. A permadeath run
+    // that ended in death has nothing left to save.
+    let run_over = ui.settings().permadeath && !game_objects[PLAYER].alive;
+    if ui.is_closed() && !run_over {
+        save_run(&game_objects, game, ui).unwrap();
+    }
+    game_objects
+}
+
+/// Waits out one frame of play for the player to do something, Idle if they don't
+fn read_input(tcod: &mut Tcod) -> Input {
+    match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+        Some((_, Event::Mouse(m))) => {
+            tcod.mouse = m;
+            // the mouse state sticks around between events, so a click only counts once
+            match mouse_tile(tcod) {
+                Some(tile) if m.lbutton_pressed => Input::Click(tile),
+                _ => Input::Idle,
+            }
+        }
+        Some((_, Event::Key(key))) => Input::Key(tcod.keybindings.action_for(key)),
+        _ => Input::Idle,
+    }
+}
+
+/// The next step of the replay being watched, after holding the last one on screen
+/// long enough to keep to the chosen speed. None once it's over or the viewer stops it.
+fn replay_input(tcod: &mut Tcod) -> Option<Input> {
+    if let Some((_, Event::Key(key))) = input::check_for_event(input::KEY_PRESS) {
+        match key {
+            Key { code: Escape, .. } => return None,
+            Key { printable: '+', .. } | Key { printable: '=', .. } => tcod.replay.faster(),
+            Key { printable: '-', .. } => tcod.replay.slower(),
+            _ => {}
+        }
+    }
+
+    thread::sleep(tcod.replay.delay());
+    tcod.replay.next()
+}
+
+/// Saves the run along with everything recorded of it, so a continued game can still
+/// be watched from the very start. Watching a replay never touches the save.
+fn save_run(
+    objects: &Objects,
+    game: &mut Game,
+    ui: &dyn Frontend,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if ui.replay().is_playing() {
+        return Ok(());
+    }
+    game.inputs = ui.replay().inputs().to_vec();
+    persistence::save_game(objects, game)
+}
+
+/// Keeps a finished run so it can be watched again from the main menu. Like the score,
+/// losing it only gets a warning.
+fn save_replay(game: &Game, ui: &dyn Frontend) {
+    let recording = replay::Recording::new(game, ui.settings(), ui.replay().inputs());
+    if replay::save(&recording).is_err() {
+        eprintln!("{}", constants::create_replay_warning());
+    }
+}
+
+/// Plays the last finished run back from its seed, with the settings it was played with
+fn watch_replay(tcod: &mut Tcod) {
+    let recording = match replay::load() {
+        Ok(recording) => recording,
+        Err(_) => {
+            msgbox(
+                constants::replay::NONE_RECORDED,
+                constants::replay::WIDTH,
+                tcod,
+            );
+            return;
+        }
+    };
+
+    let settings = mem::replace(&mut tcod.settings, recording.playback_settings());
+    tcod.replay
+        .start_playback(recording.inputs, recording.stash);
+    let (objects, mut game) = start_run(recording.class, recording.seed, tcod);
+    game.daily = recording.daily;
+    play_game(objects, &mut game, tcod);
+
+    let finished = tcod.replay.is_over();
+    tcod.replay.stop_playback();
+    tcod.settings = settings;
+    if finished && !tcod.root.window_closed() {
+        msgbox(constants::replay::OVER, constants::replay::WIDTH, tcod);
+    }
+}
+
+/// Plays the last run through from its recording without opening a window, then says
+/// how it ended
+fn check_replay() {
+    let recording = match replay::load() {
+        Ok(recording) => recording,
+        Err(_) => {
+            eprintln!("{}", constants::replay::NONE_RECORDED);
+            return;
+        }
+    };
+
+    let mut ui = Headless::new(recording.playback_settings(), vec![]);
+    ui.replay_mut()
+        .start_playback(recording.inputs, recording.stash);
+    let (objects, mut game) = start_run(recording.class, recording.seed, &mut ui);
+    game.daily = recording.daily;
+    play_game(objects, &mut game, &mut ui);
+
+    println!(
+        "{}",
+        constants::replay::create_check_summary(game.deepest, game.turns, game.won)
+    );
+}
+
+/// Puts a finished run in the hall of fame. Losing the score isn't worth stopping the
+/// game over, so a failure only gets a warning.
+fn record_score(objects: &Objects, game: &Game) {
+    let score = scores::Score::new(objects, game);
+    let recorded = if game.daily {
+        scores::record_daily(score)
+    } else {
+        scores::record(constants::SCORES_FILE_NAME, score)
+    };
+    if recorded.is_err() {
+        eprintln!("{}", constants::create_scores_warning());
+    }
+}
+
+/// Lists the best runs so far, best first, and below them the best of today's daily runs
+fn show_hall_of_fame(tcod: &mut Tcod) {
+    use constants::hall_of_fame;
+
+    let load = |file_name| {
+        scores::load(file_name).unwrap_or_else(|_| {
+            eprintln!("{}", constants::create_scores_warning());
+            vec![]
+        })
+    };
+    let today = persistence::timestamp() / constants::daily::SECONDS_PER_DAY;
+    let mut daily_scores = load(constants::DAILY_SCORES_FILE_NAME);
+    daily_scores.retain(|score| score.day() == today);
+
+    let mut text = hall_of_fame::HEADER.to_string();
+    text.push_str(&list_scores(&load(constants::SCORES_FILE_NAME)));
+    text.push_str(hall_of_fame::DAILY_HEADER);
+    text.push_str(&list_scores(&daily_scores));
+
+    msgbox(&text, hall_of_fame::WIDTH, tcod);
+}
+
+fn list_scores(scores: &[scores::Score]) -> String {
+    use constants::hall_of_fame;
+
+    if scores.is_empty() {
+        return hall_of_fame::EMPTY.to_string();
+    }
+
+    scores
+        .iter()
+        .take(hall_of_fame::SHOWN)
+        .enumerate()
+        .map(|(rank, score)| hall_of_fame::create_line(rank + 1, score))
+        .collect()
+}
+
+fn main_menu(tcod: &mut Tcod) {
+    use constants::gui::menus::*;
+    // the menu still works without its background, it just falls back to a plain screen
+    let img = match tcod::image::Image::from_file(main::IMAGE_PATH) {
+        Ok(img) => Some(img),
+        Err(_) => {
+            eprintln!("{}", main::create_missing_image_warning(main::IMAGE_PATH));
+            None
+        }
+    };
+
+    while !tcod.root.window_closed() {
+        match img {
+            // show the image, at twice the regular console resolution
+            Some(ref img) => tcod::image::blit_2x(img, (0, 0), (-1, -1), &mut tcod.root, (0, 0)),
+            None => {
+                tcod.root.set_default_background(main::FALLBACK_BACKGROUND);
+                tcod.root.clear();
+            }
+        }
+
+        tcod.root.set_default_foreground(colors::LIGHT_YELLOW);
+        tcod.root.print_ex(
+            constants::gui::SCREEN_WIDTH / 2,
+            tcod.layout.screen_height / 2 - 4,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            constants::GAME_TITLE,
+        );
+
+        if let Some(meta) = persistence::read_save_meta() {
+            tcod.root.set_default_foreground(colors::LIGHT_GREY);
+            tcod.root.print_ex(
+                constants::gui::SCREEN_WIDTH / 2,
+                tcod.layout.screen_height / 2 - 2,
+                BackgroundFlag::None,
+                TextAlignment::Center,
+                main::create_save_summary(&meta),
+            );
+        }
+
+        // show options and wait for the players choice
+        let choices = &[
+            main::NEW_GAME,
+            main::SEEDED_GAME,
+            main::DAILY_RUN,
+            main::CONTINUE,
+            main::WATCH_REPLAY,
+            main::HALL_OF_FAME,
+            main::CREDITS,
+            main::QUIT,
+        ];
+        let choice = menu(main::MENU_NO_HEADER, choices, main::START_MENU_WIDTH, tcod);
+
+        match choice {
+            Some(0) => {
+                // new game
+                let (objects, mut game) = new_game(tcod, rand::random());
+                play_game(objects, &mut game, tcod);
+            }
+            Some(1) => {
+                // new game on a dungeon someone else has played, or the player wants to replay
+                if let Some(seed) = ask_for_seed(tcod) {
+                    let (objects, mut game) = new_game(tcod, seed);
+                    play_game(objects, &mut game, tcod);
+                }
+            }
+            Some(2) => {
+                let (objects, mut game) = new_daily_game(tcod);
+                play_game(objects, &mut game, tcod);
+            }
+            Some(3) => match persistence::load_game() {
+                Ok((objects, mut game)) => {
+                    initialize_fov(&game, tcod);
+                    play_game(objects, &mut game, tcod);
+                }
+                Err(_e) => {
+                    msgbox("\nNo saved game to load.\n", 24, tcod);
+                    continue;
+                }
+            },
+            Some(4) => watch_replay(tcod),
+            Some(5) => show_hall_of_fame(tcod),
+            Some(6) => show_credits(tcod),
+            Some(7) => {
+                // quit
+                break;
+            }
+            _ => {}
+        }
+    }
+}
 
-    // Render the background
-    panel.set_default_background(back_color);
-    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+/// Asks for the seed of a new run. Numbers are used as they are and any other text is
+/// hashed into one, so a dungeon can be shared as a word. None if the player backs out.
+fn ask_for_seed(tcod: &mut Tcod) -> Option<u32> {
+    use constants::gui::menus::seed;
 
-    // Render the Bar
-    panel.set_default_background(bar_color);
-    if bar_width > 0 {
-        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+    let text = prompt_text(seed::HEADER, seed::MAX_LENGTH, seed::WIDTH, tcod)?;
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
     }
 
-    panel.set_default_foreground(colors::WHITE);
-    panel.print_ex(
-        x + total_width / 2,
-        y,
-        BackgroundFlag::None,
-        TextAlignment::Center,
-        &format!("{}: {}/{}", name, value, maximum),
-    );
+    Some(text.parse().unwrap_or_else(|_| {
+        // FNV-1a, which is enough to spread short words over the seeds
+        text.bytes().fold(0x811C_9DC5, |hash: u32, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        })
+    }))
 }
 
-fn get_names_under_mouse(mouse: Mouse, objects: &[GameObject], fov_map: &FovMap) -> String {
-    let (x, y) = (mouse.cx as i32, mouse.cy as i32);
+/// Lets the player type a line of text in a box under the header. Enter accepts it and
+/// Escape gives up, returning None.
+fn prompt_text(header: &str, max_length: usize, width: i32, tcod: &mut Tcod) -> Option<String> {
+    use tcod::input::KeyCode::{Backspace, Enter, Escape};
 
-    let names = objects
-        .iter()
-        .filter(|obj| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y))
-        .map(|obj| obj.name.clone())
-        .collect::<Vec<_>>();
+    let header_height = tcod
+        .root
+        .get_height_rect(0, 0, width, tcod.layout.screen_height, header);
+    let height = header_height + 2;
+    let mut window = Offscreen::new(width, height);
+    let mut text = String::new();
 
-    names.join(", ")
+    while !tcod.root.window_closed() {
+        window.set_default_background(colors::BLACK);
+        window.clear();
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect(0, 0, width, header_height, header);
+        window.print(0, header_height + 1, format!("{}_", text));
+
+        let x = constants::gui::SCREEN_WIDTH / 2 - width / 2;
+        let y = tcod.layout.screen_height / 2 - height / 2;
+        blit(
+            &window,
+            (0, 0),
+            (width, height),
+            &mut tcod.root,
+            (x, y),
+            1.0,
+            0.7,
+        );
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        match key.code {
+            Enter => return Some(text),
+            Escape => return None,
+            Backspace => {
+                text.pop();
+            }
+            _ if !key.printable.is_control() && text.len() < max_length => text.push(key.printable),
+            _ => {}
+        }
+    }
+
+    None
 }
 
-fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, tcod: &mut Tcod) -> Option<usize> {
-    assert!(
-        options.len() <= 26,
-        "Cannot have a menu with more than 26 options"
-    );
+/// Rolls the credits up the screen, looping until any key is pressed
+fn show_credits(tcod: &mut Tcod) {
+    use constants::gui::menus::credits;
 
-    // calculate total height for the header (after auto-wrap) and one line per option
-    let header_height = if header.is_empty() {
-        0
-    } else {
-        tcod.root
-            .get_height_rect(0, 0, width, constants::gui::SCREEN_HEIGHT, header)
-    };
+    let width = constants::gui::SCREEN_WIDTH;
+    let height = tcod.layout.screen_height;
+    let mut window = Offscreen::new(width, height);
 
-    let height = options.len() as i32 + header_height;
+    // the text starts just below the screen and wraps around once it has scrolled past the top
+    let text_height = credits::LINES.len() as i32;
+    let mut frame = 0;
+
+    while !tcod.root.window_closed() {
+        if let Some((_, Event::Key(_))) = input::check_for_event(input::KEY_PRESS) {
+            break;
+        }
+
+        let top = height - (frame / credits::FRAMES_PER_LINE) % (height + text_height);
+        window.set_default_background(colors::BLACK);
+        window.clear();
+
+        for (index, line) in credits::LINES.iter().enumerate() {
+            let y = top + index as i32;
+            if y >= 0 && y < height {
+                window.set_default_foreground(if index == 0 {
+                    colors::LIGHT_YELLOW
+                } else {
+                    colors::WHITE
+                });
+                window.print_ex(
+                    width / 2,
+                    y,
+                    BackgroundFlag::None,
+                    TextAlignment::Center,
+                    *line,
+                );
+            }
+        }
+
+        blit(
+            &window,
+            (0, 0),
+            (width, height),
+            &mut tcod.root,
+            (0, 0),
+            1.0,
+            1.0,
+        );
+        tcod.root.flush();
+        frame += 1;
+    }
+}
+
+/// Shows the whole message log on its own screen, starting at the newest messages and
+/// scrolling back a message or a page at a time until Escape is pressed
+fn show_message_history(log: &Messages, tcod: &mut Tcod) {
+    use constants::gui::menus::history;
+    use tcod::input::KeyCode::{Down, End, Escape, Home, PageDown, PageUp, Up};
 
+    let width = constants::gui::SCREEN_WIDTH;
+    let height = tcod.layout.screen_height;
     let mut window = Offscreen::new(width, height);
 
-    // print the header, with auto-wrap;
-    window.set_default_foreground(colors::WHITE);
-    window.print_rect_ex(
-        0,
-        0,
-        width,
-        height,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        header,
-    );
+    // the header and footer take a line each, with a blank line under the header
+    let text_width = width - 2;
+    let text_height = height - 3;
+    let heights: Vec<i32> = log
+        .iter()
+        .map(|(msg, _)| window.get_height_rect(1, 0, text_width, 0, msg))
+        .collect();
 
-    // print all the options
-    for (index, option_text) in options.iter().enumerate() {
-        // essentially ASCII math, probably a better way of approaching this entire menu
-        let menu_letter = (b'a' + index as u8) as char;
-        let text = format!("({}) {}", menu_letter, option_text.as_ref());
+    // the first message that still lets everything after it fill the screen
+    let last_top = {
+        let mut top = log.len();
+        let mut used = 0;
+        while top > 0 && used + heights[top - 1] <= text_height {
+            top -= 1;
+            used += heights[top];
+        }
+        top
+    };
+    let page = cmp::max(1, log.len() - last_top);
+    let mut top = last_top;
+
+    while !tcod.root.window_closed() {
+        window.set_default_background(colors::BLACK);
+        window.clear();
+
+        window.set_default_foreground(history::HEADER_COLOR);
         window.print_ex(
+            width / 2,
             0,
-            header_height + index as i32,
             BackgroundFlag::None,
-            TextAlignment::Left,
-            text,
+            TextAlignment::Center,
+            history::HEADER,
         );
-    }
-
-    let x = constants::gui::SCREEN_WIDTH / 2 - width / 2;
-    let y = constants::gui::SCREEN_HEIGHT / 2 - height / 2;
-    tcod::console::blit(
-        &window,
-        (0, 0),
-        (width, height),
-        &mut tcod.root,
-        (x, y),
-        1.0,
-        0.7,
-    );
 
-    // present the root console to the player and wait for a key press
-    tcod.root.flush();
-    let key = tcod.root.wait_for_keypress(true);
+        if log.is_empty() {
+            window.set_default_foreground(colors::WHITE);
+            window.print(1, 2, history::EMPTY);
+        }
 
-    // convert the ASCII code to an index; if it corresponds to an option, return it
-    if key.printable.is_alphabetic() {
-        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
-        if index < options.len() {
-            Some(index)
-        } else {
-            None
+        let mut y = 2;
+        for (index, (msg, color)) in log.iter().enumerate().skip(top) {
+            if y + heights[index] > text_height + 2 {
+                break;
+            }
+            window.set_default_foreground(*color);
+            window.print_rect(1, y, text_width, 0, msg);
+            y += heights[index];
         }
-    } else {
-        None
+
+        window.set_default_foreground(history::FOOTER_COLOR);
+        window.print_ex(
+            width / 2,
+            height - 1,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            history::FOOTER,
+        );
+
+        blit(
+            &window,
+            (0, 0),
+            (width, height),
+            &mut tcod.root,
+            (0, 0),
+            1.0,
+            1.0,
+        );
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        top = match key.code {
+            Up => top.saturating_sub(1),
+            Down => cmp::min(top + 1, last_top),
+            PageUp => top.saturating_sub(page),
+            PageDown => cmp::min(top + page, last_top),
+            Home => 0,
+            End => last_top,
+            Escape => break,
+            _ => top,
+        };
     }
 }
 
-fn inventory_menu(game: &Game, header: &str, tcod: &mut Tcod) -> Option<usize> {
-    let options = if game.inventory.is_empty() {
-        vec!["Inventory is empty.".into()]
-    } else {
-        game.inventory
-            .iter()
-            .map(|item| match item.equipment {
-                Some(equipment) if equipment.equipped => {
-                    format!("{} (on {})", item.name, equipment.slot)
-                }
-                _ => item.name.clone(),
-            })
-            .collect()
-    };
+fn msgbox(text: &str, width: i32, ui: &mut dyn Frontend) {
+    let options: &[&str] = &[];
+    menu(text, options, width, ui);
+}
 
-    let inventory_index = menu(header, &options, constants::gui::INVENTORY_WIDTH, tcod);
+/// Asks a yes/no question, anything but an explicit yes counts as no
+fn confirm(question: &str, ui: &mut dyn Frontend) -> bool {
+    use constants::gui::menus::confirm;
 
-    // if an item was chosen, return it
-    if !game.inventory.is_empty() {
-        inventory_index
-    } else {
-        None
+    let choice = menu(question, &[confirm::YES, confirm::NO], confirm::WIDTH, ui);
+    choice == Some(0)
+}
+
+fn main() {
+    if env::args().any(|arg| arg == constants::replay::CHECK_FLAG) {
+        check_replay();
+        return;
     }
+
+    let settings = Settings::load();
+    let layout = Layout::new(&settings);
+
+    let root = Root::initializer()
+        .font(constants::FONT_PATH, FontLayout::Tcod)
+        .font_type(FontType::Greyscale)
+        .size(constants::gui::SCREEN_WIDTH, layout.screen_height)
+        .title(constants::gui::menus::main::GAME_CONSOLE_HEADER)
+        .init();
+
+    tcod::system::set_fps(LIMIT_FPS);
+
+    let mut tcod = Tcod {
+        root,
+        con: Offscreen::new(constants::gui::MAP_WIDTH, constants::gui::MAP_HEIGHT),
+        panel: Offscreen::new(constants::gui::SCREEN_WIDTH, layout.panel_height),
+        fov: FovMap::new(constants::gui::MAP_WIDTH, constants::gui::MAP_HEIGHT),
+        mouse: Default::default(),
+        camera: (0, 0),
+        highlighted: vec![],
+        overlay: vec![],
+        layout,
+        settings,
+        keybindings: Keybindings::load(),
+        replay: Replay::default(),
+    };
+
+    main_menu(&mut tcod);
 }
 
-fn use_item(inventory_id: usize, objects: &mut [GameObject], tcod: &mut Tcod, game: &mut Game) {
-    use Item::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // just call the "use_function" if it is defined
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use = match item {
-            Heal => cast_heal,
-            Lightning => cast_lightning,
-            Confuse => cast_confuse,
-            Fireball => cast_fireball,
-            Sword => toggle_equipment,
-            Shield => toggle_equipment,
-        };
+    fn options(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| format!("Option {}", index))
+            .collect()
+    }
 
-        match on_use(inventory_id, objects, game, tcod) {
-            UseResult::UsedUp => {
-                // destroy after use, unless it was cancelled for some reason
-                game.inventory.remove(inventory_id);
-            }
-            UseResult::UsedAndKept => {} // do nothing
-            UseResult::Cancelled => {
-                game.log.add("Cancelled", colors::WHITE);
-            }
-        }
-    } else {
-        game.log.add(
-            format!("The {} cannot be used.", game.inventory[inventory_id].name),
-            colors::WHITE,
-        );
+    #[test]
+    fn menus_that_fit_the_letters_are_one_page() {
+        assert_eq!(menu_page(0, 0), (0..0, false, false));
+        assert_eq!(menu_page(26, 0), (0..26, false, false));
     }
-}
 
-fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<GameObject>) {
-    let mut item = game.inventory.remove(inventory_id);
+    #[test]
+    fn long_menus_are_paged() {
+        assert_eq!(menu_page(30, 0), (0..24, false, true));
+        assert_eq!(menu_page(30, 1), (24..30, true, false));
+        assert_eq!(menu_page(60, 1), (24..48, true, true));
+    }
 
-    if item.equipment.is_some() {
-        item.dequip(&mut game.log);
+    #[test]
+    fn paging_reaches_options_past_the_letters() {
+        // the 25th entry of the first page turns to the next one
+        let inputs = vec![Input::Menu(Some(24)), Input::Menu(Some(3))];
+        let mut ui = Headless::new(Settings::default(), inputs);
+        assert_eq!(menu("", &options(30), 50, &mut ui), Some(27));
     }
 
-    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    #[test]
+    fn paging_turns_back() {
+        // the second page holds 6 options, then the way back
+        let inputs = vec![
+            Input::Menu(Some(24)),
+            Input::Menu(Some(6)),
+            Input::Menu(Some(2)),
+        ];
+        let mut ui = Headless::new(Settings::default(), inputs);
+        assert_eq!(menu("", &options(30), 50, &mut ui), Some(2));
+    }
 
-    game.log
-        .add(format!("You dropped a {}", item.name), colors::YELLOW);
+    #[test]
+    fn the_inventory_limit_is_enforced() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 1, &mut ui);
+        let capacity = game.inventory.len() + 1;
+        let ui = Headless::new(
+            Settings {
+                inventory_capacity: capacity,
+                ..Settings::default()
+            },
+            vec![],
+        );
 
-    objects.push(item);
-}
+        let (x, y) = objects[PLAYER].pos();
+        let first = objects.insert(create_item(Item::Sword, x, y));
+        let second = objects.insert(create_item(Item::Sword, x, y));
+        pick_item_up(first, &mut objects, &mut game, &ui);
+        pick_item_up(second, &mut objects, &mut game, &ui);
 
-fn closest_monster(max_range: i32, objects: &mut [GameObject], tcod: &Tcod) -> Option<usize> {
-    let mut closest_enemy = None;
-    let mut closest_dist = (max_range + 1) as f32;
+        assert_eq!(game.inventory.len(), capacity);
+        assert!(objects.get(second).is_some());
+    }
 
-    for (id, object) in objects.iter().enumerate() {
-        if (id != PLAYER)
-            && object.fighter.is_some()
-            && object.ai.is_some()
-            && tcod.fov.is_in_fov(object.x, object.y)
-        {
-            let dist = objects[PLAYER].distance_to(object);
-            if dist < closest_dist {
-                closest_enemy = Some(id);
-                closest_dist = dist;
-            }
-        }
+    /// Generates a floor of the main dungeon the way a run with this seed would
+    fn generate(seed: u32, depth: u32) -> (Objects, Map) {
+        let level = DungeonLevel::main(depth);
+        let mut objects = Objects::default();
+        objects.insert(GameObject::builder(0, 0, '@', "player", colors::WHITE, true).build());
+        let (map, _) = create_map(
+            &mut objects,
+            level,
+            level.branch.theme(seed, depth),
+            1.0,
+            &monsters::built_in(),
+            &scripts::load(),
+            &mut floor_rng(seed, level),
+        );
+        (objects, map)
     }
 
-    closest_enemy
-}
+    fn layout(objects: &Objects) -> Vec<(String, i32, i32)> {
+        objects
+            .iter()
+            .map(|object| (object.name.clone(), object.x, object.y))
+            .collect()
+    }
 
-/// return the position of a tile left-clicked in player's FOV (optionally in a
-/// range), or (None,None) if right-clicked.
-fn target_tile(
-    mut tcod: &mut Tcod,
-    objects: &[GameObject],
-    mut game: &mut Game,
-    max_range: Option<f32>,
-) -> Option<(i32, i32)> {
-    use tcod::input::KeyCode::Escape;
+    #[test]
+    fn the_same_seed_generates_the_same_floor() {
+        for depth in 1..=6 {
+            let (first_objects, first_map) = generate(42, depth);
+            let (second_objects, second_map) = generate(42, depth);
 
-    loop {
-        // render the screen. This erases the inventory and shows the names opf objects under the mouse.
-        tcod.root.flush();
-        let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
-        let mut key = None;
-        match event {
-            Some(Event::Mouse(m)) => tcod.mouse = m,
-            Some(Event::Key(k)) => key = Some(k),
-            None => {}
+            assert_eq!(
+                serde_json::to_string(&first_map).unwrap(),
+                serde_json::to_string(&second_map).unwrap()
+            );
+            assert_eq!(layout(&first_objects), layout(&second_objects));
         }
 
-        render_all(&mut tcod, objects, &mut game);
-
-        let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+        let (_, other_map) = generate(43, 1);
+        assert_ne!(
+            serde_json::to_string(&generate(42, 1).1).unwrap(),
+            serde_json::to_string(&other_map).unwrap()
+        );
+    }
 
-        // accept the target if the played clicked in FOV and in case a range is specified, if it's in that range
-        let in_fov = (x < constants::gui::MAP_WIDTH)
-            && (y < constants::gui::MAP_HEIGHT)
-            && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
+    #[test]
+    fn everything_is_placed_on_open_floor() {
+        for seed in 0..50 {
+            for depth in 1..=6 {
+                let (objects, map) = generate(seed, depth);
+                for object in objects.iter() {
+                    let tile = &map[object.x as usize][object.y as usize];
+                    assert!(!tile.blocked, "{} in a wall, seed {}", object.name, seed);
+                }
+            }
+        }
+    }
 
-        if tcod.mouse.lbutton_pressed && in_fov && in_range {
-            return Some((x, y));
+    #[test]
+    fn nothing_that_blocks_shares_a_tile() {
+        for seed in 0..50 {
+            for depth in 1..=6 {
+                let (objects, _) = generate(seed, depth);
+                let blocking: Vec<(i32, i32)> = objects
+                    .iter()
+                    .filter(|object| object.blocks)
+                    .map(|object| object.pos())
+                    .collect();
+                for (index, tile) in blocking.iter().enumerate() {
+                    assert!(!blocking[index + 1..].contains(tile), "seed {}", seed);
+                }
+            }
         }
+    }
 
-        let escape = key.map_or(false, |k| k.code == Escape);
-        if tcod.mouse.rbutton_pressed || escape {
-            return None;
+    #[test]
+    fn the_stairs_can_be_reached() {
+        for seed in 0..50 {
+            for depth in 1..=6 {
+                let (objects, map) = generate(seed, depth);
+                let start = objects[PLAYER].pos();
+                let stairs: Vec<(i32, i32)> = objects
+                    .iter()
+                    .filter(|object| object.char == '<')
+                    .map(|object| object.pos())
+                    .collect();
+
+                assert!(
+                    !stairs.is_empty(),
+                    "no stairs, seed {} depth {}",
+                    seed,
+                    depth
+                );
+                for &tile in &stairs {
+                    assert!(map::find_path(&map, start, tile).is_some(), "seed {}", seed);
+                    assert!(map::find_path(&map, tile, start).is_some(), "seed {}", seed);
+                }
+            }
         }
     }
-}
 
-fn target_monster(
-    tcod: &mut Tcod,
-    objects: &[GameObject],
-    game: &mut Game,
-    max_range: Option<f32>,
-) -> Option<usize> {
-    loop {
-        match target_tile(tcod, objects, game, max_range) {
-            Some((x, y)) => {
-                // return the first clicked monster, otherwise continue looping
-                for (id, obj) in objects.iter().enumerate() {
-                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
-                        return Some(id);
+    #[test]
+    fn monsters_stay_within_the_budget_and_room_caps() {
+        use map::spawn_tables;
+
+        let bestiary = monsters::built_in();
+        // one room too small for more than a single monster, and a few roomier ones
+        let rooms = [
+            Rect::new(1, 1, 4, 4),
+            Rect::new(10, 1, 9, 9),
+            Rect::new(25, 1, 12, 8),
+            Rect::new(40, 1, 7, 12),
+        ];
+
+        for level in 1..=12 {
+            for &difficulty in &[0.5, 1.0, 1.5] {
+                for seed in 0..20 {
+                    let mut rng = XorShiftRng::from_seed([seed + 1, level, 7, 11]);
+                    let mut objects = Objects::default();
+                    place_monsters(
+                        &rooms,
+                        &mut objects,
+                        level,
+                        FloorTheme::Mixed,
+                        difficulty,
+                        &bestiary,
+                        &mut rng,
+                    );
+
+                    let budget = (spawn_tables::monster_budget(level) as f32 * difficulty).round();
+                    assert!(objects.iter().count() as f32 <= budget, "level {}", level);
+                    for room in &rooms {
+                        let inside = objects
+                            .iter()
+                            .filter(|monster| {
+                                monster.x > room.x1
+                                    && monster.x < room.x2
+                                    && monster.y > room.y1
+                                    && monster.y < room.y2
+                            })
+                            .count();
+                        assert!(inside as i32 <= room.monster_cap(), "{:?}", room);
                     }
                 }
             }
-            None => return None,
         }
     }
-}
 
-fn cast_heal(
-    _inventory_id: usize,
-    objects: &mut [GameObject],
-    game: &mut Game,
-    _tcod: &mut Tcod,
-) -> UseResult {
-    // heal the player
-    let player = &mut objects[PLAYER];
-    if let Some(fighter) = player.fighter {
-        if fighter.hp == player.max_hp(game) {
-            game.log.add("You are already at full health.", colors::RED);
-            return UseResult::Cancelled;
-        }
+    #[test]
+    fn a_reloaded_run_plays_like_an_uninterrupted_one() {
+        use keybindings::Action::*;
+
+        // wander about and wait on whatever turns up. On this seed that's an orc, and the
+        // save falls in the middle of the fight.
+        let inputs: Vec<Input> = [MoveRight, MoveRight, MoveDown, Rest, MoveLeft, MoveUpRight]
+            .iter()
+            .cycle()
+            .take(40)
+            .map(|&action| Input::Key(Some(action)))
+            .collect();
+        let (before, after) = inputs.split_at(20);
+        let play = |objects: Objects, game: &mut Game, inputs: Vec<Input>| {
+            play_game(
+                objects,
+                game,
+                &mut Headless::new(Settings::default(), inputs),
+            )
+        };
+        let state =
+            |objects: &Objects, game: &Game| serde_json::to_string(&(objects, game)).unwrap();
+
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (objects, mut game) = start_run(Class::Warrior, 7, &mut ui);
+        let objects = play(objects, &mut game, inputs.clone());
+        let uninterrupted = state(&objects, &game);
+        assert!(game.rng.draws > 0);
+
+        let (objects, mut game) = start_run(Class::Warrior, 7, &mut ui);
+        let objects = play(objects, &mut game, before.to_vec());
+        assert!(objects[PLAYER].alive);
+        let save = state(&objects, &game);
+        let (objects, mut game) = serde_json::from_str::<(Objects, Game)>(&save).unwrap();
+        game.bestiary = monsters::built_in();
+        game.scripts = scripts::load();
+        let mut resumed = vec![Input::Resumed];
+        resumed.extend_from_slice(after);
+        let objects = play(objects, &mut game, resumed);
+
+        assert_eq!(state(&objects, &game), uninterrupted);
+    }
 
-        game.log
-            .add("Your wounds start to close up!", colors::LIGHT_VIOLET);
-        objects[PLAYER].heal(HEAL_AMOUNT, game);
-        return UseResult::UsedUp;
+    /// A run on an open floor with nothing in it but the player, standing at (10, 10)
+    fn open_floor(inputs: Vec<Input>) -> (Objects, Game, Headless) {
+        use constants::gui::{MAP_HEIGHT, MAP_WIDTH};
+
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 3, &mut ui);
+        objects.drain_except(PLAYER);
+        objects[PLAYER].set_pos(10, 10);
+        game.map = vec![vec![Tile::empty(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        (objects, game, Headless::new(Settings::default(), inputs))
     }
 
-    UseResult::Cancelled
-}
+    fn spawn(name: &str, x: i32, y: i32, objects: &mut Objects, game: &Game) -> EntityId {
+        let kind = monsters::find(&game.bestiary, name).unwrap();
+        objects.insert(create_monster(kind, x, y))
+    }
 
-fn cast_lightning(
-    _inventory_id: usize,
-    objects: &mut [GameObject],
-    mut game: &mut Game,
-    tcod: &mut Tcod,
-) -> UseResult {
-    // find the closest enemy inside a max range and damage it
-    let monster_id = closest_monster(LIGHTNING_RANGE, objects, &tcod);
-    if let Some(monster_id) = monster_id {
-        // ZAP
-        game.log.add(format!("A lightning bolt strikes the {} with a loud thunder! \n The damage is {} hit points ", objects[monster_id].name, LIGHTNING_DAMAGE), colors::LIGHT_BLUE);
-
-        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, &mut game) {
-            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
-        };
+    /// Brings the field of view up to date the way a rendered frame does, so the
+    /// monsters around the player notice them
+    fn refresh_fov(objects: &Objects, game: &mut Game, ui: &mut Headless) {
+        initialize_fov(game, ui);
+        render_all(ui, objects, game);
+    }
 
-        UseResult::UsedUp
-    } else {
-        // No enemy found within max range
+    fn hits_taken(game: &Game) -> usize {
         game.log
-            .add("No enemy is close enough to strike.", colors::RED);
-        UseResult::Cancelled
+            .iter()
+            .filter(|(message, _)| message.contains("Player for"))
+            .count()
     }
-}
-
-fn cast_confuse(
-    _inventory_id: usize,
-    objects: &mut [GameObject],
-    game: &mut Game,
-    tcod: &mut Tcod,
-) -> UseResult {
-    // ask the player for a target to confuse
-    game.log.add(
-        "Left-click an enemy to confuse it, or right-click to cancel.",
-        colors::LIGHT_CYAN,
-    );
 
-    let monster_id = target_monster(tcod, objects, game, Some(CONFUSE_RANGE as f32));
+    #[test]
+    fn healing_at_full_health_is_cancelled() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let result = run_effect(
+            Item::Heal,
+            "Healing Potion",
+            &mut objects,
+            &mut game,
+            &mut ui,
+        );
+        assert!(matches!(result, UseResult::Cancelled));
+    }
 
-    if let Some(monster_id) = monster_id {
-        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-        objects[monster_id].ai = Some(Ai::Confused {
-            previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
-        });
+    #[test]
+    fn healing_closes_wounds() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let max_hp = objects[PLAYER].max_hp(&game);
+        objects[PLAYER].fighter.as_mut().unwrap().hp = 1;
 
-        game.log.add(
-            format!(
-                "The eyes of the {} look vacant, as it starts to stumble around!",
-                objects[monster_id].name
-            ),
-            colors::LIGHT_GREEN,
+        let result = run_effect(
+            Item::Heal,
+            "Healing Potion",
+            &mut objects,
+            &mut game,
+            &mut ui,
         );
+        assert!(matches!(result, UseResult::UsedUp));
+        let hp = objects[PLAYER].fighter.unwrap().hp;
+        assert_eq!(hp, cmp::min(max_hp, 1 + HEAL_AMOUNT));
+    }
 
-        UseResult::UsedUp
-    } else {
-        // No enemy found in range
-        game.log
-            .add("No enemy is close enough to strike.", colors::RED);
-        UseResult::Cancelled
+    #[test]
+    fn a_fireball_burns_what_it_does_not_kill() {
+        use constants::consumables::scrolls::fireball;
+
+        let (mut objects, mut game, mut ui) = open_floor(vec![Input::Target(Some((20, 10)))]);
+        let orc = spawn("Orc", 20, 10, &mut objects, &game);
+        let troll = spawn("Troll", 21, 10, &mut objects, &game);
+        let troll_hp = objects[troll].fighter.unwrap().hp;
+
+        let result = run_effect(Item::Fireball, "Fireball", &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::UsedUp));
+        assert!(!objects[orc].alive);
+        assert!(objects[PLAYER].fighter.unwrap().xp > 0);
+        assert_eq!(
+            objects[troll].fighter.unwrap().hp,
+            troll_hp - fireball::DAMAGE
+        );
+        assert!(objects[troll].has_effect(EffectKind::Burning));
     }
-}
 
-fn cast_fireball(
-    _inventory_id: usize,
-    objects: &mut [GameObject],
-    mut game: &mut Game,
-    tcod: &mut Tcod,
-) -> UseResult {
-    use constants::consumables::scrolls::fireball;
-    // Ask the player for a target tile to throw a fireball at
-    game.log
-        .add(fireball::INSTRUCTIONS, fireball::INSTRUCTION_COLOR);
+    #[test]
+    fn backing_out_of_a_fireball_on_yourself_costs_nothing() {
+        // the blast would reach the player, who says no when asked
+        let inputs = vec![Input::Target(Some((11, 10))), Input::Menu(Some(1))];
+        let (mut objects, mut game, mut ui) = open_floor(inputs);
+        let troll = spawn("Troll", 12, 10, &mut objects, &game);
+        let troll_hp = objects[troll].fighter.unwrap().hp;
+
+        let result = run_effect(Item::Fireball, "Fireball", &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::Cancelled));
+        assert_eq!(objects[troll].fighter.unwrap().hp, troll_hp);
+    }
 
-    let (x, y) = match target_tile(tcod, objects, game, None) {
-        Some(tile_pos) => tile_pos,
-        None => return UseResult::Cancelled,
-    };
+    #[test]
+    fn confusion_takes_the_monster_picked() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![Input::Target(Some((12, 12)))]);
+        let orc = spawn("Orc", 12, 12, &mut objects, &game);
 
-    game.log
-        .add(fireball::create_radius_message(), fireball::RADIUS_COLOR);
+        let result = run_effect(Item::Confuse, "Confusion", &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::UsedUp));
+        assert!(objects[orc].has_effect(EffectKind::Confusion));
+    }
 
-    let mut xp_to_gain = 0;
-    for (id, obj) in objects.iter_mut().enumerate() {
-        if obj.distance(x, y) <= fireball::RADIUS as f32 && obj.fighter.is_some() {
-            game.log.add(
-                fireball::create_damage_message(&obj.name),
-                fireball::DAMAGE_COLOR,
-            );
+    #[test]
+    fn confusion_lasts_exactly_its_turns() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![Input::Target(Some((15, 15)))]);
+        let orc = spawn("Orc", 15, 15, &mut objects, &game);
+        run_effect(Item::Confuse, "Confusion", &mut objects, &mut game, &mut ui);
 
-            if let Some(xp) = obj.take_damage(fireball::DAMAGE, &mut game) {
-                // can't alter player in this loop, and don't wanna give them xp for killing themselves.
-                // so we track it outside the loop and then award it after
-                if id != PLAYER {
-                    xp_to_gain = xp;
-                }
-            };
+        for _ in 1..CONFUSE_NUM_TURNS {
+            monsters_respond(&mut objects, &mut ui, &mut game);
+            assert!(objects[orc].has_effect(EffectKind::Confusion));
         }
+        monsters_respond(&mut objects, &mut ui, &mut game);
+        assert!(!objects[orc].has_effect(EffectKind::Confusion));
     }
 
-    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
-
-    UseResult::UsedUp
-}
-
-fn toggle_equipment(
-    inventory_id: usize,
-    _objects: &mut [GameObject],
-    game: &mut Game,
-    _tcod: &mut Tcod,
-) -> UseResult {
-    let equipment = match game.inventory[inventory_id].equipment {
-        Some(equipment) => equipment,
-        None => return UseResult::Cancelled,
-    };
+    #[test]
+    fn scripts_from_the_file_run_through_the_game() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![Input::Target(Some((14, 10)))]);
+        let zombie = spawn("Zombie", 14, 10, &mut objects, &game);
+        let zombie_hp = objects[zombie].fighter.unwrap().hp;
+        let frost = scripts::find(&game.scripts, "Scroll of Frost").unwrap();
+        let script = frost.compile().unwrap();
+
+        let result = run_script(&script, &[], "frost", &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::UsedUp));
+        assert_eq!(objects[zombie].fighter.unwrap().hp, zombie_hp - 8);
+        assert!(objects[zombie].has_effect(EffectKind::Slow));
+    }
 
-    if equipment.equipped {
-        game.inventory[inventory_id].dequip(&mut game.log);
-    } else {
-        if let Some(old_equipment) = get_equipped_in_slot(equipment.slot, game) {
-            game.inventory[old_equipment].dequip(&mut game.log);
-        }
+    #[test]
+    fn a_script_that_goes_wrong_is_reported() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let script = Script::compile("damage(player(), \"lots\");").unwrap();
 
-        game.inventory[inventory_id].equip(&mut game.log);
+        let result = run_script(&script, &[], "oops", &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::UsedUp));
+        let (message, _) = game.log.last().unwrap();
+        assert!(message.starts_with("The magic fizzles out"));
     }
 
-    UseResult::UsedAndKept
-}
+    /// Puts the player on the stairs down of the floor they're on
+    fn stand_on_stairs(objects: &mut Objects) {
+        let stairs = objects
+            .iter()
+            .skip(1)
+            .find(|object| object.name == "stairs")
+            .map(|object| object.pos())
+            .unwrap();
+        objects[PLAYER].set_pos(stairs.0, stairs.1);
+    }
 
-fn get_equipped_in_slot(slot: Slot, game: &Game) -> Option<usize> {
-    for (inventory_id, item) in game.inventory.iter().enumerate() {
-        if item
-            .equipment
-            .as_ref()
-            .map_or(false, |e| e.equipped && e.slot == slot)
-        {
-            return Some(inventory_id);
-        }
+    #[test]
+    fn each_boss_floor_keeps_its_own_seal() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 5, &mut ui);
+        change_floor(DungeonLevel::main(5), &mut ui, &mut objects, &mut game);
+        stand_on_stairs(&mut objects);
+        assert!(stairs_locked(&objects));
+
+        let boss = objects
+            .find(|object| object.fighter.map(|f| f.on_death) == Some(DeathCallback::Boss))
+            .unwrap();
+        objects[boss].take_damage(10_000, true, &mut game);
+        drop_loot(&mut objects, &mut game);
+        assert!(!stairs_locked(&objects));
+
+        // the next boss floor is sealed behind its own boss, and leaving it unbeaten
+        // doesn't seal the one already cleared
+        change_floor(DungeonLevel::main(10), &mut ui, &mut objects, &mut game);
+        stand_on_stairs(&mut objects);
+        assert!(stairs_locked(&objects));
+        change_floor(DungeonLevel::main(5), &mut ui, &mut objects, &mut game);
+        stand_on_stairs(&mut objects);
+        assert!(!stairs_locked(&objects));
     }
-    None
-}
 
-/// Returns a vaue that depends on current dungeon level. The table specifies what
-/// value occurs at each level, the default is 0
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
-    table
-        .iter()
-        .rev()
-        .find(|transition| level >= transition.level)
-        .map_or(0, |transition| transition.value)
-}
+    #[test]
+    fn a_fallen_boss_leaves_its_crown() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 5, &mut ui);
+        change_floor(DungeonLevel::main(5), &mut ui, &mut objects, &mut game);
+        let boss = objects
+            .find(|object| object.fighter.map(|f| f.on_death) == Some(DeathCallback::Boss))
+            .unwrap();
+        let pos = objects[boss].pos();
+
+        objects[boss].take_damage(10_000, true, &mut game);
+        drop_loot(&mut objects, &mut game);
+        let crown = objects
+            .iter()
+            .find(|object| object.pos() == pos && object.item == Some(Item::Crown))
+            .unwrap();
+        assert_eq!(crown.name, constants::gear::crown::NAME);
+        assert!(crown.equipment.is_some());
+    }
 
-/// Advance to the next level
-fn next_level(tcod: &mut Tcod, objects: &mut Vec<GameObject>, game: &mut Game) {
-    use constants::gui::menus::next_level;
+    #[test]
+    fn a_replay_withdraws_from_the_recorded_stash() {
+        let (_, mut game, mut ui) = open_floor(vec![]);
+        let carried = game.inventory.len();
+        let had_stash_file = std::path::Path::new(constants::STASH_FILE_NAME).exists();
+        ui.replay_mut().start_playback(
+            vec![Input::Menu(Some(1)), Input::Menu(Some(0))],
+            vec![create_item(Item::Helmet, 0, 0)],
+        );
 
-    game.log
-        .add(next_level::REST_LOG_MESSAGE, next_level::REST_COLOR);
-    let player = &mut objects[PLAYER];
-    let heal_hp = player.max_hp(game) / 2;
-    player.heal(heal_hp, game);
+        open_stash(&mut game, &mut ui);
+        assert_eq!(game.inventory.len(), carried + 1);
+        assert_eq!(game.inventory.last().unwrap().item, Some(Item::Helmet));
+        assert!(ui.replay_mut().stash_mut().unwrap().is_empty());
+        assert_eq!(
+            std::path::Path::new(constants::STASH_FILE_NAME).exists(),
+            had_stash_file
+        );
+    }
 
-    game.log.add(
-        next_level::NEXT_LEVEL_LOG_MESSAGE,
-        next_level::NEXT_LEVEL_COLOR,
-    );
-    game.dungeon_level += 1;
-    game.map = create_map(objects, game.dungeon_level);
-    initialize_fov(game, tcod);
-}
+    #[test]
+    fn playing_again_starts_the_same_run_over() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (objects, mut game) = start_run(Class::Mage, 11, &mut ui);
+        game.daily = true;
+        game.turns = 500;
+
+        let (fresh_objects, fresh_game) = restart_run(&game, &mut ui);
+        assert_eq!(fresh_game.class, Class::Mage);
+        assert_eq!(fresh_game.seed, 11);
+        assert!(fresh_game.daily);
+        assert_eq!(fresh_game.turns, 0);
+        assert_eq!(layout(&fresh_objects), layout(&objects));
+    }
 
-fn level_up(objects: &mut [GameObject], game: &mut Game, mut tcod: &mut Tcod) {
-    use constants::gui::menus::level_up;
+    #[test]
+    fn the_town_has_a_stocked_shop() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 2, &mut ui);
+        change_floor(DungeonLevel::main(0), &mut ui, &mut objects, &mut game);
 
-    let player = &mut objects[PLAYER];
-    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+        assert!(objects
+            .iter()
+            .any(|object| object.npc == Some(Npc::Shopkeeper)));
+        assert_eq!(game.shop_stock.len(), constants::shop::STOCK_SIZE);
+    }
 
-    // see if the player has enough xp
-    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
-        // level up!
-        player.level += 1;
-        game.log
-            .add(level_up::create_log_message(player.level), colors::YELLOW);
+    #[test]
+    fn recall_goes_to_town_and_back() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 4, &mut ui);
+        change_floor(DungeonLevel::main(3), &mut ui, &mut objects, &mut game);
+        // step off the stairs, so coming back to the spot isn't just arriving by them
+        let (x, y) = objects[PLAYER].pos();
+        let left_from = [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+            .iter()
+            .copied()
+            .find(|&(x, y)| !is_blocked(x, y, &game.map, &objects))
+            .unwrap();
+        objects[PLAYER].set_pos(left_from.0, left_from.1);
+
+        let result = cast_recall(0, &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::UsedUp));
+        assert!(game.dungeon_level.is_town());
+
+        let result = cast_recall(0, &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::UsedUp));
+        assert_eq!(game.dungeon_level, DungeonLevel::main(3));
+        assert_eq!(objects[PLAYER].pos(), left_from);
+        assert!(game.recall_return.is_none());
+
+        // with the way back used up, a scroll read in town has nowhere to go
+        change_floor(DungeonLevel::main(0), &mut ui, &mut objects, &mut game);
+        let result = cast_recall(0, &mut objects, &mut game, &mut ui);
+        assert!(matches!(result, UseResult::Cancelled));
+    }
 
-        let mut fighter = player.fighter.as_mut().unwrap();
-        let mut choice = None;
+    #[test]
+    fn nothing_acts_after_a_mutual_kill() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let orc = spawn("Orc", 11, 10, &mut objects, &game);
+        spawn("Troll", 9, 10, &mut objects, &game);
+        spawn("Troll", 10, 11, &mut objects, &game);
+        objects[orc].fighter.as_mut().unwrap().hp = 1;
+        objects[PLAYER].fighter.as_mut().unwrap().hp = 1;
+        refresh_fov(&objects, &mut game, &mut ui);
+
+        // the player takes the orc down with them, and the trolls finish the player off
+        player_move_or_attack(1, 0, &mut game, &mut objects, &mut ui);
+        assert!(!objects[orc].alive);
+        assert!(objects[orc].fighter.is_none());
+        assert!(objects[PLAYER].fighter.unwrap().xp > 0);
+
+        monsters_respond(&mut objects, &mut ui, &mut game);
+        assert!(!objects[PLAYER].alive);
+        assert_eq!(hits_taken(&game), 1);
+    }
 
-        while choice.is_none() {
-            choice = menu(
-                level_up::TITLE,
-                &[
-                    level_up::create_constitution_option(fighter.base_max_hp),
-                    level_up::create_stength_option(fighter.base_power),
-                    level_up::create_agility_option(fighter.base_defense),
-                ],
-                level_up::WIDTH,
-                &mut tcod,
-            );
+    #[test]
+    fn a_confused_monster_can_stumble_into_the_player() {
+        let (mut objects, mut game, _) = open_floor(vec![]);
+        let orc = spawn("Orc", 11, 10, &mut objects, &game);
+        // walled in on every side but the player's, so every step the orc manages is a bump
+        for x in 10..13 {
+            for y in 9..12 {
+                if (x, y) != (10, 10) && (x, y) != (11, 10) {
+                    game.map[x as usize][y as usize] = Tile::wall();
+                }
+            }
         }
 
-        fighter.xp -= level_up_xp;
-        match choice {
-            Some(0) => {
-                fighter.base_max_hp += 20;
-                fighter.hp += 20;
-            }
-            Some(1) => {
-                fighter.base_power += 1;
-            }
-            Some(2) => {
-                fighter.base_defense += 1;
-            }
-            _ => unreachable!(),
+        let max_hp = objects[PLAYER].max_hp(&game);
+        for _ in 0..30 {
+            ai_confused(orc, &mut objects, &mut game);
         }
+        assert_eq!(objects[orc].pos(), (11, 10));
+        assert!(objects[PLAYER].fighter.unwrap().hp < max_hp);
+        assert!(hits_taken(&game) > 0);
     }
-}
 
-fn new_game(tcod: &mut Tcod) -> (Vec<GameObject>, Game) {
-    use constants::player_base;
-    let mut player = GameObject::new(
-        0,
-        0,
-        player_base::SYMBOL,
-        player_base::NAME,
-        player_base::COLOR,
-        true,
-    );
-    player.alive = true;
-    player.fighter = Some(Fighter {
-        base_max_hp: 100,
-        hp: 100,
-        base_defense: 1,
-        base_power: 2,
-        on_death: DeathCallback::Player,
-        xp: 0,
-    });
+    #[test]
+    fn a_boomerang_hits_the_nearest_fighter_and_comes_back() {
+        use constants::gear::boomerang;
+
+        // aimed at the troll, with the orc standing in the way
+        let (mut objects, mut game, mut ui) = open_floor(vec![Input::Target(Some((14, 10)))]);
+        let orc = spawn("Orc", 12, 10, &mut objects, &game);
+        let troll = spawn("Troll", 14, 10, &mut objects, &game);
+        let orc_hp = objects[orc].fighter.unwrap().hp;
+        let troll_hp = objects[troll].fighter.unwrap().hp;
+        game.inventory.push(create_item(Item::Boomerang, 0, 0));
+        let carried = game.inventory.len();
+
+        use_item(carried - 1, &mut objects, &mut ui, &mut game);
+        assert_eq!(objects[orc].fighter.unwrap().hp, orc_hp - boomerang::DAMAGE);
+        assert_eq!(objects[troll].fighter.unwrap().hp, troll_hp);
+        assert_eq!(game.inventory.len(), carried);
+        assert_eq!(game.inventory[carried - 1].item, Some(Item::Boomerang));
+    }
 
-    let level = 1;
-    let mut game_objects = vec![player];
-    let mut game = Game {
-        map: create_map(&mut game_objects, level),
-        log: vec![],
-        inventory: vec![],
-        dungeon_level: 1,
-    };
+    #[test]
+    fn walking_onto_a_corpse_steps_over_it() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let orc = spawn("Orc", 11, 10, &mut objects, &game);
+        objects[orc].take_damage(1_000, true, &mut game);
+
+        player_move_or_attack(1, 0, &mut game, &mut objects, &mut ui);
+        assert_eq!(objects[PLAYER].pos(), (11, 10));
+        let stepped_over = constants::underfoot::create_message(&["Remains of Orc".to_string()]);
+        assert_eq!(
+            describe_underfoot(&objects, &game),
+            Some(stepped_over.clone())
+        );
+        let (message, _) = game.log.last().unwrap();
+        assert_eq!(message, &stepped_over);
+    }
 
-    use constants::gear::*;
-    let mut dagger = GameObject::new(0, 0, dagger::SYMBOL, dagger::NAME, dagger::COLOR, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::LeftHand,
-        hp_bonus: dagger::HP_BONUS,
-        defense_bonus: dagger::DEFENSE_BONUS,
-        power_bonus: dagger::POWER_BONUS,
-    });
-    game.inventory.push(dagger);
+    #[test]
+    fn walking_into_a_living_monster_attacks_it() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let orc = spawn("Orc", 11, 10, &mut objects, &game);
+        let orc_hp = objects[orc].fighter.unwrap().hp;
 
-    initialize_fov(&game, tcod);
+        player_move_or_attack(1, 0, &mut game, &mut objects, &mut ui);
+        assert_eq!(objects[PLAYER].pos(), (10, 10));
+        assert!(objects[orc].fighter.unwrap().hp < orc_hp);
+    }
 
-    game.log.add(constants::gui::WELCOME_MESSAGE, colors::RED);
+    #[test]
+    fn going_down_settles_every_level_earned() {
+        // enough xp for two levels, one put into constitution and one into strength
+        let inputs = vec![Input::Menu(Some(0)), Input::Menu(Some(1))];
+        let mut ui = Headless::new(Settings::default(), inputs);
+        let (mut objects, mut game) = start_run(Class::Warrior, 6, &mut ui);
+        let before = objects[PLAYER].fighter.unwrap();
+        let xp = (LEVEL_UP_BASE + LEVEL_UP_FACTOR) + (LEVEL_UP_BASE + 2 * LEVEL_UP_FACTOR);
+        objects[PLAYER].fighter.as_mut().unwrap().xp = xp;
+
+        next_level(DungeonLevel::main(2), &mut ui, &mut objects, &mut game);
+        let after = objects[PLAYER].fighter.unwrap();
+        assert_eq!(objects[PLAYER].level, 3);
+        assert_eq!(after.xp, 0);
+        assert!(after.base_max_hp > before.base_max_hp);
+        assert!(after.base_power > before.base_power);
+        assert_eq!(game.dungeon_level, DungeonLevel::main(2));
+    }
 
-    (game_objects, game)
-}
+    #[test]
+    fn dropped_items_wait_on_the_floor_they_were_left_on() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 8, &mut ui);
+        game.inventory.push(create_item(Item::Helmet, 0, 0));
+        let spot = objects[PLAYER].pos();
+        drop_item(game.inventory.len() - 1, &mut game, &mut objects);
 
-fn initialize_fov(game: &Game, tcod: &mut Tcod) {
-    for y in 0..constants::gui::MAP_HEIGHT {
-        for x in 0..constants::gui::MAP_WIDTH {
-            tcod.fov.set(
-                x,
-                y,
-                !game.map[x as usize][y as usize].block_sight,
-                !game.map[x as usize][y as usize].blocked,
-            );
-        }
+        change_floor(DungeonLevel::main(2), &mut ui, &mut objects, &mut game);
+        assert!(!objects
+            .iter()
+            .any(|object| object.item == Some(Item::Helmet)));
+        change_floor(DungeonLevel::main(1), &mut ui, &mut objects, &mut game);
+        assert!(objects
+            .iter()
+            .any(|object| object.item == Some(Item::Helmet) && object.pos() == spot));
     }
 
-    tcod.con.clear(); // Ensure there is no carry over when returning to main menu and starting a new game
-}
+    #[test]
+    fn the_builder_makes_the_same_object_as_setting_fields() {
+        let fighter = Fighter {
+            base_max_hp: 12,
+            hp: 12,
+            base_defense: 1,
+            base_power: 3,
+            on_death: DeathCallback::Monster,
+            xp: 20,
+            speed: 100,
+            energy: 0,
+        };
+        let built = GameObject::builder(3, 4, 'k', "Kobold", colors::GREEN, true)
+            .with_fighter(fighter)
+            .with_ai(Ai::Basic)
+            .with_enemy("Kobold")
+            .with_weight(5)
+            .with_quantity(2)
+            .with_gold(7)
+            .alive()
+            .always_visible()
+            .build();
+
+        let mut manual = GameObject::new(3, 4, 'k', "Kobold", colors::GREEN, true);
+        manual.fighter = Some(fighter);
+        manual.ai = Some(Ai::Basic);
+        manual.enemy = Some("Kobold".into());
+        manual.weight = 5;
+        manual.quantity = 2;
+        manual.gold = 7;
+        manual.alive = true;
+        manual.always_visible = true;
+
+        assert_eq!(
+            serde_json::to_value(&built).unwrap(),
+            serde_json::to_value(&manual).unwrap()
+        );
+    }
 
-fn play_game(mut game_objects: Vec<GameObject>, mut game: &mut Game, mut tcod: &mut Tcod) {
-    let mut key = Default::default();
+    #[test]
+    fn the_builder_sets_up_items_like_setting_fields() {
+        let built = create_item(Item::Helmet, 5, 6);
+
+        let mut manual = GameObject::new(
+            5,
+            6,
+            constants::gear::helmet::SYMBOL,
+            constants::gear::helmet::NAME,
+            constants::gear::helmet::COLOR,
+            false,
+        );
+        manual.item = Some(Item::Helmet);
+        manual.equipment = Some(Equipment {
+            slot: Slot::Head,
+            equipped: false,
+            power_bonus: 0,
+            defense_bonus: constants::gear::helmet::DEFENSE_BONUS,
+            hp_bonus: 0,
+            crit_bonus: 0.0,
+            blessing: Blessing::Uncursed,
+            blessing_known: false,
+        });
+        manual.weight = constants::gear::helmet::WEIGHT;
+        manual.always_visible = true;
 
-    while !tcod.root.window_closed() {
-        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
-            Some((_, Event::Mouse(m))) => tcod.mouse = m,
-            Some((_, Event::Key(k))) => key = k,
-            _ => key = Default::default(),
-        }
+        assert_eq!(
+            serde_json::to_value(&built).unwrap(),
+            serde_json::to_value(&manual).unwrap()
+        );
+    }
 
-        render_all(&mut tcod, &game_objects, &mut game);
+    #[test]
+    fn recall_costs_a_turn_where_it_lands() {
+        let mut ui = Headless::new(Settings::default(), vec![]);
+        let (mut objects, mut game) = start_run(Class::Warrior, 4, &mut ui);
+        // an orc waits right beside the spot the player recalls from
+        let (x, y) = objects[PLAYER].pos();
+        let (orc_x, orc_y) = [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+            .iter()
+            .copied()
+            .find(|&(x, y)| !is_blocked(x, y, &game.map, &objects))
+            .unwrap();
+        spawn("Orc", orc_x, orc_y, &mut objects, &game);
+        cast_recall(0, &mut objects, &mut game, &mut ui);
+
+        game.inventory.push(create_item(Item::Recall, 0, 0));
+        let action = use_item(game.inventory.len() - 1, &mut objects, &mut ui, &mut game);
+        assert_eq!(action, PlayerAction::TookTurn);
+        assert_eq!(objects[PLAYER].pos(), (x, y));
+
+        // so the orc gets its swing in before the player can do anything else
+        render_all(&mut ui, &objects, &mut game);
+        monsters_respond(&mut objects, &mut ui, &mut game);
+        assert_eq!(hits_taken(&game), 1);
+    }
 
-        // Clear the GameObjects once their position is moved to the visible screen.
-        // If we do this earlier or later we won't erase the last pos.
-        for object in &game_objects {
-            object.clear(&mut tcod.con);
-        }
+    /// A run carrying nothing but one load of the given weight
+    fn carrying(weight: i32) -> (Objects, Game) {
+        let (objects, mut game, _) = open_floor(vec![]);
+        game.inventory.clear();
+        let load = GameObject::builder(0, 0, '*', "Rock", colors::GREY, false)
+            .with_weight(weight)
+            .build();
+        game.inventory.push(load);
+        (objects, game)
+    }
 
-        // Handle player movement
-        let action = handle_keys(key, &mut tcod, &mut game, &mut game_objects);
+    #[test]
+    fn a_full_load_is_not_encumbering() {
+        let (objects, game) = carrying(0);
+        let capacity = carry_capacity(&objects, &game);
 
-        if action == PlayerAction::Exit {
-            save_game(&game_objects, game).unwrap();
-            break;
-        }
+        let (objects, game) = carrying(capacity);
+        assert!(!is_encumbered(&objects, &game));
+        assert_eq!(player_speed(&objects, &game), objects[PLAYER].speed());
+    }
 
-        if game_objects[PLAYER].alive && action != PlayerAction::DidntTakeTurn {
-            for id in 0..game_objects.len() {
-                if game_objects[id].ai.is_some() {
-                    ai_take_turn(id, &mut game_objects, &mut tcod, &mut game);
-                }
-            }
-        }
+    #[test]
+    fn one_over_the_capacity_is_encumbering() {
+        let (objects, game) = carrying(0);
+        let capacity = carry_capacity(&objects, &game);
 
-        level_up(&mut game_objects, game, tcod);
+        let (objects, game) = carrying(capacity + 1);
+        assert!(is_encumbered(&objects, &game));
+        assert_eq!(
+            player_speed(&objects, &game),
+            objects[PLAYER].speed() * constants::encumbrance::SPEED_PERCENT / 100
+        );
     }
-}
-
-fn main_menu(mut tcod: &mut Tcod) {
-    use constants::gui::menus::*;
-    let img = tcod::image::Image::from_file(main::IMAGE_PATH)
-        .ok()
-        .expect("Background image not found");
 
-    while !tcod.root.window_closed() {
-        // show the image, at twice the regular console resolution
-        tcod::image::blit_2x(&img, (0, 0), (-1, -1), &mut tcod.root, (0, 0));
+    #[test]
+    fn critical_hits_leave_critical_popups() {
+        let (mut objects, mut game, _) = open_floor(vec![]);
+        let troll = spawn("Troll", 11, 10, &mut objects, &game);
 
-        tcod.root.set_default_foreground(colors::LIGHT_YELLOW);
-        tcod.root.print_ex(
-            constants::gui::SCREEN_WIDTH / 2,
-            constants::gui::SCREEN_HEIGHT / 2 - 4,
-            BackgroundFlag::None,
-            TextAlignment::Center,
-            constants::GAME_TITLE,
-        );
-        tcod.root.print_ex(
-            constants::gui::SCREEN_WIDTH / 2,
-            constants::gui::SCREEN_HEIGHT - 2,
-            BackgroundFlag::None,
-            TextAlignment::Center,
-            main::AUTHOR_LINE,
-        );
+        objects[troll].take_damage(3, true, &mut game);
+        objects[troll].take_hit(6, true, true, &mut game);
+        let popups: Vec<(i32, bool)> = game
+            .damage_popups
+            .iter()
+            .map(|popup| (popup.amount, popup.critical))
+            .collect();
+        assert_eq!(popups, vec![(3, false), (6, true)]);
+    }
 
-        // show options and wait for the players choice
-        let choices = &[main::NEW_GAME, main::CONTINUE, main::QUIT];
-        let choice = menu(
-            main::MENU_NO_HEADER,
-            choices,
-            main::START_MENU_WIDTH,
-            &mut tcod,
-        );
+    #[test]
+    fn waiting_ends_each_turn_like_any_other() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![Input::Menu(Some(0))]);
+        objects[PLAYER].fighter.as_mut().unwrap().xp = LEVEL_UP_BASE + LEVEL_UP_FACTOR;
+        let turns = game.turns;
 
-        match choice {
-            Some(0) => {
-                // new game
-                let (objects, mut game) = new_game(tcod);
-                play_game(objects, &mut game, tcod);
-            }
-            Some(1) => match load_game() {
-                Ok((objects, mut game)) => {
-                    initialize_fov(&game, tcod);
-                    play_game(objects, &mut game, tcod);
-                }
-                Err(_e) => {
-                    msgbox("\nNo saved game to load.\n", 24, &mut tcod);
-                    continue;
-                }
-            },
-            Some(2) => {
-                // quit
-                break;
-            }
-            _ => {}
-        }
+        wait_turns(3, &mut objects, &mut ui, &mut game);
+        assert_eq!(game.turns, turns + 3);
+        assert_eq!(objects[PLAYER].level, 2);
     }
-}
 
-fn msgbox(text: &str, width: i32, mut tcod: &mut Tcod) {
-    let options: &[&str] = &[];
-    menu(text, options, width, &mut tcod);
-}
+    #[test]
+    fn an_idle_monster_keeps_its_home_through_a_chase() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let orc = spawn("Orc", 30, 20, &mut objects, &game);
+        objects[orc].ai = Some(Ai::Chasing {
+            last_seen: (30, 20),
+            num_turns: 3,
+            home: Some((20, 20)),
+        });
 
-fn save_game(objects: &[GameObject], game: &Game) -> Result<(), Box<Error>> {
-    let save_data = serde_json::to_string(&(objects, game))?;
-    let mut file = File::create(constants::SAVE_FILE_NAME)?;
-    file.write_all(save_data.as_bytes())?;
-    Ok(())
-}
+        // the trail ends where it stands, far from the home it started out at
+        ai_take_turn(orc, &mut objects, &mut ui, &mut game);
+        assert_eq!(objects[orc].ai, Some(Ai::Idle { home: (20, 20) }));
+        // and from then on it heads back there
+        ai_take_turn(orc, &mut objects, &mut ui, &mut game);
+        assert_eq!(objects[orc].x, 29);
+    }
 
-fn load_game() -> Result<(Vec<GameObject>, Game), Box<Error>> {
-    let mut json_save_state = String::new();
-    let mut file = File::open(constants::SAVE_FILE_NAME)?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Vec<GameObject>, Game)>(&json_save_state)?;
-    Ok(result)
-}
+    #[test]
+    fn a_chase_without_a_home_settles_where_it_ends() {
+        let (mut objects, mut game, mut ui) = open_floor(vec![]);
+        let orc = spawn("Orc", 30, 20, &mut objects, &game);
+        objects[orc].ai = Some(Ai::Chasing {
+            last_seen: (30, 20),
+            num_turns: 3,
+            home: None,
+        });
 
-fn main() {
-    let root = Root::initializer()
-        .font(constants::FONT_PATH, FontLayout::Tcod)
-        .font_type(FontType::Greyscale)
-        .size(constants::gui::SCREEN_WIDTH, constants::gui::SCREEN_HEIGHT)
-        .title(constants::gui::menus::main::GAME_CONSOLE_HEADER)
-        .init();
+        ai_take_turn(orc, &mut objects, &mut ui, &mut game);
+        assert_eq!(objects[orc].ai, Some(Ai::Idle { home: (30, 20) }));
+    }
 
-    tcod::system::set_fps(LIMIT_FPS);
+    #[test]
+    fn bars_fill_in_proportion() {
+        assert_eq!(bar_width(5, 10, 20), 10);
+        assert_eq!(bar_width(10, 10, 20), 20);
+        assert_eq!(bar_width(0, 10, 20), 0);
+    }
 
-    let mut tcod = Tcod {
-        root,
-        con: Offscreen::new(constants::gui::MAP_WIDTH, constants::gui::MAP_HEIGHT),
-        panel: Offscreen::new(constants::gui::SCREEN_WIDTH, constants::gui::PANEL_HEIGHT),
-        fov: FovMap::new(constants::gui::MAP_WIDTH, constants::gui::MAP_HEIGHT),
-        mouse: Default::default(),
-    };
+    #[test]
+    fn negative_values_leave_the_bar_empty() {
+        assert_eq!(bar_width(-3, 10, 20), 0);
+        assert_eq!(bar_width(5, -10, 20), 0);
+    }
 
-    main_menu(&mut tcod);
+    #[test]
+    fn an_empty_maximum_leaves_the_bar_empty() {
+        assert_eq!(bar_width(0, 0, 20), 0);
+        assert_eq!(bar_width(5, 0, 20), 0);
+    }
+
+    #[test]
+    fn values_past_the_maximum_fill_the_bar() {
+        assert_eq!(bar_width(15, 10, 20), 20);
+    }
 }
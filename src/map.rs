@@ -0,0 +1,264 @@
+pub mod branches;
+pub mod shops;
+pub mod spawn_tables;
+
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::constants::gui::glyphs;
+use crate::constants::gui::{MAP_HEIGHT, MAP_WIDTH};
+use crate::Map;
+
+/// Picks the line-drawing character for a wall so it joins up with the explored
+/// walls around it. Unexplored neighbors are ignored, otherwise the solid rock
+/// behind every wall would turn it into a crossing.
+pub fn wall_glyph(map: &Map, x: i32, y: i32) -> char {
+    let is_seen_wall = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && x < MAP_WIDTH
+            && y < MAP_HEIGHT
+            && map[x as usize][y as usize].block_sight
+            && map[x as usize][y as usize].explored
+    };
+
+    let mut connections = 0;
+    if is_seen_wall(x, y - 1) {
+        connections |= glyphs::NORTH;
+    }
+    if is_seen_wall(x, y + 1) {
+        connections |= glyphs::SOUTH;
+    }
+    if is_seen_wall(x + 1, y) {
+        connections |= glyphs::EAST;
+    }
+    if is_seen_wall(x - 1, y) {
+        connections |= glyphs::WEST;
+    }
+
+    glyphs::WALLS[connections]
+}
+
+/// Walks a Bresenham line between two tiles and returns every tile after `from`,
+/// ending with `to` itself. The line is always drawn from the same end, so it runs
+/// over the same tiles whichever way round it's asked for.
+pub fn line_between(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    if to < from {
+        let mut tiles = bresenham(to, from);
+        tiles.pop();
+        tiles.reverse();
+        tiles.push(to);
+        return tiles;
+    }
+    bresenham(from, to)
+}
+
+fn bresenham(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let (target_x, target_y) = to;
+
+    let dx = (target_x - x).abs();
+    let dy = -(target_y - y).abs();
+    let step_x = if x < target_x { 1 } else { -1 };
+    let step_y = if y < target_y { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    let mut tiles = vec![];
+    while (x, y) != to {
+        let doubled_error = 2 * error;
+        if doubled_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+        tiles.push((x, y));
+    }
+
+    tiles
+}
+
+/// Returns false if any tile strictly between the two blocks sight. The end points
+/// themselves are never checked, so a monster standing in a doorway can still be seen.
+pub fn has_line_of_sight(from: (i32, i32), to: (i32, i32), map: &Map) -> bool {
+    line_between(from, to)
+        .iter()
+        .filter(|&&tile| tile != to)
+        .all(|&(x, y)| !map[x as usize][y as usize].block_sight)
+}
+
+/// Searches walkable terrain, moving the same eight ways the player can. Objects are
+/// ignored, only walls count as obstacles. Returns the tiles after `from` up to and
+/// including `to`, or None if there is no way through.
+pub fn find_path(map: &Map, from: (i32, i32), to: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+    find_path_through(from, to, |(x, y)| !map[x as usize][y as usize].blocked)
+}
+
+/// Like find_path, but only ever steps onto tiles the player has explored, so
+/// travelling doesn't give away the layout of unexplored parts of the floor
+pub fn find_explored_path(map: &Map, from: (i32, i32), to: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+    find_path_through(from, to, |(x, y)| {
+        let tile = &map[x as usize][y as usize];
+        !tile.blocked && tile.explored
+    })
+}
+
+/// Routes a monster around the walls and around whatever `occupied` reports, so it
+/// can get past the others in a corridor. The destination itself may be occupied,
+/// since that's usually who the monster is after.
+pub fn find_route(
+    map: &Map,
+    from: (i32, i32),
+    to: (i32, i32),
+    occupied: impl Fn((i32, i32)) -> bool,
+) -> Option<Vec<(i32, i32)>> {
+    find_path_through(from, to, |tile| {
+        !map[tile.0 as usize][tile.1 as usize].blocked && (tile == to || !occupied(tile))
+    })
+}
+
+/// A* over the tiles `passable` allows. Every step costs the same, diagonals included,
+/// so the number of moves left to the target is a heuristic that never overestimates.
+fn find_path_through(
+    from: (i32, i32),
+    to: (i32, i32),
+    passable: impl Fn((i32, i32)) -> bool,
+) -> Option<Vec<(i32, i32)>> {
+    if from == to {
+        return Some(vec![]);
+    }
+
+    let index = |(x, y): (i32, i32)| (x * MAP_HEIGHT + y) as usize;
+    let moves_left = |(x, y): (i32, i32)| cmp::max((to.0 - x).abs(), (to.1 - y).abs());
+    let mut came_from: Vec<Option<(i32, i32)>> = vec![None; (MAP_WIDTH * MAP_HEIGHT) as usize];
+    let mut cost = vec![i32::MAX; (MAP_WIDTH * MAP_HEIGHT) as usize];
+    let mut frontier = BinaryHeap::new();
+
+    came_from[index(from)] = Some(from);
+    cost[index(from)] = 0;
+    frontier.push(Reverse((moves_left(from), from)));
+
+    while let Some(Reverse((_, (x, y)))) = frontier.pop() {
+        if (x, y) == to {
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(previous) = came_from[index(current)].filter(|&tile| tile != from) {
+                path.push(previous);
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let next_cost = cost[index((x, y))] + 1;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let next = (x + dx, y + dy);
+                let in_bounds =
+                    next.0 >= 0 && next.1 >= 0 && next.0 < MAP_WIDTH && next.1 < MAP_HEIGHT;
+                if !in_bounds || next_cost >= cost[index(next)] || !passable(next) {
+                    continue;
+                }
+
+                came_from[index(next)] = Some((x, y));
+                cost[index(next)] = next_cost;
+                frontier.push(Reverse((next_cost + moves_left(next), next)));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tile;
+
+    /// A map that's all wall apart from the tiles given
+    fn carve(open: &[(i32, i32)]) -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for &(x, y) in open {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+        map
+    }
+
+    #[test]
+    fn a_path_follows_the_corridor() {
+        let corridor: Vec<_> = (1..=6).map(|x| (x, 1)).collect();
+        let map = carve(&corridor);
+
+        let path = find_path(&map, (1, 1), (6, 1));
+        assert_eq!(path, Some(corridor[1..].to_vec()));
+    }
+
+    #[test]
+    fn there_is_no_path_to_a_walled_off_target() {
+        let mut open: Vec<_> = (1..=4).map(|x| (x, 1)).collect();
+        open.push((6, 1));
+        let map = carve(&open);
+
+        assert_eq!(find_path(&map, (1, 1), (6, 1)), None);
+    }
+
+    #[test]
+    fn diagonal_moves_cut_corners() {
+        // the only way on is between two walls that only touch at their corners
+        let map = carve(&[(1, 1), (2, 2)]);
+
+        assert_eq!(find_path(&map, (1, 1), (2, 2)), Some(vec![(2, 2)]));
+    }
+
+    #[test]
+    fn a_wall_blocks_the_line_of_sight() {
+        let mut room = vec![];
+        for x in 1..=5 {
+            for y in 1..=3 {
+                room.push((x, y));
+            }
+        }
+        let mut map = carve(&room);
+        map[3][2] = Tile::wall();
+
+        assert!(!has_line_of_sight((1, 2), (5, 2), &map));
+        assert!(has_line_of_sight((1, 1), (5, 1), &map));
+    }
+
+    #[test]
+    fn adjacent_tiles_always_see_each_other() {
+        // there's nothing between them, walls on either end don't count
+        let map = carve(&[]);
+
+        assert_eq!(line_between((4, 4), (5, 5)), vec![(5, 5)]);
+        assert!(has_line_of_sight((4, 4), (5, 5), &map));
+        assert!(has_line_of_sight((4, 4), (4, 3), &map));
+    }
+
+    #[test]
+    fn sight_is_the_same_both_ways() {
+        let mut room = vec![];
+        for x in 1..=8 {
+            for y in 1..=6 {
+                room.push((x, y));
+            }
+        }
+        let mut map = carve(&room);
+        map[4][3] = Tile::wall();
+        map[6][2] = Tile::wall();
+
+        for &from in &room {
+            for &to in &room {
+                assert_eq!(
+                    has_line_of_sight(from, to, &map),
+                    has_line_of_sight(to, from, &map),
+                    "{:?} and {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+}
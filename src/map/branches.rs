@@ -0,0 +1,127 @@
+use crate::constants::{self, branches};
+use crate::map::spawn_tables::FloorTheme;
+
+/// The separate stretches of dungeon. The main one goes all the way down to the Amulet,
+/// the side branches split off it partway and end after a few floors.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Branch {
+    Main,
+    Crypt,
+    Mines,
+}
+
+impl Branch {
+    pub const SIDE: [Branch; 2] = [Branch::Crypt, Branch::Mines];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Branch::Main => branches::MAIN_NAME,
+            Branch::Crypt => branches::crypt::NAME,
+            Branch::Mines => branches::mines::NAME,
+        }
+    }
+
+    /// The main dungeon floor holding the stairs down into the branch
+    pub fn entry_depth(self) -> u32 {
+        match self {
+            Branch::Main => 0,
+            Branch::Crypt => branches::crypt::ENTRY_DEPTH,
+            Branch::Mines => branches::mines::ENTRY_DEPTH,
+        }
+    }
+
+    /// How many floors the branch has
+    pub fn floors(self) -> u32 {
+        match self {
+            Branch::Main => constants::amulet::FINAL_DEPTH,
+            Branch::Crypt => branches::crypt::FLOORS,
+            Branch::Mines => branches::mines::FLOORS,
+        }
+    }
+
+    /// Said when the player steps down into the branch from the main dungeon
+    pub fn arrival_message(self) -> Option<&'static str> {
+        match self {
+            Branch::Main => None,
+            Branch::Crypt => Some(branches::crypt::ARRIVAL),
+            Branch::Mines => Some(branches::mines::ARRIVAL),
+        }
+    }
+
+    /// The side branches are always home to the same monsters, only the main dungeon
+    /// varies from floor to floor
+    pub fn theme(self, seed: u32, depth: u32) -> FloorTheme {
+        match self {
+            Branch::Main => FloorTheme::pick(seed, depth),
+            Branch::Crypt => FloorTheme::TrollDen,
+            Branch::Mines => FloorTheme::OrcWarren,
+        }
+    }
+}
+
+/// Where a floor sits in the dungeon: the branch it belongs to and how many floors
+/// down that branch it is. Town is depth 0 of the main dungeon.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DungeonLevel {
+    pub branch: Branch,
+    pub depth: u32,
+}
+
+impl DungeonLevel {
+    pub fn main(depth: u32) -> Self {
+        DungeonLevel {
+            branch: Branch::Main,
+            depth,
+        }
+    }
+
+    pub fn is_town(self) -> bool {
+        self == DungeonLevel::main(0)
+    }
+
+    /// How far below town the floor really is, counting the main dungeon floors above
+    /// a branch. Spawn tables go by this, so a branch is as dangerous as its depth.
+    pub fn absolute_depth(self) -> u32 {
+        self.branch.entry_depth() + self.depth
+    }
+
+    /// Every few floors the main dungeon holds a boss instead of an ordinary floor
+    pub fn is_boss_floor(self) -> bool {
+        self.branch == Branch::Main
+            && self.depth > 0
+            && self.depth.is_multiple_of(constants::boss::EVERY)
+    }
+
+    pub fn has_stairs_down(self) -> bool {
+        self.depth < self.branch.floors()
+    }
+
+    /// The bottom of the main dungeon, where the Amulet waits
+    pub fn is_final_floor(self) -> bool {
+        self == DungeonLevel::main(constants::amulet::FINAL_DEPTH)
+    }
+
+    pub fn below(self) -> Self {
+        DungeonLevel {
+            depth: self.depth + 1,
+            ..self
+        }
+    }
+
+    /// The floor the up stairs lead to. The top of a branch leads back out to the main
+    /// dungeon floor it split off from.
+    pub fn above(self) -> Self {
+        if self.depth == 1 && self.branch != Branch::Main {
+            DungeonLevel::main(self.branch.entry_depth())
+        } else {
+            DungeonLevel {
+                depth: self.depth - 1,
+                ..self
+            }
+        }
+    }
+
+    pub fn label(self) -> String {
+        branches::create_level_label(self.branch.name(), self.depth)
+    }
+}
@@ -0,0 +1,61 @@
+use rand::distributions::{IndependentSample, WeightedChoice};
+use rand::{Rng, XorShiftRng};
+
+use crate::constants::shop;
+use crate::map::branches::Branch;
+use crate::map::spawn_tables;
+use crate::{Item, Rect};
+
+/// Decides whether a floor gets a shop, and if so which room it takes over. Never the
+/// first room, that's where the player arrives, and never the last since the stairs
+/// are there.
+pub fn choose_room(rooms: &[Rect], level: u32, rng: &mut XorShiftRng) -> Option<usize> {
+    if level < shop::MIN_LEVEL || rooms.len() < 3 || rng.gen::<f32>() >= shop::CHANCE {
+        return None;
+    }
+
+    Some(rng.gen_range(1, rooms.len() - 1))
+}
+
+/// Where the shopkeeper stands: against the room's back wall, clear of the doorways
+/// the tunnels cut into the middle of each side
+pub fn counter(room: Rect) -> (i32, i32) {
+    let (center_x, _) = room.center();
+    (center_x, room.y1 + 1)
+}
+
+/// Fills the shelves from the same table the floor's loot comes from, so a shop
+/// never sells anything that couldn't have turned up this deep anyway
+pub fn roll_stock(level: u32, branch: Branch, rng: &mut XorShiftRng) -> Vec<Item> {
+    let mut item_chances = spawn_tables::item_table(level, branch);
+    let item_choice = WeightedChoice::new(&mut item_chances);
+
+    (0..shop::STOCK_SIZE)
+        .map(|_| item_choice.ind_sample(rng))
+        .collect()
+}
+
+pub fn price(item: Item) -> i32 {
+    match item {
+        Item::Heal => 20,
+        Item::Lightning => 40,
+        Item::Confuse => 30,
+        Item::Fireball => 50,
+        Item::Recall => 35,
+        Item::Identify => 30,
+        Item::RemoveCurse => 40,
+        Item::Dig => 45,
+        Item::Muddle => 25,
+        Item::Boomerang => 40,
+        Item::Bow => 60,
+        Item::Crossbow => 90,
+        Item::Arrows => 15,
+        Item::Sword => 80,
+        Item::Shield => 70,
+        Item::LeatherArmor => 65,
+        Item::Helmet => 40,
+        Item::Boots => 35,
+        // none of these ever turns up in the loot table, so no shop stocks them
+        Item::Key(_) | Item::Amulet | Item::Scripted | Item::Dagger | Item::Crown => 0,
+    }
+}
@@ -0,0 +1,409 @@
+use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
+use rand::{SeedableRng, XorShiftRng};
+
+use crate::map::branches::Branch;
+use crate::monsters::MonsterDef;
+use crate::scripts::ScriptDef;
+use crate::traps::TrapKind;
+use crate::{from_dungeon_level, Item, Transition};
+
+/// The identity of a floor, narrowing down which monsters show up on it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FloorTheme {
+    Mixed,
+    OrcWarren,
+    TrollDen,
+}
+
+impl FloorTheme {
+    /// Picks the theme for a floor. It only depends on the run's seed and the depth,
+    /// so the same run always gets the same sequence of floors.
+    pub fn pick(seed: u32, level: u32) -> Self {
+        let mut rng = XorShiftRng::from_seed([seed, level, 0x85EB_CA6B, 0xC2B2_AE35]);
+        let mut themes = theme_table(level);
+        WeightedChoice::new(&mut themes).ind_sample(&mut rng)
+    }
+}
+
+pub fn theme_table(level: u32) -> Vec<Weighted<FloorTheme>> {
+    vec![
+        Weighted {
+            weight: 60,
+            item: FloorTheme::Mixed,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 25)], level),
+            item: FloorTheme::OrcWarren,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(4, 15)], level),
+            item: FloorTheme::TrollDen,
+        },
+    ]
+}
+
+/// The most monsters a whole floor may hold at this depth
+pub fn monster_budget(level: u32) -> u32 {
+    from_dungeon_level(
+        &[
+            Transition::new(1, 10),
+            Transition::new(4, 15),
+            Transition::new(6, 24),
+        ],
+        level,
+    )
+}
+
+/// How many items a single room may hold at this depth
+pub fn max_items(level: u32) -> u32 {
+    from_dungeon_level(&[Transition::new(1, 1), Transition::new(4, 2)], level)
+}
+
+/// Weights for every monster in the bestiary, by index. A theme can override a
+/// monster's weight for the depth or raise it to a minimum.
+pub fn monster_table(
+    level: u32,
+    theme: FloorTheme,
+    bestiary: &[MonsterDef],
+) -> Vec<Weighted<usize>> {
+    bestiary
+        .iter()
+        .enumerate()
+        .map(|(index, monster)| {
+            let transitions: Vec<Transition> = monster
+                .spawn_weights
+                .iter()
+                .map(|&(level, weight)| Transition::new(level, weight))
+                .collect();
+            let mut weight = from_dungeon_level(&transitions, level);
+
+            if let Some(adjustment) = monster
+                .themes
+                .iter()
+                .find(|adjustment| adjustment.theme == theme)
+            {
+                weight = adjustment
+                    .weight
+                    .unwrap_or(weight)
+                    .max(adjustment.min_weight);
+            }
+
+            Weighted {
+                weight,
+                item: index,
+            }
+        })
+        .collect()
+}
+
+/// Weights for every scripted item, by index
+pub fn script_table(level: u32, scripts: &[ScriptDef]) -> Vec<Weighted<usize>> {
+    scripts
+        .iter()
+        .enumerate()
+        .map(|(index, script)| {
+            let transitions: Vec<Transition> = script
+                .spawn_weights
+                .iter()
+                .map(|&(level, weight)| Transition::new(level, weight))
+                .collect();
+            Weighted {
+                weight: from_dungeon_level(&transitions, level),
+                item: index,
+            }
+        })
+        .collect()
+}
+
+pub fn item_table(level: u32, branch: Branch) -> Vec<Weighted<Item>> {
+    let mut table = vec![
+        Weighted {
+            weight: 35,
+            item: Item::Heal,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(4, 25)], level),
+            item: Item::Lightning,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(6, 25)], level),
+            item: Item::Fireball,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 10)], level),
+            item: Item::Confuse,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(3, 10)], level),
+            item: Item::Recall,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 10)], level),
+            item: Item::Identify,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(3, 5)], level),
+            item: Item::RemoveCurse,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 5)], level),
+            item: Item::Dig,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 5)], level),
+            item: Item::Muddle,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(3, 5)], level),
+            item: Item::Boomerang,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 5)], level),
+            item: Item::Bow,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(5, 5)], level),
+            item: Item::Crossbow,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 10)], level),
+            item: Item::Arrows,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(4, 5)], level),
+            item: Item::Sword,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(8, 15)], level),
+            item: Item::Shield,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 10)], level),
+            item: Item::Boots,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(3, 10)], level),
+            item: Item::Helmet,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(5, 10)], level),
+            item: Item::LeatherArmor,
+        },
+    ];
+
+    for chance in table.iter_mut() {
+        chance.weight *= branch_bonus(branch, chance.item);
+    }
+    table
+}
+
+/// The crypt's dead were buried with their scrolls, and the miners left their tools
+/// and gear behind
+fn branch_bonus(branch: Branch, item: Item) -> u32 {
+    match (branch, item) {
+        (Branch::Crypt, Item::Lightning)
+        | (Branch::Crypt, Item::Confuse)
+        | (Branch::Crypt, Item::Fireball)
+        | (Branch::Crypt, Item::Recall)
+        | (Branch::Crypt, Item::Identify)
+        | (Branch::Crypt, Item::RemoveCurse) => 2,
+        (Branch::Mines, Item::Dig)
+        | (Branch::Mines, Item::Bow)
+        | (Branch::Mines, Item::Crossbow)
+        | (Branch::Mines, Item::Arrows)
+        | (Branch::Mines, Item::Sword)
+        | (Branch::Mines, Item::Shield)
+        | (Branch::Mines, Item::LeatherArmor)
+        | (Branch::Mines, Item::Helmet)
+        | (Branch::Mines, Item::Boots) => 2,
+        _ => 1,
+    }
+}
+
+/// Which trap a trapped room hides. The nastier ones only start turning up deeper down.
+pub fn trap_table(level: u32) -> Vec<Weighted<TrapKind>> {
+    vec![
+        Weighted {
+            weight: 40,
+            item: TrapKind::Spike,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(2, 30)], level),
+            item: TrapKind::Dart,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(3, 20)], level),
+            item: TrapKind::Alarm,
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition::new(4, 20)], level),
+            item: TrapKind::Pit,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monsters;
+
+    const DEEPEST: u32 = 20;
+
+    fn weight_of<T: PartialEq>(table: &[Weighted<T>], item: T) -> u32 {
+        table
+            .iter()
+            .find(|chance| chance.item == item)
+            .map_or(0, |chance| chance.weight)
+    }
+
+    fn total<T>(table: &[Weighted<T>]) -> u32 {
+        table.iter().map(|chance| chance.weight).sum()
+    }
+
+    #[test]
+    fn items_unlock_at_their_depths() {
+        // (item, first level it turns up on, its weight from then on)
+        let progression = [
+            (Item::Heal, 1, 35),
+            (Item::Confuse, 2, 10),
+            (Item::Identify, 2, 10),
+            (Item::Dig, 2, 5),
+            (Item::Muddle, 2, 5),
+            (Item::Bow, 2, 5),
+            (Item::Arrows, 2, 10),
+            (Item::Boots, 2, 10),
+            (Item::Recall, 3, 10),
+            (Item::RemoveCurse, 3, 5),
+            (Item::Boomerang, 3, 5),
+            (Item::Helmet, 3, 10),
+            (Item::Lightning, 4, 25),
+            (Item::Sword, 4, 5),
+            (Item::Crossbow, 5, 5),
+            (Item::LeatherArmor, 5, 10),
+            (Item::Fireball, 6, 25),
+            (Item::Shield, 8, 15),
+        ];
+
+        for &(item, unlocked, weight) in &progression {
+            if unlocked > 1 {
+                let table = item_table(unlocked - 1, Branch::Main);
+                assert_eq!(
+                    weight_of(&table, item),
+                    0,
+                    "{:?} before level {}",
+                    item,
+                    unlocked
+                );
+            }
+            for level in unlocked..=DEEPEST {
+                let table = item_table(level, Branch::Main);
+                assert_eq!(
+                    weight_of(&table, item),
+                    weight,
+                    "{:?} on level {}",
+                    item,
+                    level
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn branches_double_their_loot() {
+        let main = item_table(6, Branch::Main);
+        let crypt = item_table(6, Branch::Crypt);
+        let mines = item_table(6, Branch::Mines);
+
+        assert_eq!(
+            weight_of(&crypt, Item::Fireball),
+            2 * weight_of(&main, Item::Fireball)
+        );
+        assert_eq!(weight_of(&crypt, Item::Heal), weight_of(&main, Item::Heal));
+        assert_eq!(
+            weight_of(&mines, Item::Dig),
+            2 * weight_of(&main, Item::Dig)
+        );
+        assert_eq!(
+            weight_of(&mines, Item::Fireball),
+            weight_of(&main, Item::Fireball)
+        );
+    }
+
+    #[test]
+    fn monsters_follow_their_spawn_weights() {
+        let bestiary = monsters::built_in();
+        let index = |name: &str| {
+            bestiary
+                .iter()
+                .position(|monster| monster.name == name)
+                .unwrap()
+        };
+        let weight = |name: &str, level: u32| {
+            let table = monster_table(level, FloorTheme::Mixed, &bestiary);
+            weight_of(&table, index(name))
+        };
+
+        assert_eq!(weight("Orc", 1), 80);
+        assert_eq!(weight("Orc", DEEPEST), 80);
+        assert_eq!(weight("Troll", 2), 0);
+        assert_eq!(weight("Troll", 3), 15);
+        assert_eq!(weight("Troll", 5), 30);
+        assert_eq!(weight("Troll", 7), 60);
+        assert_eq!(weight("Bat", 1), 20);
+        assert_eq!(weight("Bat", 4), 10);
+        assert_eq!(weight("Zombie", 1), 0);
+        assert_eq!(weight("Zombie", 2), 15);
+        assert_eq!(weight("Zombie", 5), 25);
+    }
+
+    #[test]
+    fn themes_adjust_monster_weights() {
+        let bestiary = monsters::built_in();
+        let troll = bestiary
+            .iter()
+            .position(|monster| monster.name == "Troll")
+            .unwrap();
+
+        let warren = monster_table(5, FloorTheme::OrcWarren, &bestiary);
+        let den = monster_table(3, FloorTheme::TrollDen, &bestiary);
+        assert_eq!(weight_of(&warren, troll), 0);
+        assert_eq!(weight_of(&den, troll), 60);
+    }
+
+    #[test]
+    fn bosses_never_spawn_at_random() {
+        let bestiary = monsters::built_in();
+        for level in 1..=DEEPEST {
+            let table = monster_table(level, FloorTheme::Mixed, &bestiary);
+            for (monster, chance) in bestiary.iter().zip(&table) {
+                if monster.boss {
+                    assert_eq!(chance.weight, 0, "{} on level {}", monster.name, level);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_table_is_ever_empty() {
+        let bestiary = monsters::built_in();
+        let themes = [
+            FloorTheme::Mixed,
+            FloorTheme::OrcWarren,
+            FloorTheme::TrollDen,
+        ];
+        let branches = [Branch::Main, Branch::Crypt, Branch::Mines];
+
+        for level in 1..=DEEPEST {
+            assert!(total(&theme_table(level)) > 0, "themes on level {}", level);
+            assert!(total(&trap_table(level)) > 0, "traps on level {}", level);
+            for &theme in &themes {
+                let table = monster_table(level, theme, &bestiary);
+                assert!(total(&table) > 0, "{:?} monsters on level {}", theme, level);
+            }
+            for &branch in &branches {
+                let table = item_table(level, branch);
+                assert!(total(&table) > 0, "{:?} items on level {}", branch, level);
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::cmp;
+use std::fs::File;
+use std::io::Read;
+
+use tcod::colors::Color;
+
+use crate::constants;
+use crate::effects::EffectKind;
+use crate::map::spawn_tables::FloorTheme;
+
+// the definitions the game ships with, used when there is no monsters file to read
+const BUILT_IN: &str = include_str!("../monsters.json");
+
+/// Everything needed to spawn one kind of monster, read from the monsters file so
+/// new ones can be added without recompiling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonsterDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub xp: i32,
+    // how fast it acts, normal speed is 100
+    #[serde(default = "crate::normal_speed")]
+    pub speed: i32,
+    pub ai: MonsterAi,
+    pub death_flavor: Option<String>,
+    pub on_hit: Option<OnHit>,
+    // (dungeon level, weight) pairs, the weight holds from that level on
+    pub spawn_weights: Vec<(u32, u32)>,
+    #[serde(default)]
+    pub themes: Vec<ThemeWeight>,
+    // bosses never spawn at random, each one waits alone on its own boss floor
+    #[serde(default)]
+    pub boss: bool,
+}
+
+/// How a freshly spawned monster behaves before it spots the player
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MonsterAi {
+    Basic,
+    Idle,
+}
+
+/// A status the monster's hits may leave behind
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OnHit {
+    pub effect: EffectKind,
+    pub turns: i32,
+    pub chance: f32,
+}
+
+/// Changes a monster's spawn weight on floors of one theme
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThemeWeight {
+    pub theme: FloorTheme,
+    // replaces the weight for the current depth outright
+    #[serde(default)]
+    pub weight: Option<u32>,
+    // raises the weight to at least this much
+    #[serde(default)]
+    pub min_weight: u32,
+}
+
+/// Reads the monster definitions from the monsters file. A missing file means the
+/// built-in monsters, a broken one gets a warning and the built-in monsters too.
+pub fn load() -> Vec<MonsterDef> {
+    let mut json = String::new();
+    let read = File::open(constants::MONSTERS_FILE_NAME)
+        .and_then(|mut file| file.read_to_string(&mut json));
+    if read.is_ok() {
+        match serde_json::from_str(&json) {
+            Ok(definitions) => return definitions,
+            Err(_) => eprintln!("{}", constants::create_bad_monsters_warning()),
+        }
+    }
+
+    built_in()
+}
+
+pub fn built_in() -> Vec<MonsterDef> {
+    serde_json::from_str(BUILT_IN).expect("built-in monster definitions are valid")
+}
+
+pub fn find<'a>(bestiary: &'a [MonsterDef], name: &str) -> Option<&'a MonsterDef> {
+    bestiary.iter().find(|monster| monster.name == name)
+}
+
+/// The boss guarding the given boss floor, counting from 1. They come in the order
+/// the monsters file lists them, and once the list runs out the last one keeps coming.
+pub fn boss_for(bestiary: &[MonsterDef], boss_number: u32) -> Option<&MonsterDef> {
+    let bosses: Vec<&MonsterDef> = bestiary.iter().filter(|monster| monster.boss).collect();
+    let index = cmp::min(
+        boss_number.saturating_sub(1) as usize,
+        bosses.len().checked_sub(1)?,
+    );
+    Some(bosses[index])
+}
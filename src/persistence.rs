@@ -0,0 +1,163 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::replay::Input;
+use crate::{constants, inventory_label, monsters, scripts, Game, GameObject, Objects, PLAYER};
+
+/// A short summary of the saved run, written next to the save so the main menu
+/// can describe it without deserializing the whole game.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveMeta {
+    pub dungeon_level: u32,
+    pub player_level: i32,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub turns: u32,
+    pub timestamp: u64,
+    // saves written before winning was possible don't have this
+    #[serde(default)]
+    pub won: bool,
+}
+
+impl SaveMeta {
+    pub fn new(objects: &Objects, game: &Game) -> Self {
+        let player = &objects[PLAYER];
+        let timestamp = timestamp();
+
+        SaveMeta {
+            dungeon_level: game.dungeon_level.absolute_depth(),
+            player_level: player.level,
+            hp: player.fighter.map_or(0, |f| f.hp),
+            max_hp: player.max_hp(game),
+            turns: game.turns,
+            timestamp,
+            won: game.won,
+        }
+    }
+
+    /// Minutes since the save was written, zero if the clock went backwards
+    pub fn minutes_ago(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs().saturating_sub(self.timestamp) / 60)
+    }
+}
+
+pub fn save_game(objects: &Objects, game: &Game) -> Result<(), Box<dyn Error>> {
+    let save_data = serde_json::to_string(&(objects, game))?;
+    let mut file = File::create(constants::SAVE_FILE_NAME)?;
+    file.write_all(save_data.as_bytes())?;
+
+    let meta_data = serde_json::to_string(&SaveMeta::new(objects, game))?;
+    let mut meta_file = File::create(constants::SAVE_META_FILE_NAME)?;
+    meta_file.write_all(meta_data.as_bytes())?;
+    Ok(())
+}
+
+pub fn load_game() -> Result<(Objects, Game), Box<dyn Error>> {
+    let mut json_save_state = String::new();
+    let mut file = File::open(constants::SAVE_FILE_NAME)?;
+    file.read_to_string(&mut json_save_state)?;
+    let (objects, mut game) = serde_json::from_str::<(Objects, Game)>(&json_save_state)?;
+    game.bestiary = monsters::load();
+    game.scripts = scripts::load();
+    game.autosaved_at = game.turns;
+    // a replay has to know the walk the player was on ended here too
+    game.inputs.push(Input::Resumed);
+    Ok((objects, game))
+}
+
+/// Seconds since the epoch, zero if the clock is set before it
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Removes the save and its summary, so a permadeath run can't be continued. Missing
+/// files are fine, there may never have been a save.
+pub fn delete_save() {
+    let _ = fs::remove_file(constants::SAVE_FILE_NAME);
+    let _ = fs::remove_file(constants::SAVE_META_FILE_NAME);
+}
+
+/// Writes a plain text account of a finished run next to the game, returning the
+/// name of the file it went into
+pub fn write_morgue(objects: &Objects, game: &Game) -> Result<String, Box<dyn Error>> {
+    use constants::morgue;
+
+    let player = &objects[PLAYER];
+    let mut text = morgue::create_summary(
+        game.class.stats().name,
+        player.level,
+        &game.dungeon_level.label(),
+        game.turns,
+        game.hurt_by.as_deref().unwrap_or(morgue::UNKNOWN_CAUSE),
+    );
+    text.push_str(&morgue::create_stats(
+        player.max_hp(game),
+        player.power(game),
+        player.defense(game),
+        player.fighter.map_or(0, |f| f.xp),
+    ));
+    text.push_str(&morgue::create_record(game.deepest, game.kills, game.gold));
+
+    text.push_str(morgue::ACHIEVEMENTS_HEADER);
+    if game.achievements.is_empty() {
+        text.push_str(morgue::NO_ACHIEVEMENTS);
+    }
+    for achievement in &game.achievements {
+        text.push_str(&format!(
+            "  {}
+",
+            achievement.name()
+        ));
+    }
+
+    text.push_str(morgue::INVENTORY_HEADER);
+    if game.inventory.is_empty() {
+        text.push_str(morgue::EMPTY_INVENTORY);
+    }
+    for item in &game.inventory {
+        text.push_str(&format!("  {}\n", inventory_label(item, game)));
+    }
+
+    text.push_str(morgue::MESSAGES_HEADER);
+    let skipped = game.log.len().saturating_sub(morgue::LAST_MESSAGES);
+    for (message, _) in game.log.iter().skip(skipped) {
+        text.push_str(&format!("  {}\n", message));
+    }
+
+    let file_name = morgue::create_file_name(timestamp());
+    fs::write(&file_name, text)?;
+    Ok(file_name)
+}
+
+/// Reads the summary of the last save, if there is one and it's readable
+pub fn read_save_meta() -> Option<SaveMeta> {
+    let mut json_meta = String::new();
+    let mut file = File::open(constants::SAVE_META_FILE_NAME).ok()?;
+    file.read_to_string(&mut json_meta).ok()?;
+    serde_json::from_str(&json_meta).ok()
+}
+
+/// The stash is kept apart from the run's save so it carries over into new games
+pub fn save_stash(stash: &[GameObject]) -> Result<(), Box<dyn Error>> {
+    let stash_data = serde_json::to_string(stash)?;
+    let mut file = File::create(constants::STASH_FILE_NAME)?;
+    file.write_all(stash_data.as_bytes())?;
+    Ok(())
+}
+
+/// No stash file just means nothing has been stored yet
+pub fn load_stash() -> Result<Vec<GameObject>, Box<dyn Error>> {
+    let mut json_stash = String::new();
+    match File::open(constants::STASH_FILE_NAME) {
+        Ok(mut file) => file.read_to_string(&mut json_stash)?,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+    Ok(serde_json::from_str(&json_stash)?)
+}
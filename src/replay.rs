@@ -0,0 +1,208 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::time::Duration;
+
+use crate::constants::{self, replay};
+use crate::keybindings::Action;
+use crate::settings::Settings;
+use crate::{Class, Game, GameObject};
+
+/// One thing the player did that the game reacted to. Played back in order from the
+/// run's seed, these make the same run again.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Input {
+    // a key press during play, None when the key isn't bound to anything
+    Key(Option<Action>),
+    // a click on the map, setting off a walk to that tile
+    Click((i32, i32)),
+    // a frame where the player walked on along their path without pressing anything
+    Idle,
+    Menu(Option<usize>),
+    Target(Option<(i32, i32)>),
+    // the run was saved and continued here, which ends any walk the player was on
+    Resumed,
+}
+
+/// Everything needed to watch a finished run again: how it started and every input
+/// after that
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: u32,
+    pub class: Class,
+    pub daily: bool,
+    // the settings the run was played with, some of them change how it goes
+    pub settings: Settings,
+    pub inputs: Vec<Input>,
+    // what the town stash held when the run began
+    #[serde(default)]
+    pub stash: Vec<GameObject>,
+}
+
+impl Recording {
+    pub fn new(game: &Game, settings: &Settings, inputs: &[Input]) -> Self {
+        Recording {
+            seed: game.seed,
+            class: game.class,
+            daily: game.daily,
+            settings: settings.clone(),
+            inputs: inputs.to_vec(),
+            stash: game.starting_stash.clone(),
+        }
+    }
+
+    /// The recorded settings, minus anything that would touch the player's files
+    pub fn playback_settings(&self) -> Settings {
+        Settings {
+            event_log: false,
+            autosave_turns: 0,
+            permadeath: false,
+            ..self.settings.clone()
+        }
+    }
+}
+
+pub fn save(recording: &Recording) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        constants::REPLAY_FILE_NAME,
+        serde_json::to_string(recording)?,
+    )?;
+    Ok(())
+}
+
+pub fn load() -> Result<Recording, Box<dyn Error>> {
+    let mut json_recording = String::new();
+    let mut file = File::open(constants::REPLAY_FILE_NAME)?;
+    file.read_to_string(&mut json_recording)?;
+    Ok(serde_json::from_str(&json_recording)?)
+}
+
+/// Writes down the inputs of the run being played, or feeds a recorded run's inputs
+/// back in while it's being watched
+#[derive(Default)]
+pub struct Replay {
+    inputs: Vec<Input>,
+    playback: Option<Playback>,
+}
+
+struct Playback {
+    inputs: Vec<Input>,
+    next: usize,
+    // index into the replay delays, higher is faster
+    speed: usize,
+    // the stash as the replayed run has left it so far, standing in for the stash file
+    stash: Vec<GameObject>,
+}
+
+impl Replay {
+    /// Starts writing down a run, carrying on from whatever it had recorded before
+    pub fn start_recording(&mut self, inputs: Vec<Input>) {
+        self.inputs = inputs;
+        self.playback = None;
+    }
+
+    pub fn start_playback(&mut self, inputs: Vec<Input>, stash: Vec<GameObject>) {
+        self.inputs.clear();
+        self.playback = Some(Playback {
+            inputs,
+            next: 0,
+            speed: replay::DEFAULT_SPEED,
+            stash,
+        });
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.playback = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// The stash being replayed, None when a run is being played for real
+    pub fn stash_mut(&mut self) -> Option<&mut Vec<GameObject>> {
+        self.playback.as_mut().map(|playback| &mut playback.stash)
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    /// Writes the input down, unless it came out of a replay in the first place
+    pub fn record(&mut self, input: Input) {
+        if self.playback.is_none() {
+            self.inputs.push(input);
+        }
+    }
+
+    /// The next recorded input, None once the replay has run out
+    pub fn next(&mut self) -> Option<Input> {
+        let playback = self.playback.as_mut()?;
+        let input = playback.inputs.get(playback.next).copied();
+        playback.next += 1;
+        input
+    }
+
+    /// The option picked in the menu being shown. Anything else coming up means the
+    /// replay no longer matches the game, so it's cut short.
+    pub fn next_menu(&mut self) -> Option<usize> {
+        match self.next() {
+            Some(Input::Menu(choice)) => choice,
+            _ => {
+                self.run_out();
+                None
+            }
+        }
+    }
+
+    /// The tile aimed at, cut short like `next_menu` when the replay doesn't match
+    pub fn next_target(&mut self) -> Option<(i32, i32)> {
+        match self.next() {
+            Some(Input::Target(target)) => target,
+            _ => {
+                self.run_out();
+                None
+            }
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.playback
+            .as_ref()
+            .is_some_and(|playback| playback.next >= playback.inputs.len())
+    }
+
+    fn run_out(&mut self) {
+        if let Some(playback) = self.playback.as_mut() {
+            playback.next = playback.inputs.len();
+        }
+    }
+
+    pub fn faster(&mut self) {
+        if let Some(playback) = self.playback.as_mut() {
+            playback.speed = (playback.speed + 1).min(replay::DELAYS_MS.len() - 1);
+        }
+    }
+
+    pub fn slower(&mut self) {
+        if let Some(playback) = self.playback.as_mut() {
+            playback.speed = playback.speed.saturating_sub(1);
+        }
+    }
+
+    /// Which speed the replay runs at, counting from 1 for the slowest
+    pub fn speed(&self) -> usize {
+        self.playback
+            .as_ref()
+            .map_or(0, |playback| playback.speed + 1)
+    }
+
+    /// How long to hold each step of the replay on screen
+    pub fn delay(&self) -> Duration {
+        let speed = self
+            .playback
+            .as_ref()
+            .map_or(replay::DEFAULT_SPEED, |playback| playback.speed);
+        Duration::from_millis(replay::DELAYS_MS[speed])
+    }
+}
@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read};
+
+use crate::{constants, persistence, Game, Objects, PLAYER};
+
+/// One finished run, as the hall of fame remembers it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Score {
+    pub class: String,
+    pub player_level: i32,
+    // the deepest floor the run got to
+    pub depth: u32,
+    pub xp: i32,
+    pub turns: u32,
+    pub won: bool,
+    // what ended the run, None for a victory
+    pub killed_by: Option<String>,
+    pub timestamp: u64,
+    // the adaptive difficulty the run ended on, a harder dungeon is worth more
+    #[serde(default = "no_multiplier")]
+    pub multiplier: f32,
+}
+
+// runs recorded before scores were multiplied count as played at normal difficulty
+fn no_multiplier() -> f32 {
+    1.0
+}
+
+impl Score {
+    pub fn new(objects: &Objects, game: &Game) -> Self {
+        let player = &objects[PLAYER];
+        let killed_by = if game.won {
+            None
+        } else {
+            let cause = game.hurt_by.as_deref();
+            Some(
+                cause
+                    .unwrap_or(constants::morgue::UNKNOWN_CAUSE)
+                    .to_string(),
+            )
+        };
+
+        Score {
+            class: game.class.stats().name.to_string(),
+            player_level: player.level,
+            depth: game.deepest,
+            xp: player.fighter.map_or(0, |f| f.xp),
+            turns: game.turns,
+            won: game.won,
+            killed_by,
+            timestamp: persistence::timestamp(),
+            multiplier: game.performance.factor,
+        }
+    }
+
+    pub fn depth_points(&self) -> i32 {
+        self.depth as i32 * constants::hall_of_fame::DEPTH_POINTS
+    }
+
+    /// The run's experience and how deep it got, scaled by how hard the dungeon was
+    pub fn points(&self) -> i32 {
+        ((self.xp + self.depth_points()) as f32 * self.multiplier).round() as i32
+    }
+
+    /// The day the run ended on, counted from the epoch
+    pub fn day(&self) -> u64 {
+        self.timestamp / constants::daily::SECONDS_PER_DAY
+    }
+
+    /// Winning beats everything, then more points, then going deeper, then more
+    /// experience, and a faster run breaks any tie that's left
+    fn rank(&self, other: &Score) -> Ordering {
+        other
+            .won
+            .cmp(&self.won)
+            .then(other.points().cmp(&self.points()))
+            .then(other.depth.cmp(&self.depth))
+            .then(other.xp.cmp(&self.xp))
+            .then(self.turns.cmp(&other.turns))
+    }
+}
+
+/// The recorded runs from best to worst. No scores file just means no run has
+/// ended yet.
+pub fn load(file_name: &str) -> Result<Vec<Score>, Box<dyn Error>> {
+    let mut json_scores = String::new();
+    match File::open(file_name) {
+        Ok(mut file) => file.read_to_string(&mut json_scores)?,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+    Ok(serde_json::from_str(&json_scores)?)
+}
+
+/// Adds a finished run to the scores file, keeping only the best few
+pub fn record(file_name: &str, score: Score) -> Result<(), Box<dyn Error>> {
+    let mut scores = load(file_name)?;
+    scores.push(score);
+    write(file_name, scores)
+}
+
+/// Adds a finished daily run to the daily scores file. Only runs of the same day are
+/// comparable, so whatever is left from earlier days is dropped.
+pub fn record_daily(score: Score) -> Result<(), Box<dyn Error>> {
+    let mut scores = load(constants::DAILY_SCORES_FILE_NAME)?;
+    scores.retain(|other| other.day() == score.day());
+    scores.push(score);
+    write(constants::DAILY_SCORES_FILE_NAME, scores)
+}
+
+fn write(file_name: &str, mut scores: Vec<Score>) -> Result<(), Box<dyn Error>> {
+    scores.sort_by(Score::rank);
+    scores.truncate(constants::hall_of_fame::KEPT);
+
+    fs::write(file_name, serde_json::to_string(&scores)?)?;
+    Ok(())
+}
@@ -0,0 +1,954 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+use tcod::colors::Color;
+
+use crate::constants;
+use crate::constants::scripts::errors;
+use crate::entities::EntityId;
+
+// the scripted items the game ships with, used when there is no item scripts file to read
+const BUILT_IN: &str = include_str!("../item_scripts.json");
+
+/// A scroll or potion whose effect is a script, read from the item scripts file so new
+/// ones can be added without recompiling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub description: String,
+    // (dungeon level, weight) pairs, the weight holds from that level on
+    pub spawn_weights: Vec<(u32, u32)>,
+    // the lines of the script, JSON has no strings running over several lines
+    pub script: Vec<String>,
+}
+
+impl ScriptDef {
+    pub fn compile(&self) -> Result<Script, String> {
+        Script::compile(&self.script.join("\n"))
+    }
+}
+
+/// Anything a script can hold in a variable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Int(i32),
+    Str(String),
+    List(Vec<Value>),
+    // a monster or the player, only ever handed out by the game
+    Entity(EntityId),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "nothing",
+            Value::Bool(_) => "a bool",
+            Value::Int(_) => "a number",
+            Value::Str(_) => "a string",
+            Value::List(_) => "a list",
+            Value::Entity(_) => "a creature",
+        }
+    }
+
+    fn truthy(&self) -> Result<bool, Halt> {
+        match *self {
+            Value::Bool(value) => Ok(value),
+            _ => Err(Halt::Error(errors::create_type_error(
+                "a bool",
+                self.type_name(),
+            ))),
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i32, Halt> {
+        match *self {
+            Value::Int(value) => Ok(value),
+            _ => Err(Halt::Error(errors::create_type_error(
+                "a number",
+                self.type_name(),
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, Halt> {
+        match self {
+            Value::Str(value) => Ok(value),
+            _ => Err(Halt::Error(errors::create_type_error(
+                "a string",
+                self.type_name(),
+            ))),
+        }
+    }
+
+    pub fn as_entity(&self) -> Result<EntityId, Halt> {
+        match *self {
+            Value::Entity(id) => Ok(id),
+            _ => Err(Halt::Error(errors::create_type_error(
+                "a creature",
+                self.type_name(),
+            ))),
+        }
+    }
+
+    fn as_list(&self) -> Result<&[Value], Halt> {
+        match self {
+            Value::List(values) => Ok(values),
+            _ => Err(Halt::Error(errors::create_type_error(
+                "a list",
+                self.type_name(),
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+            Value::List(values) => {
+                let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            Value::Entity(_) => write!(f, "creature"),
+        }
+    }
+}
+
+/// Why a script stopped before its end
+#[derive(Debug, Clone, PartialEq)]
+pub enum Halt {
+    // the player backed out, nothing is used up
+    Cancelled,
+    Error(String),
+}
+
+/// The game's side of a script. Scripts only ever get at the game through the functions
+/// it offers here, so a broken or hostile one can't do more than a badly balanced scroll.
+pub trait Host {
+    fn call(&mut self, function: &str, args: &[Value]) -> Result<Value, Halt>;
+}
+
+/// Fails unless a function was given between `min` and `max` arguments
+pub fn check_args(function: &str, args: &[Value], min: usize, max: usize) -> Result<(), Halt> {
+    if args.len() < min || args.len() > max {
+        return Err(Halt::Error(errors::create_arguments_error(
+            function,
+            args.len(),
+        )));
+    }
+    Ok(())
+}
+
+/// A parsed script, ready to run as often as needed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    statements: Vec<Statement>,
+}
+
+impl Script {
+    pub fn compile(source: &str) -> Result<Script, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let mut statements = vec![];
+        while !parser.at_end() {
+            statements.push(parser.statement()?);
+        }
+        Ok(Script { statements })
+    }
+
+    /// Runs the script from the top. The globals are there as variables before its
+    /// first line, and everything it does to the game goes through the host.
+    pub fn run(&self, globals: &[(&str, Value)], host: &mut dyn Host) -> Result<(), Halt> {
+        let globals = globals
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        let mut interpreter = Interpreter {
+            scopes: vec![globals],
+            host,
+        };
+        match interpreter.block(&self.statements)? {
+            Flow::Next | Flow::Return => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i32),
+    Str(String),
+    Name(String),
+    // punctuation and operators, e.g. `(` or `<=`
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 22] = [
+    "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", "[", "]", ",", ";", "=", "<", ">", "+",
+    "-", "*", "/", "%",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = vec![];
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+            } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+                // the rest of the line is a comment
+                break;
+            } else if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                let value = digits
+                    .parse()
+                    .map_err(|_| errors::create_syntax_error(line_number, &digits))?;
+                tokens.push((Token::Int(value), line_number));
+            } else if c.is_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push((Token::Name(chars[start..i].iter().collect()), line_number));
+            } else if c == '"' {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.get(i + 1) {
+                                Some('n') => text.push('\n'),
+                                Some('"') => text.push('"'),
+                                Some('\\') => text.push('\\'),
+                                _ => return Err(errors::create_syntax_error(line_number, "\\")),
+                            }
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                        None => return Err(errors::create_syntax_error(line_number, "\"")),
+                    }
+                }
+                i += 1;
+                tokens.push((Token::Str(text), line_number));
+            } else if c == '!' && chars.get(i + 1) != Some(&'=') {
+                tokens.push((Token::Symbol("!"), line_number));
+                i += 1;
+            } else {
+                let rest: String = chars[i..].iter().collect();
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|symbol| rest.starts_with(*symbol))
+                    .ok_or_else(|| errors::create_syntax_error(line_number, &c.to_string()))?;
+                tokens.push((Token::Symbol(symbol), line_number));
+                i += symbol.len();
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Let(String, Expr),
+    Assign(String, Expr),
+    // each condition with its block in turn, then the else block
+    If(Vec<(Expr, Vec<Statement>)>, Vec<Statement>),
+    For(String, Expr, Vec<Statement>),
+    Return,
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(Value),
+    Variable(String),
+    List(Vec<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    // `&&` and `||` only look at their right side when they have to
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+}
+
+const KEYWORDS: [&str; 8] = ["let", "if", "else", "for", "in", "return", "true", "false"];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser {
+    fn at_end(&self) -> bool {
+        self.next >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn error(&self) -> String {
+        match self.tokens.get(self.next) {
+            Some((token, line)) => {
+                let text = match token {
+                    Token::Int(value) => value.to_string(),
+                    Token::Str(text) => format!("\"{}\"", text),
+                    Token::Name(name) => name.clone(),
+                    Token::Symbol(symbol) => symbol.to_string(),
+                };
+                errors::create_syntax_error(*line, &text)
+            }
+            None => errors::UNEXPECTED_END.to_string(),
+        }
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(name)) if name == keyword)
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = self.is_symbol(symbol);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Name(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.next += 1;
+                Ok(name)
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, String> {
+        self.expect_symbol("{")?;
+        let mut statements = vec![];
+        while !self.eat_symbol("}") {
+            if self.at_end() {
+                return Err(self.error());
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        if self.eat_keyword("let") {
+            let name = self.name()?;
+            self.expect_symbol("=")?;
+            let value = self.expr()?;
+            self.expect_symbol(";")?;
+            return Ok(Statement::Let(name, value));
+        }
+        if self.eat_keyword("if") {
+            return self.if_statement();
+        }
+        if self.eat_keyword("for") {
+            let name = self.name()?;
+            if !self.eat_keyword("in") {
+                return Err(self.error());
+            }
+            let list = self.expr()?;
+            let body = self.block()?;
+            return Ok(Statement::For(name, list, body));
+        }
+        if self.eat_keyword("return") {
+            self.expect_symbol(";")?;
+            return Ok(Statement::Return);
+        }
+
+        let expr = self.expr()?;
+        if self.eat_symbol("=") {
+            let name = match expr {
+                Expr::Variable(name) => name,
+                _ => return Err(self.error()),
+            };
+            let value = self.expr()?;
+            self.expect_symbol(";")?;
+            return Ok(Statement::Assign(name, value));
+        }
+        self.expect_symbol(";")?;
+        Ok(Statement::Expr(expr))
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, String> {
+        let mut branches = vec![(self.expr()?, self.block()?)];
+        let mut otherwise = vec![];
+        while self.eat_keyword("else") {
+            if self.eat_keyword("if") {
+                branches.push((self.expr()?, self.block()?));
+            } else {
+                otherwise = self.block()?;
+                break;
+            }
+        }
+        Ok(Statement::If(branches, otherwise))
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat_symbol("||") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.binary(0)?;
+        while self.eat_symbol("&&") {
+            left = Expr::And(Box::new(left), Box::new(self.binary(0)?));
+        }
+        Ok(left)
+    }
+
+    /// Operators from the loosest binding to the tightest
+    const LEVELS: [&'static [&'static str]; 4] = [
+        &["==", "!="],
+        &["<", "<=", ">", ">="],
+        &["+", "-"],
+        &["*", "/", "%"],
+    ];
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == Self::LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let operator = Self::LEVELS[level]
+                .iter()
+                .find(|operator| self.is_symbol(operator));
+            match operator {
+                Some(&operator) => {
+                    self.next += 1;
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(operator, Box::new(left), Box::new(right));
+                }
+                None => return Ok(left),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat_symbol("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat_symbol("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        let mut expr = self.primary()?;
+        while self.eat_symbol("[") {
+            let index = self.expr()?;
+            self.expect_symbol("]")?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.eat_symbol("(") {
+            // `()` is nothing, e.g. when there's no monster near
+            if self.eat_symbol(")") {
+                return Ok(Expr::Literal(Value::Unit));
+            }
+            let expr = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        if self.eat_symbol("[") {
+            return Ok(Expr::List(self.list("]")?));
+        }
+        if self.eat_keyword("true") {
+            return Ok(Expr::Literal(Value::Bool(true)));
+        }
+        if self.eat_keyword("false") {
+            return Ok(Expr::Literal(Value::Bool(false)));
+        }
+
+        match self.peek().cloned() {
+            Some(Token::Int(value)) => {
+                self.next += 1;
+                Ok(Expr::Literal(Value::Int(value)))
+            }
+            Some(Token::Str(text)) => {
+                self.next += 1;
+                Ok(Expr::Literal(Value::Str(text)))
+            }
+            Some(Token::Name(_)) => {
+                let name = self.name()?;
+                if self.eat_symbol("(") {
+                    Ok(Expr::Call(name, self.list(")")?))
+                } else {
+                    Ok(Expr::Variable(name))
+                }
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    /// Comma separated expressions up to the closing symbol
+    fn list(&mut self, close: &str) -> Result<Vec<Expr>, String> {
+        let mut items = vec![];
+        if self.eat_symbol(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(self.expr()?);
+            if self.eat_symbol(close) {
+                return Ok(items);
+            }
+            self.expect_symbol(",")?;
+        }
+    }
+}
+
+enum Flow {
+    Next,
+    Return,
+}
+
+struct Interpreter<'a> {
+    // the innermost block's variables last
+    scopes: Vec<HashMap<String, Value>>,
+    host: &'a mut dyn Host,
+}
+
+impl Interpreter<'_> {
+    fn block(&mut self, statements: &[Statement]) -> Result<Flow, Halt> {
+        self.scopes.push(HashMap::new());
+        let flow = self.statements(statements);
+        self.scopes.pop();
+        flow
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<Flow, Halt> {
+        for statement in statements {
+            if let Flow::Return = self.statement(statement)? {
+                return Ok(Flow::Return);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<Flow, Halt> {
+        match statement {
+            Statement::Let(name, value) => {
+                let value = self.eval(value)?;
+                self.scopes.last_mut().unwrap().insert(name.clone(), value);
+            }
+            Statement::Assign(name, value) => {
+                let value = self.eval(value)?;
+                match self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(name))
+                {
+                    Some(variable) => *variable = value,
+                    None => return Err(Halt::Error(errors::create_unknown_variable_error(name))),
+                }
+            }
+            Statement::If(branches, otherwise) => {
+                for (condition, body) in branches {
+                    if self.eval(condition)?.truthy()? {
+                        return self.block(body);
+                    }
+                }
+                return self.block(otherwise);
+            }
+            // lists only ever come from the script or the game, so every loop ends
+            Statement::For(name, list, body) => {
+                let list = self.eval(list)?;
+                for item in list.as_list()? {
+                    self.scopes.push(HashMap::new());
+                    self.scopes
+                        .last_mut()
+                        .unwrap()
+                        .insert(name.clone(), item.clone());
+                    let flow = self.statements(body);
+                    self.scopes.pop();
+                    if let Flow::Return = flow? {
+                        return Ok(Flow::Return);
+                    }
+                }
+            }
+            Statement::Return => return Ok(Flow::Return),
+            Statement::Expr(expr) => {
+                self.eval(expr)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, Halt> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Variable(name) => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .cloned()
+                .ok_or_else(|| Halt::Error(errors::create_unknown_variable_error(name))),
+            Expr::List(items) => Ok(Value::List(
+                items
+                    .iter()
+                    .map(|item| self.eval(item))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expr::Not(value) => Ok(Value::Bool(!self.eval(value)?.truthy()?)),
+            Expr::Negate(value) => {
+                let value = self.eval(value)?.as_int()?;
+                value
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or_else(|| Halt::Error(errors::OVERFLOW.to_string()))
+            }
+            Expr::And(left, right) => {
+                if !self.eval(left)?.truthy()? {
+                    return Ok(Value::Bool(false));
+                }
+                Ok(Value::Bool(self.eval(right)?.truthy()?))
+            }
+            Expr::Or(left, right) => {
+                if self.eval(left)?.truthy()? {
+                    return Ok(Value::Bool(true));
+                }
+                Ok(Value::Bool(self.eval(right)?.truthy()?))
+            }
+            Expr::Binary(operator, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(operator, &left, &right)
+            }
+            Expr::Call(function, args) => {
+                let args: Vec<Value> = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<_, _>>()?;
+                match built_in(function, &args)? {
+                    Some(value) => Ok(value),
+                    None => self.host.call(function, &args),
+                }
+            }
+            Expr::Index(list, index) => {
+                let list = self.eval(list)?;
+                let index = self.eval(index)?.as_int()?;
+                let list = list.as_list()?;
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| list.get(index))
+                    .cloned()
+                    .ok_or_else(|| Halt::Error(errors::create_index_error(index, list.len())))
+            }
+        }
+    }
+}
+
+fn binary(operator: &str, left: &Value, right: &Value) -> Result<Value, Halt> {
+    match (operator, left, right) {
+        ("==", _, _) => return Ok(Value::Bool(left == right)),
+        ("!=", _, _) => return Ok(Value::Bool(left != right)),
+        // anything added to a string is written out into it
+        ("+", Value::Str(_), _) | ("+", _, Value::Str(_)) => {
+            return Ok(Value::Str(format!("{}{}", left, right)))
+        }
+        _ => {}
+    }
+
+    let (left, right) = (left.as_int()?, right.as_int()?);
+    let result = match operator {
+        "<" => return Ok(Value::Bool(left < right)),
+        "<=" => return Ok(Value::Bool(left <= right)),
+        ">" => return Ok(Value::Bool(left > right)),
+        ">=" => return Ok(Value::Bool(left >= right)),
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" | "%" if right == 0 => return Err(Halt::Error(errors::DIVIDE_BY_ZERO.to_string())),
+        "/" => left.checked_div(right),
+        _ => left.checked_rem(right),
+    };
+    result
+        .map(Value::Int)
+        .ok_or_else(|| Halt::Error(errors::OVERFLOW.to_string()))
+}
+
+/// The functions every script has, whatever the game offers. None if it's not one of them.
+fn built_in(function: &str, args: &[Value]) -> Result<Option<Value>, Halt> {
+    let value = match function {
+        "len" => {
+            check_args(function, args, 1, 1)?;
+            let len = match &args[0] {
+                Value::Str(text) => text.chars().count(),
+                other => other.as_list()?.len(),
+            };
+            Value::Int(len as i32)
+        }
+        "contains" => {
+            check_args(function, args, 2, 2)?;
+            Value::Bool(args[0].as_list()?.contains(&args[1]))
+        }
+        "min" | "max" => {
+            check_args(function, args, 2, 2)?;
+            let (a, b) = (args[0].as_int()?, args[1].as_int()?);
+            Value::Int(if function == "min" {
+                a.min(b)
+            } else {
+                a.max(b)
+            })
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Reads the scripted items from their file. A missing file means the built-in ones,
+/// a broken file or script gets a warning and the built-in ones too.
+pub fn load() -> Vec<ScriptDef> {
+    let mut json = String::new();
+    let read = File::open(constants::ITEM_SCRIPTS_FILE_NAME)
+        .and_then(|mut file| file.read_to_string(&mut json));
+    if read.is_ok() {
+        match parse(&json) {
+            Ok(definitions) => return definitions,
+            Err(error) => eprintln!("{}", constants::create_bad_item_scripts_warning(&error)),
+        }
+    }
+
+    parse(BUILT_IN).expect("built-in item scripts are valid")
+}
+
+fn parse(json: &str) -> Result<Vec<ScriptDef>, String> {
+    let definitions: Vec<ScriptDef> =
+        serde_json::from_str(json).map_err(|error| error.to_string())?;
+    for definition in &definitions {
+        definition.compile()?;
+    }
+    Ok(definitions)
+}
+
+pub fn find<'a>(scripts: &'a [ScriptDef], name: &str) -> Option<&'a ScriptDef> {
+    scripts.iter().find(|script| script.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Calls = Vec<(String, Vec<Value>)>;
+
+    /// Stands in for the game, remembering every call a script makes
+    #[derive(Default)]
+    struct Recorder {
+        calls: Calls,
+    }
+
+    impl Host for Recorder {
+        fn call(&mut self, function: &str, args: &[Value]) -> Result<Value, Halt> {
+            self.calls.push((function.to_string(), args.to_vec()));
+            match function {
+                "cancel" => Err(Halt::Cancelled),
+                "answer" => Ok(Value::Int(42)),
+                _ => Ok(Value::Unit),
+            }
+        }
+    }
+
+    fn run(source: &str) -> (Result<(), Halt>, Calls) {
+        let mut host = Recorder::default();
+        let result = Script::compile(source).unwrap().run(&[], &mut host);
+        (result, host.calls)
+    }
+
+    /// The values a script hands to `log`, in order
+    fn logged(source: &str) -> Vec<Value> {
+        let (result, calls) = run(source);
+        assert_eq!(result, Ok(()));
+        calls
+            .into_iter()
+            .filter(|(function, _)| function == "log")
+            .map(|(_, mut args)| args.remove(0))
+            .collect()
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence() {
+        assert_eq!(
+            logged("log(1 + 2 * 3); log((1 + 2) * 3); log(-7 / 2); log(7 % 3);"),
+            vec![Value::Int(7), Value::Int(9), Value::Int(-3), Value::Int(1)]
+        );
+    }
+
+    #[test]
+    fn strings_take_anything_added_to_them() {
+        assert_eq!(
+            logged(r#"log("hit for " + 4 + " damage"); log(1 + 2 + "!");"#),
+            vec![
+                Value::Str("hit for 4 damage".into()),
+                Value::Str("3!".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_can_be_changed_and_go_out_of_scope() {
+        assert_eq!(
+            logged("let x = 1; if true { let y = 2; x = x + y; } log(x);"),
+            vec![Value::Int(3)]
+        );
+        let (result, _) = run("if true { let y = 2; } log(y);");
+        assert!(matches!(result, Err(Halt::Error(_))));
+    }
+
+    #[test]
+    fn conditionals_pick_one_branch() {
+        let source = "
+            let n = answer();
+            if n < 10 { log(\"small\"); }
+            else if n < 100 { log(\"medium\"); }
+            else { log(\"large\"); }
+        ";
+        assert_eq!(logged(source), vec![Value::Str("medium".into())]);
+    }
+
+    #[test]
+    fn loops_go_over_every_item() {
+        assert_eq!(
+            logged("let total = 0; for n in [1, 2, 3] { total = total + n; } log(total);"),
+            vec![Value::Int(6)]
+        );
+        assert_eq!(
+            logged("let list = [4, 5]; log(len(list)); log(list[1]); log(contains(list, 4));"),
+            vec![Value::Int(2), Value::Int(5), Value::Bool(true)]
+        );
+    }
+
+    #[test]
+    fn nothing_can_be_checked_for() {
+        assert_eq!(
+            logged("let target = missing(); log(target == ()); log(answer() != ());"),
+            vec![Value::Bool(true), Value::Bool(true)]
+        );
+    }
+
+    #[test]
+    fn logic_only_looks_as_far_as_it_needs_to() {
+        let (_, calls) = run("if false && cancel() { } if true || cancel() { }");
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn return_ends_the_script_early() {
+        assert_eq!(
+            logged("for n in [1, 2, 3] { log(n); if n == 2 { return; } } log(0);"),
+            vec![Value::Int(1), Value::Int(2)]
+        );
+    }
+
+    #[test]
+    fn cancelling_stops_the_script() {
+        let (result, calls) = run("log(1); cancel(); log(2);");
+        assert_eq!(result, Err(Halt::Cancelled));
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[test]
+    fn globals_are_there_from_the_start() {
+        let mut host = Recorder::default();
+        let script = Script::compile("log(DAMAGE * 2);").unwrap();
+        let globals = [("DAMAGE", Value::Int(20))];
+        assert_eq!(script.run(&globals, &mut host), Ok(()));
+        assert_eq!(host.calls[0].1, vec![Value::Int(40)]);
+    }
+
+    #[test]
+    fn mistakes_are_errors() {
+        for source in &[
+            "1 / 0;",
+            "log(nothing);",
+            "if 1 { }",
+            "[1][5];",
+            "-\"text\";",
+        ] {
+            let (result, _) = run(source);
+            assert!(matches!(result, Err(Halt::Error(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn broken_scripts_do_not_compile() {
+        for source in &[
+            "let = 1;",
+            "log(1",
+            "if true { log(1);",
+            "\"open",
+            "let x = 1",
+            "x =",
+        ] {
+            assert!(Script::compile(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn the_built_in_scripts_compile() {
+        use constants::scripts::built_in;
+
+        assert!(parse(BUILT_IN).is_ok());
+        for source in &[
+            built_in::HEAL,
+            built_in::LIGHTNING,
+            built_in::CONFUSE,
+            built_in::FIREBALL,
+        ] {
+            assert_eq!(Script::compile(source).err(), None);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::Read;
+
+use tcod::map::FovAlgorithm;
+
+use crate::constants;
+use crate::constants::gui::{BAR_WIDTH, SCREEN_WIDTH, VIEW_HEIGHT};
+
+/// Player preferences read from the settings file at startup. Anything the file
+/// leaves out keeps its default, and a missing file just means all defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub panel_height: i32,
+    pub glyphs: GlyphSet,
+    // tint monsters by how dangerous they are to the player
+    pub threat_tint: bool,
+    #[serde(with = "FovAlgorithmDef")]
+    pub fov_algorithm: FovAlgorithm,
+    pub fov_light_walls: bool,
+    // append the structured event stream to the event log file as the game runs
+    pub event_log: bool,
+    // lean on the player when the run is going well and ease off after close calls
+    pub adaptive_difficulty: bool,
+    // hold on to keys after unlocking a door with them instead of using them up
+    pub keep_keys: bool,
+    // save without being asked every this many turns and on going downstairs, 0 for never
+    pub autosave_turns: u32,
+    // dying deletes the save and leaves a morgue file describing the run instead
+    pub permadeath: bool,
+    // how many separate items the player can carry, stacks count once
+    pub inventory_capacity: usize,
+}
+
+/// Mirrors tcod's FOV algorithms so they can be named in the settings file
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "FovAlgorithm")]
+enum FovAlgorithmDef {
+    Basic,
+    Diamond,
+    Shadow,
+    Permissive0,
+    Permissive1,
+    Permissive2,
+    Permissive3,
+    Permissive4,
+    Permissive5,
+    Permissive6,
+    Permissive7,
+    Permissive8,
+    Restrictive,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            panel_height: constants::gui::PANEL_HEIGHT,
+            glyphs: GlyphSet::Ascii,
+            threat_tint: true,
+            fov_algorithm: FovAlgorithm::Basic,
+            fov_light_walls: true,
+            event_log: false,
+            adaptive_difficulty: false,
+            keep_keys: false,
+            autosave_turns: constants::AUTOSAVE_TURNS,
+            permadeath: false,
+            inventory_capacity: constants::gui::menus::inventory::CAPACITY,
+        }
+    }
+}
+
+/// How the map is drawn: plain letters on colored tiles, or the font's extra symbols
+/// with line-drawn walls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GlyphSet {
+    Ascii,
+    Symbols,
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let mut json_settings = String::new();
+        let read = File::open(constants::SETTINGS_FILE_NAME)
+            .and_then(|mut file| file.read_to_string(&mut json_settings));
+        if read.is_err() {
+            return Settings::default();
+        }
+
+        serde_json::from_str(&json_settings).unwrap_or_else(|_| {
+            eprintln!("{}", constants::create_bad_settings_warning());
+            Settings::default()
+        })
+    }
+}
+
+/// Where the panel and its message log sit on screen. The map keeps its size, so a
+/// taller panel makes the whole window taller rather than covering the dungeon.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub screen_height: i32,
+    pub panel_height: i32,
+    pub panel_y: i32,
+    pub msg_x: i32,
+    pub msg_width: i32,
+    pub msg_height: i32,
+}
+
+impl Layout {
+    pub fn new(settings: &Settings) -> Self {
+        use constants::gui::{MAX_PANEL_HEIGHT, PANEL_HEIGHT};
+
+        // the HP bar and stat rows need the default height, so that's the floor
+        let panel_height = settings.panel_height.clamp(PANEL_HEIGHT, MAX_PANEL_HEIGHT);
+
+        Layout {
+            screen_height: VIEW_HEIGHT + panel_height,
+            panel_height,
+            panel_y: VIEW_HEIGHT,
+            msg_x: BAR_WIDTH + 2,
+            msg_width: SCREEN_WIDTH - BAR_WIDTH - 2,
+            msg_height: panel_height - 1,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+/// The traps that can be hidden in a dungeon floor
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    Spike,
+    Dart,
+    Alarm,
+    Pit,
+}
+
+/// A trap lying in wait on a tile. It stays hidden until something sets it off,
+/// and from then on it is drawn like any other feature of the map.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub revealed: bool,
+}
+
+impl Trap {
+    pub fn hidden(kind: TrapKind) -> Self {
+        Trap {
+            kind,
+            revealed: false,
+        }
+    }
+}